@@ -1,7 +1,8 @@
-use spark_note_sdk::{NoteManager, create_note};
+use spark_note_sdk::{NoteManager, SpendingKeys, create_note};
 use spark_note_sdk::secret::Secret;
 use spark_note_sdk::tezos::TezosClient;
-// use std::sync::Arc;
+use spark_note_sdk::tezos_keys::TezosSecretKey;
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -10,10 +11,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Initialize NoteManager with a Tezos Client
     // In a real scenario, this would be a Ghostnet node and a deployed contract address
     let rpc_node = "https://rpc.ghostnet.teztnets.com";
-    let contract_address = "KT1TezosDummyAddressForPOC";
-    let tezos_client = TezosClient::new(rpc_node, contract_address);
+    let contract_address = std::env::var("SPARK_CONTRACT_ADDRESS")
+        .unwrap_or_else(|_| "KT1M7dL5CKWHiZhoTZCMdEbX17z7nSvWkW9r".to_string());
+    // Commitments and nullifiers are listed through a TzKT indexer
+    let tezos_client = TezosClient::new(rpc_node, &contract_address)
+        .with_indexer("https://api.ghostnet.tzkt.io");
     
-    let mut manager = NoteManager::new().with_tezos_client(tezos_client);
+    // Key of the account paying for the operations; defaults to the well-known
+    // flextesa sandbox key, which only works against a sandbox node
+    let secret_key = std::env::var("TEZOS_SECRET_KEY")
+        .unwrap_or_else(|_| "edsk3QoqBuvdamxouPhin7swCvkQNgq4jP5KZPbwWNnwdZpSpJiEbq".to_string());
+    println!("Operations are sent from {}", TezosSecretKey::from_base58(&secret_key)?.address());
+
+    // Spends are proved with Groth16; the setup takes a few seconds
+    println!("Setting up spending circuit keys...");
+    let spending_keys = Arc::new(SpendingKeys::setup());
+
+    let mut manager = NoteManager::new()
+        .with_tezos_client(tezos_client)
+        .with_spending_keys(spending_keys);
     println!("Manager initialized with Tezos Client (RPC: {})", rpc_node);
 
     // 2. Create a new Spark Note
@@ -28,7 +44,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 3. Simulate Deposit to Tezos
     println!("\nStep 3: Depositing note to Tezos Ghostnet...");
-    let deposit_result = manager.sync_deposit_to_tezos(note_id, "edsk..._dummy_key").await?;
+    let deposit_result = manager.sync_deposit_to_tezos(note_id, &secret_key).await?;
     println!("Deposit successful! Operation Hash: {}", deposit_result.operation_hash);
 
     // 4. Scan the blockchain for new notes (Simulation)
@@ -42,7 +58,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let secret_bytes = vec![0, 1, 2, 3, 4, 5, 6, 7];
     manager.generate_nullifier_for_note(note_id, secret_bytes)?;
     
-    let spend_result = manager.sync_spend_to_tezos(note_id, "edsk..._dummy_key").await?;
+    let spend_result = manager.sync_spend_to_tezos(note_id, &secret_key).await?;
     println!("Spend successful! Operation Hash: {}", spend_result.operation_hash);
     
     let final_entry = manager.get_note(note_id).unwrap();
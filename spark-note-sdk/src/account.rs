@@ -0,0 +1,487 @@
+//! Multiple accounts in one database
+//!
+//! This module provides [`WalletDb`], which hosts several independent
+//! accounts inside a single sled database. Each account has its own note IDs,
+//! metadata, indexes and balances, stored in trees prefixed with the account
+//! name, while the spent nullifier set is shared so that a nullifier spent by
+//! one account is seen as spent by all of them. Accounts can optionally
+//! encrypt their note entries (including secrets) at rest with their own key.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+use crate::error::{SparkError, SparkResult};
+use crate::manager::{NoteManager, SPENT_NULLIFIERS_TREE};
+use crate::nullifier_store::SledNullifierStore;
+use crate::rng::generate_random_bytes;
+
+/// Name of the account stored in the database's top-level trees
+///
+/// This is the account used by [`NoteManager::open`]; it always exists, is
+/// never encrypted and cannot be deleted.
+pub const DEFAULT_ACCOUNT: &str = "default";
+/// Maximum length of an account name
+pub const MAX_ACCOUNT_NAME_LENGTH: usize = 64;
+
+/// Sled tree holding the account registry
+const ACCOUNTS_TREE: &str = "accounts";
+/// Prefix of every per-account tree name
+const ACCOUNT_TREE_PREFIX: &str = "account/";
+/// Marker at the start of an encrypted note entry
+const ENCRYPTED_ENTRY_MAGIC: &[u8; 4] = b"SPE1";
+const NONCE_LENGTH: usize = 24;
+
+/// Public information about an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountInfo {
+    /// Account name
+    pub name: String,
+    /// Unix time at which the account was created (0 for the default account)
+    pub created_at: u64,
+    /// Whether note entries are encrypted with an account key
+    pub encrypted: bool,
+}
+
+/// Registry record stored for each named account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccountRecord {
+    info: AccountInfo,
+    /// Keyed hash identifying the account key, used to reject wrong keys
+    key_check: Option<String>,
+}
+
+/// A 32-byte key encrypting an account's note entries at rest
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct AccountKey([u8; 32]);
+
+impl AccountKey {
+    /// Use 32 bytes of key material directly
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        AccountKey(key)
+    }
+
+    /// Derive a key from arbitrary high-entropy key material
+    ///
+    /// This is not a password hash; derive keys from passwords with a slow
+    /// KDF such as Argon2 first.
+    pub fn derive(material: &[u8]) -> Self {
+        AccountKey(blake3::derive_key("spark-note-sdk account key v1", material))
+    }
+
+    /// Generate a fresh random key
+    pub fn random() -> SparkResult<Self> {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&generate_random_bytes(32)?);
+        Ok(AccountKey(key))
+    }
+
+    /// Get the key bytes
+    ///
+    /// WARNING: This exposes the key. Use only to store it securely.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Keyed hash stored in the registry to recognise this key
+    fn check_value(&self) -> String {
+        hex::encode(blake3::keyed_hash(&self.0, b"spark-note-sdk account key check").as_bytes())
+    }
+
+    /// Encrypt a note entry, binding it to its location via `aad`
+    pub(crate) fn seal(&self, aad: &[u8], plaintext: &[u8]) -> SparkResult<Vec<u8>> {
+        let nonce = generate_random_bytes(NONCE_LENGTH)?;
+        let ciphertext = XChaCha20Poly1305::new((&self.0).into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .map_err(|_| account_error("Failed to encrypt note entry"))?;
+
+        let mut sealed = Vec::with_capacity(ENCRYPTED_ENTRY_MAGIC.len() + NONCE_LENGTH + ciphertext.len());
+        sealed.extend_from_slice(ENCRYPTED_ENTRY_MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt a note entry written by [`seal`](Self::seal)
+    pub(crate) fn open(&self, aad: &[u8], sealed: &[u8]) -> SparkResult<Vec<u8>> {
+        let body = sealed
+            .strip_prefix(ENCRYPTED_ENTRY_MAGIC.as_slice())
+            .filter(|body| body.len() > NONCE_LENGTH)
+            .ok_or_else(|| account_error("Note entry is not encrypted"))?;
+        let (nonce, ciphertext) = body.split_at(NONCE_LENGTH);
+
+        XChaCha20Poly1305::new((&self.0).into())
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| account_error("Failed to decrypt note entry"))
+    }
+}
+
+impl std::fmt::Debug for AccountKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AccountKey(***)")
+    }
+}
+
+/// Name of an account's copy of a per-account tree
+///
+/// The default account (`None`) uses the unprefixed tree, which keeps
+/// databases created by [`NoteManager::open`] readable.
+pub(crate) fn account_tree_name(namespace: Option<&str>, base: &str) -> String {
+    match namespace {
+        Some(name) => format!("{}{}/{}", ACCOUNT_TREE_PREFIX, name, base),
+        None => base.to_string(),
+    }
+}
+
+/// A sled database holding any number of accounts
+///
+/// # Example
+/// ```
+/// use spark_note_sdk::account::{AccountKey, WalletDb};
+/// use spark_note_sdk::{create_note, secret::Secret};
+///
+/// let path = std::env::temp_dir().join("spark_doc_wallet_db");
+/// # let _ = std::fs::remove_dir_all(&path);
+/// let wallet = WalletDb::open(path.to_str().unwrap()).unwrap();
+///
+/// wallet.create_account("alice").unwrap();
+/// let key = AccountKey::random().unwrap();
+/// wallet.create_encrypted_account("bob", &key).unwrap();
+///
+/// let mut alice = wallet.open_account("alice").unwrap();
+/// alice.add_note("n1".to_string(), create_note(500, Secret::new(vec![1; 16])).unwrap()).unwrap();
+///
+/// let bob = wallet.open_encrypted_account("bob", &key).unwrap();
+/// assert_eq!(alice.balance(), 500);
+/// assert_eq!(bob.balance(), 0);
+/// # drop((alice, bob, wallet));
+/// # let _ = std::fs::remove_dir_all(&path);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WalletDb {
+    db: sled::Db,
+    /// Spent set shared by every account opened from this handle
+    spent: SledNullifierStore,
+}
+
+impl WalletDb {
+    /// Open (or create) a database at `path`
+    pub fn open(path: &str) -> SparkResult<Self> {
+        let db = sled::open(path).map_err(|e| SparkError::OperationError {
+            message: format!("Failed to open database at {}: {}", path, e),
+        })?;
+        let spent = SledNullifierStore::open(&db, SPENT_NULLIFIERS_TREE)?;
+        Ok(WalletDb { db, spent })
+    }
+
+    /// Create an unencrypted account
+    ///
+    /// # Errors
+    /// Fails if the name is invalid or the account already exists
+    pub fn create_account(&self, name: &str) -> SparkResult<AccountInfo> {
+        self.insert_account(name, None)
+    }
+
+    /// Create an account whose note entries are encrypted with `key`
+    pub fn create_encrypted_account(&self, name: &str, key: &AccountKey) -> SparkResult<AccountInfo> {
+        self.insert_account(name, Some(key))
+    }
+
+    /// List every account, starting with the default account
+    pub fn list_accounts(&self) -> SparkResult<Vec<AccountInfo>> {
+        let mut accounts = vec![AccountInfo {
+            name: DEFAULT_ACCOUNT.to_string(),
+            created_at: 0,
+            encrypted: false,
+        }];
+        for item in self.registry()?.iter() {
+            let (_, bytes) = item.map_err(|e| SparkError::SerializationError {
+                message: format!("Database read error: {}", e),
+            })?;
+            accounts.push(decode_record(&bytes)?.info);
+        }
+        Ok(accounts)
+    }
+
+    /// Delete an account and all of its notes
+    ///
+    /// Nullifiers the account added to the shared spent set are kept, since
+    /// they remain spent on chain.
+    pub fn delete_account(&self, name: &str) -> SparkResult<()> {
+        if name == DEFAULT_ACCOUNT {
+            return Err(account_error("The default account cannot be deleted"));
+        }
+        self.record(name)?;
+
+        let prefix = account_tree_name(Some(name), "");
+        for tree_name in self.db.tree_names() {
+            if tree_name.starts_with(prefix.as_bytes()) {
+                self.db.drop_tree(&tree_name).map_err(|e| SparkError::OperationError {
+                    message: format!("Failed to drop tree: {}", e),
+                })?;
+            }
+        }
+
+        self.registry()?.remove(name).map_err(|e| SparkError::OperationError {
+            message: format!("Database write error: {}", e),
+        })?;
+        self.flush()
+    }
+
+    /// Open an unencrypted account
+    pub fn open_account(&self, name: &str) -> SparkResult<NoteManager> {
+        if name == DEFAULT_ACCOUNT {
+            return NoteManager::open_in(self.db.clone(), self.spent.clone(), None, None);
+        }
+        let record = self.record(name)?;
+        if record.info.encrypted {
+            return Err(account_error(format!("Account '{}' is encrypted; a key is required", name)));
+        }
+        NoteManager::open_in(self.db.clone(), self.spent.clone(), Some(name.to_string()), None)
+    }
+
+    /// Open an encrypted account with its key
+    ///
+    /// # Errors
+    /// Fails if the account is not encrypted or `key` is not its key
+    pub fn open_encrypted_account(&self, name: &str, key: &AccountKey) -> SparkResult<NoteManager> {
+        let record = self.record(name)?;
+        match &record.key_check {
+            Some(check) if crate::crypto::constant_time_eq(check.as_bytes(), key.check_value().as_bytes()) => {
+                NoteManager::open_in(self.db.clone(), self.spent.clone(), Some(name.to_string()), Some(key.clone()))
+            }
+            Some(_) => Err(account_error(format!("Wrong key for account '{}'", name))),
+            None => Err(account_error(format!("Account '{}' is not encrypted", name))),
+        }
+    }
+
+    fn insert_account(&self, name: &str, key: Option<&AccountKey>) -> SparkResult<AccountInfo> {
+        validate_account_name(name)?;
+        if name == DEFAULT_ACCOUNT {
+            return Err(account_error("The default account always exists"));
+        }
+
+        let record = AccountRecord {
+            info: AccountInfo {
+                name: name.to_string(),
+                created_at: crate::clock::unix_timestamp(),
+                encrypted: key.is_some(),
+            },
+            key_check: key.map(AccountKey::check_value),
+        };
+        let bytes = serde_json::to_vec(&record).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize account: {}", e),
+        })?;
+
+        self.registry()?
+            .compare_and_swap(name, None as Option<&[u8]>, Some(bytes))
+            .map_err(|e| SparkError::OperationError {
+                message: format!("Database write error: {}", e),
+            })?
+            .map_err(|_| account_error(format!("Account '{}' already exists", name)))?;
+        self.flush()?;
+        Ok(record.info)
+    }
+
+    fn record(&self, name: &str) -> SparkResult<AccountRecord> {
+        let bytes = self.registry()?
+            .get(name)
+            .map_err(|e| SparkError::SerializationError {
+                message: format!("Database read error: {}", e),
+            })?
+            .ok_or_else(|| account_error(format!("Account '{}' not found", name)))?;
+        decode_record(&bytes)
+    }
+
+    fn registry(&self) -> SparkResult<sled::Tree> {
+        self.db.open_tree(ACCOUNTS_TREE).map_err(|e| SparkError::OperationError {
+            message: format!("Failed to open {} tree: {}", ACCOUNTS_TREE, e),
+        })
+    }
+
+    fn flush(&self) -> SparkResult<()> {
+        self.db.flush().map_err(|e| SparkError::OperationError {
+            message: format!("Database flush error: {}", e),
+        })?;
+        Ok(())
+    }
+}
+
+fn decode_record(bytes: &[u8]) -> SparkResult<AccountRecord> {
+    serde_json::from_slice(bytes).map_err(|e| SparkError::SerializationError {
+        message: format!("Failed to deserialize account: {}", e),
+    })
+}
+
+/// Account names become part of sled tree names, so keep them simple
+fn validate_account_name(name: &str) -> SparkResult<()> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || name.len() > MAX_ACCOUNT_NAME_LENGTH || !valid_chars {
+        return Err(account_error(format!(
+            "Account names must be 1-{} characters of [A-Za-z0-9._-], got '{}'",
+            MAX_ACCOUNT_NAME_LENGTH, name
+        )));
+    }
+    Ok(())
+}
+
+fn account_error(message: impl Into<String>) -> SparkError {
+    SparkError::OperationError {
+        message: message.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{NullifierErrorCode, SparkError};
+    use crate::note::create_note;
+    use crate::secret::Secret;
+
+    fn temp_wallet(name: &str) -> (WalletDb, String) {
+        let path = std::env::temp_dir().join(name);
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_dir_all(&path);
+        (WalletDb::open(&path).unwrap(), path)
+    }
+
+    fn add(manager: &mut NoteManager, id: &str, value: u64, seed: u8) {
+        let note = create_note(value, Secret::new(vec![seed; 16])).unwrap();
+        manager.add_note(id.to_string(), note).unwrap();
+    }
+
+    #[test]
+    fn test_create_list_delete_accounts() {
+        let (wallet, path) = temp_wallet("spark_test_accounts_crud");
+
+        wallet.create_account("alice").unwrap();
+        wallet.create_encrypted_account("bob", &AccountKey::derive(b"bob")).unwrap();
+        assert!(wallet.create_account("alice").is_err());
+        assert!(wallet.create_account("bad name").is_err());
+        assert!(wallet.create_account(DEFAULT_ACCOUNT).is_err());
+
+        let names: Vec<_> = wallet.list_accounts().unwrap().into_iter().map(|a| (a.name, a.encrypted)).collect();
+        assert_eq!(names, vec![
+            (DEFAULT_ACCOUNT.to_string(), false),
+            ("alice".to_string(), false),
+            ("bob".to_string(), true),
+        ]);
+
+        {
+            let mut alice = wallet.open_account("alice").unwrap();
+            add(&mut alice, "n1", 100, 1);
+        }
+        wallet.delete_account("alice").unwrap();
+        assert!(wallet.open_account("alice").is_err());
+        assert!(wallet.delete_account(DEFAULT_ACCOUNT).is_err());
+
+        // A recreated account starts empty
+        wallet.create_account("alice").unwrap();
+        assert_eq!(wallet.open_account("alice").unwrap().note_count(), 0);
+
+        drop(wallet);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_accounts_keep_separate_notes_and_balances() {
+        let (wallet, path) = temp_wallet("spark_test_accounts_isolation");
+        wallet.create_account("alice").unwrap();
+        wallet.create_account("carol").unwrap();
+
+        {
+            let mut alice = wallet.open_account("alice").unwrap();
+            let mut carol = wallet.open_account("carol").unwrap();
+            let mut default = wallet.open_account(DEFAULT_ACCOUNT).unwrap();
+
+            // The same note ID can be used by every account
+            add(&mut alice, "note", 100, 1);
+            add(&mut carol, "note", 250, 2);
+            add(&mut default, "note", 400, 3);
+            add(&mut carol, "extra", 50, 4);
+
+            assert_eq!(alice.balance(), 100);
+            assert_eq!(carol.balance(), 300);
+            assert_eq!(default.balance(), 400);
+        }
+
+        drop(wallet);
+        let wallet = WalletDb::open(&path).unwrap();
+        let carol = wallet.open_account("carol").unwrap();
+        assert_eq!(carol.note_count(), 2);
+        assert_eq!(carol.get_note("note").unwrap().note.value, 250);
+
+        drop((carol, wallet));
+        // The default account is what NoteManager::open sees
+        let legacy = NoteManager::open(&path).unwrap();
+        assert_eq!(legacy.balance(), 400);
+
+        drop(legacy);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_spent_set_is_shared_across_accounts() {
+        let (wallet, path) = temp_wallet("spark_test_accounts_shared_spent");
+        wallet.create_account("alice").unwrap();
+        wallet.create_account("mallory").unwrap();
+
+        let mut alice = wallet.open_account("alice").unwrap();
+        let mut mallory = wallet.open_account("mallory").unwrap();
+
+        // Both accounts hold a copy of the same note
+        add(&mut alice, "n", 100, 7);
+        add(&mut mallory, "copy", 100, 7);
+        alice.generate_nullifier_for_note("n", vec![7; 16]).unwrap();
+        let nullifier = mallory.generate_nullifier_for_note("copy", vec![7; 16]).unwrap();
+
+        alice.mark_note_as_spent("n").unwrap();
+
+        // Mallory was opened before the spend but still sees it
+        assert!(mallory.is_nullifier_spent(&nullifier));
+        assert!(matches!(
+            mallory.mark_note_as_spent("copy"),
+            Err(SparkError::NullifierError { code: NullifierErrorCode::AlreadySpent, .. })
+        ));
+        assert_eq!(mallory.spendable_balance(), 0);
+
+        drop((alice, mallory, wallet));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_encrypted_account() {
+        let (wallet, path) = temp_wallet("spark_test_accounts_encrypted");
+        let key = AccountKey::random().unwrap();
+        wallet.create_encrypted_account("vault", &key).unwrap();
+
+        {
+            let mut vault = wallet.open_encrypted_account("vault", &key).unwrap();
+            add(&mut vault, "secret-note", 900, 0xAB);
+        }
+
+        assert!(wallet.open_account("vault").is_err());
+        assert!(wallet.open_encrypted_account("vault", &AccountKey::random().unwrap()).is_err());
+
+        // Nothing in the account's note tree contains the secret in clear text
+        let tree = wallet.db.open_tree(account_tree_name(Some("vault"), "notes")).unwrap();
+        let (_, stored) = tree.iter().next().unwrap().unwrap();
+        assert!(stored.starts_with(ENCRYPTED_ENTRY_MAGIC));
+        assert!(!stored.windows(16).any(|w| w == [0xAB; 16]));
+
+        let vault = wallet.open_encrypted_account("vault", &key).unwrap();
+        assert_eq!(vault.get_note("secret-note").unwrap().note.value, 900);
+
+        drop((vault, wallet));
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn test_sealed_entry_bound_to_location() {
+        let key = AccountKey::derive(b"k");
+        let sealed = key.seal(b"vault/a", b"entry").unwrap();
+        assert_eq!(key.open(b"vault/a", &sealed).unwrap(), b"entry");
+        assert!(key.open(b"vault/b", &sealed).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Encrypted wallet backups
+//!
+//! This module defines the versioned, password-encrypted backup format
+//! produced by [`NoteManager::export_backup`](crate::manager::NoteManager::export_backup)
+//! and consumed by [`NoteManager::restore_backup`](crate::manager::NoteManager::restore_backup).
+//!
+//! The envelope is JSON. Its payload (notes with secrets, states, nullifiers,
+//! metadata and the spent set) is encrypted with XChaCha20-Poly1305 under a
+//! key derived from the password with Argon2id. The unencrypted header fields
+//! are bound to the ciphertext as associated data, so tampering with any part
+//! of the backup is detected on restore.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::error::{SparkError, SparkResult};
+use crate::manager::{NoteMetadata, NoteState};
+use crate::rng::generate_random_bytes;
+
+/// Format identifier stored in every backup
+pub const BACKUP_FORMAT: &str = "spark-wallet-backup";
+/// Current backup format version
+pub const BACKUP_VERSION: u32 = 1;
+/// Upper bound on the Argon2 memory cost accepted on restore (1 GiB)
+pub const MAX_KDF_MEMORY_KIB: u32 = 1 << 20;
+/// Upper bound on the Argon2 iteration count accepted on restore
+pub const MAX_KDF_ITERATIONS: u32 = 64;
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+/// Argon2id parameters used to derive the backup encryption key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Random salt (hex-encoded)
+    pub salt: String,
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over memory
+    pub iterations: u32,
+    /// Degree of parallelism
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Recommended parameters (19 MiB, 2 passes) with a fresh random salt
+    pub fn recommended() -> SparkResult<Self> {
+        Self::with_cost(19 * 1024, 2, 1)
+    }
+
+    /// Custom cost parameters with a fresh random salt
+    ///
+    /// Lower costs make backups faster to create and restore, but also
+    /// cheaper to brute-force.
+    pub fn with_cost(memory_kib: u32, iterations: u32, parallelism: u32) -> SparkResult<Self> {
+        let params = KdfParams {
+            salt: hex::encode(generate_random_bytes(SALT_LENGTH)?),
+            memory_kib,
+            iterations,
+            parallelism,
+        };
+        params.validate()?;
+        Ok(params)
+    }
+
+    fn validate(&self) -> SparkResult<()> {
+        if self.memory_kib > MAX_KDF_MEMORY_KIB || self.iterations > MAX_KDF_ITERATIONS {
+            return Err(backup_error("Key derivation cost exceeds the supported maximum"));
+        }
+        Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| backup_error(format!("Invalid key derivation parameters: {}", e)))?;
+        Ok(())
+    }
+
+    fn derive_key(&self, password: &str) -> SparkResult<Zeroizing<[u8; 32]>> {
+        self.validate()?;
+        let salt = hex::decode(&self.salt)
+            .map_err(|e| backup_error(format!("Invalid salt encoding: {}", e)))?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| backup_error(format!("Invalid key derivation parameters: {}", e)))?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| backup_error(format!("Key derivation failed: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// Encrypted backup envelope
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletBackup {
+    /// Always [`BACKUP_FORMAT`]
+    pub format: String,
+    /// Format version
+    pub version: u32,
+    /// Unix time at which the backup was created
+    pub created_at: u64,
+    /// Key derivation parameters
+    pub kdf: KdfParams,
+    /// XChaCha20-Poly1305 nonce (hex-encoded)
+    pub nonce: String,
+    /// Encrypted payload including the authentication tag (hex-encoded)
+    pub ciphertext: String,
+}
+
+impl WalletBackup {
+    /// Parse a backup from its JSON form
+    pub fn from_json(json: &str) -> SparkResult<Self> {
+        let backup: WalletBackup = serde_json::from_str(json).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to deserialize wallet backup: {}", e),
+        })?;
+
+        if backup.format != BACKUP_FORMAT {
+            return Err(backup_error(format!("Not a wallet backup (format '{}')", backup.format)));
+        }
+        if backup.version > BACKUP_VERSION {
+            return Err(SparkError::SerializationError {
+                message: format!(
+                    "Unsupported version: {} (current: {})",
+                    backup.version, BACKUP_VERSION
+                ),
+            });
+        }
+        Ok(backup)
+    }
+
+    /// Serialize the backup to JSON
+    pub fn to_json(&self) -> SparkResult<String> {
+        serde_json::to_string(self).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize wallet backup: {}", e),
+        })
+    }
+
+    /// Encrypt a payload under a password
+    pub(crate) fn seal(payload: &BackupPayload, password: &str, kdf: KdfParams) -> SparkResult<Self> {
+        let mut backup = WalletBackup {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            created_at: crate::clock::unix_timestamp(),
+            kdf,
+            nonce: hex::encode(generate_random_bytes(NONCE_LENGTH)?),
+            ciphertext: String::new(),
+        };
+
+        let plaintext = Zeroizing::new(serde_json::to_vec(payload).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize backup payload: {}", e),
+        })?);
+
+        let key = backup.kdf.derive_key(password)?;
+        let nonce = hex::decode(&backup.nonce).map_err(|e| backup_error(e.to_string()))?;
+        let aad = backup.associated_data()?;
+        let ciphertext = XChaCha20Poly1305::new(key.as_ref().into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| backup_error("Encryption failed"))?;
+
+        backup.ciphertext = hex::encode(ciphertext);
+        Ok(backup)
+    }
+
+    /// Decrypt and authenticate the payload
+    pub(crate) fn open(&self, password: &str) -> SparkResult<BackupPayload> {
+        let nonce = hex::decode(&self.nonce)
+            .ok()
+            .filter(|n| n.len() == NONCE_LENGTH)
+            .ok_or_else(|| backup_error("Invalid nonce"))?;
+        let ciphertext = hex::decode(&self.ciphertext)
+            .map_err(|e| backup_error(format!("Invalid ciphertext encoding: {}", e)))?;
+
+        let key = self.kdf.derive_key(password)?;
+        let aad = self.associated_data()?;
+        let plaintext = Zeroizing::new(
+            XChaCha20Poly1305::new(key.as_ref().into())
+                .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+                .map_err(|_| backup_error("Wrong password or corrupted backup"))?,
+        );
+
+        serde_json::from_slice(&plaintext).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to deserialize backup payload: {}", e),
+        })
+    }
+
+    /// Header fields authenticated alongside the ciphertext
+    fn associated_data(&self) -> SparkResult<Vec<u8>> {
+        serde_json::to_vec(&(&self.format, self.version, self.created_at, &self.kdf)).map_err(|e| {
+            SparkError::SerializationError {
+                message: format!("Failed to serialize backup header: {}", e),
+            }
+        })
+    }
+}
+
+/// Decrypted backup contents
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BackupPayload {
+    /// Every note held by the wallet
+    pub notes: Vec<BackupNote>,
+    /// The spent nullifier set (hex-encoded)
+    pub spent_nullifiers: Vec<String>,
+}
+
+/// A note together with its secret and local state
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct BackupNote {
+    pub id: String,
+    pub value: u64,
+    /// Commitment (hex-encoded)
+    pub commitment: String,
+    /// Spending secret (hex-encoded)
+    pub secret: String,
+    pub state: NoteState,
+    /// Nullifier if generated (hex-encoded)
+    pub nullifier: Option<String>,
+    pub metadata: NoteMetadata,
+}
+
+impl Drop for BackupNote {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+/// How to handle a backup note whose ID already holds a different note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Fail the restore without changing the wallet
+    #[default]
+    Abort,
+    /// Keep the wallet's note and skip the one from the backup
+    KeepExisting,
+    /// Replace the wallet's note with the one from the backup
+    Overwrite,
+}
+
+/// Outcome of a restore
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestoreReport {
+    /// Notes added to the wallet
+    pub restored: Vec<String>,
+    /// Notes already in the wallet under the same ID, merged with the backup copy
+    pub merged: Vec<String>,
+    /// Conflicting wallet notes replaced by the backup copy
+    pub overwritten: Vec<String>,
+    /// Backup notes not restored because of a conflict or because the
+    /// wallet already holds the same note under another ID
+    pub skipped: Vec<String>,
+    /// Spent nullifiers that were not yet in the wallet's spent set
+    pub nullifiers_imported: usize,
+}
+
+fn backup_error(message: impl Into<String>) -> SparkError {
+    SparkError::OperationError {
+        message: format!("Wallet backup: {}", message.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_kdf() -> KdfParams {
+        KdfParams::with_cost(256, 1, 1).unwrap()
+    }
+
+    /// Long enough that random ciphertext cannot contain it by chance
+    const SECRET_HEX: &str = "5ec2e75ec2e75ec2e75ec2e75ec2e75ec2e75ec2e75ec2e75ec2e75ec2e75ec2";
+
+    fn payload() -> BackupPayload {
+        BackupPayload {
+            notes: vec![BackupNote {
+                id: "a".to_string(),
+                value: 5,
+                commitment: "00".to_string(),
+                secret: SECRET_HEX.to_string(),
+                state: NoteState::Unspent,
+                nullifier: None,
+                metadata: NoteMetadata::default(),
+            }],
+            spent_nullifiers: vec![hex::encode([7u8; 32])],
+        }
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let backup = WalletBackup::seal(&payload(), "correct horse", test_kdf()).unwrap();
+        let json = backup.to_json().unwrap();
+        assert!(!json.contains(SECRET_HEX), "secret must not appear in clear text");
+
+        let opened = WalletBackup::from_json(&json).unwrap().open("correct horse").unwrap();
+        assert_eq!(opened.notes[0].secret, SECRET_HEX);
+        assert_eq!(opened.spent_nullifiers.len(), 1);
+    }
+
+    #[test]
+    fn test_wrong_password_fails() {
+        let backup = WalletBackup::seal(&payload(), "correct horse", test_kdf()).unwrap();
+        assert!(backup.open("battery staple").is_err());
+    }
+
+    #[test]
+    fn test_tampered_header_fails() {
+        let mut backup = WalletBackup::seal(&payload(), "pw", test_kdf()).unwrap();
+        backup.created_at += 1;
+        assert!(backup.open("pw").is_err());
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_fails() {
+        let mut backup = WalletBackup::seal(&payload(), "pw", test_kdf()).unwrap();
+        let mut bytes = hex::decode(&backup.ciphertext).unwrap();
+        bytes[0] ^= 1;
+        backup.ciphertext = hex::encode(bytes);
+        assert!(backup.open("pw").is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_format_and_version() {
+        let mut backup = WalletBackup::seal(&payload(), "pw", test_kdf()).unwrap();
+        backup.version = BACKUP_VERSION + 1;
+        assert!(WalletBackup::from_json(&backup.to_json().unwrap()).is_err());
+
+        backup.version = BACKUP_VERSION;
+        backup.format = "something-else".to_string();
+        assert!(WalletBackup::from_json(&backup.to_json().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rejects_excessive_kdf_cost() {
+        assert!(KdfParams::with_cost(MAX_KDF_MEMORY_KIB + 1, 1, 1).is_err());
+
+        let mut backup = WalletBackup::seal(&payload(), "pw", test_kdf()).unwrap();
+        backup.kdf.iterations = MAX_KDF_ITERATIONS + 1;
+        assert!(backup.open("pw").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+//! Wall-clock time helpers
+//!
+//! `std::time::SystemTime` is unavailable on `wasm32-unknown-unknown`, so
+//! timestamps are read from the JavaScript `Date` object there instead.
+
+/// Current Unix time in seconds
+#[cfg(not(all(target_arch = "wasm32", feature = "wasm")))]
+pub fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Current Unix time in seconds
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+pub fn unix_timestamp() -> u64 {
+    (js_sys::Date::now() / 1000.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unix_timestamp_is_recent() {
+        // 2023-01-01T00:00:00Z
+        assert!(unix_timestamp() > 1_672_531_200);
+    }
+}
@@ -0,0 +1,238 @@
+//! Incremental commitment tree
+//!
+//! This module provides the [`CommitmentTree`] holding every note commitment
+//! deposited into the pool, in on-chain order. It is a fixed-depth
+//! ([`MERKLE_TREE_DEPTH`]) Poseidon Merkle tree whose leaves and node hashes
+//! match the spending circuit, so the [`MerkleWitness`] it returns can be
+//! passed straight to [`SparkNote::prove_spending`](crate::note::SparkNote::prove_spending).
+//! Unfilled positions hold the hash of an empty subtree.
+
+use std::collections::HashMap;
+
+use ark_crypto_primitives::sponge::poseidon::{PoseidonConfig, PoseidonSponge};
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_ff::Zero;
+use ark_serialize::CanonicalSerialize;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{commitment_leaf, setup_poseidon_config, BlsFr, MERKLE_TREE_DEPTH};
+use crate::error::{SparkError, SparkResult};
+
+/// Number of leaves the tree can hold
+pub const COMMITMENT_TREE_CAPACITY: u64 = 1 << MERKLE_TREE_DEPTH;
+
+/// Everything needed to prove membership of one leaf
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleWitness {
+    /// Position of the leaf
+    pub position: u64,
+    /// Root the path leads to (serialized field element)
+    pub root: Vec<u8>,
+    /// `(sibling, is_right)` pairs from the leaf up, as expected by the circuit
+    pub path: Vec<(Vec<u8>, bool)>,
+}
+
+/// Append-only Merkle tree of note commitments
+#[derive(Debug, Clone)]
+pub struct CommitmentTree {
+    /// Commitments in insertion order
+    commitments: Vec<Vec<u8>>,
+    /// `levels[h]` holds the filled nodes at height `h` (leaves at 0)
+    levels: Vec<Vec<BlsFr>>,
+    /// Hash of an empty subtree at each height
+    empty: Vec<BlsFr>,
+    /// Position of the first occurrence of each commitment
+    positions: HashMap<Vec<u8>, u64>,
+    poseidon: PoseidonConfig<BlsFr>,
+}
+
+impl CommitmentTree {
+    /// Creates an empty tree
+    pub fn new() -> Self {
+        let poseidon = setup_poseidon_config();
+        let mut empty = vec![BlsFr::zero()];
+        for height in 0..MERKLE_TREE_DEPTH {
+            empty.push(hash_pair(&poseidon, empty[height], empty[height]));
+        }
+
+        CommitmentTree {
+            commitments: Vec::new(),
+            levels: vec![Vec::new(); MERKLE_TREE_DEPTH + 1],
+            empty,
+            positions: HashMap::new(),
+            poseidon,
+        }
+    }
+
+    /// Number of commitments in the tree
+    pub fn len(&self) -> u64 {
+        self.commitments.len() as u64
+    }
+
+    /// Whether the tree holds no commitments
+    pub fn is_empty(&self) -> bool {
+        self.commitments.is_empty()
+    }
+
+    /// Commitment stored at `position`
+    pub fn commitment(&self, position: u64) -> Option<&[u8]> {
+        self.commitments.get(position as usize).map(Vec::as_slice)
+    }
+
+    /// Position of the first leaf holding `commitment`
+    pub fn position(&self, commitment: &[u8]) -> Option<u64> {
+        self.positions.get(commitment).copied()
+    }
+
+    /// Append a commitment, updating the path from its leaf to the root
+    ///
+    /// # Returns
+    /// * `Ok(u64)` - The position of the new leaf
+    /// * `Err(SparkError)` if the tree is full
+    pub fn append(&mut self, commitment: &[u8]) -> SparkResult<u64> {
+        let position = self.len();
+        if position >= COMMITMENT_TREE_CAPACITY {
+            return Err(SparkError::OperationError {
+                message: format!("Commitment tree is full ({} leaves)", COMMITMENT_TREE_CAPACITY),
+            });
+        }
+
+        self.levels[0].push(commitment_leaf(commitment));
+        for height in 0..MERKLE_TREE_DEPTH {
+            let index = (position >> height) as usize;
+            let left = self.node(height, index & !1);
+            let right = self.node(height, index | 1);
+            let parent = hash_pair(&self.poseidon, left, right);
+
+            let level = &mut self.levels[height + 1];
+            match level.get_mut(index / 2) {
+                Some(node) => *node = parent,
+                None => level.push(parent),
+            }
+        }
+
+        self.positions.entry(commitment.to_vec()).or_insert(position);
+        self.commitments.push(commitment.to_vec());
+        Ok(position)
+    }
+
+    /// Current root of the tree
+    pub fn root(&self) -> BlsFr {
+        self.node(MERKLE_TREE_DEPTH, 0)
+    }
+
+    /// Current root, serialized as expected by the spending proof helpers
+    pub fn root_bytes(&self) -> Vec<u8> {
+        field_bytes(&self.root())
+    }
+
+    /// Authentication path from the leaf at `position` to the current root
+    ///
+    /// # Returns
+    /// * `Ok(MerkleWitness)` for a filled position
+    /// * `Err(SparkError)` if no commitment has been appended there
+    pub fn witness(&self, position: u64) -> SparkResult<MerkleWitness> {
+        if position >= self.len() {
+            return Err(SparkError::OperationError {
+                message: format!("No commitment at tree position {}", position),
+            });
+        }
+
+        let path = (0..MERKLE_TREE_DEPTH)
+            .map(|height| {
+                let index = (position >> height) as usize;
+                (field_bytes(&self.node(height, index ^ 1)), index & 1 == 1)
+            })
+            .collect();
+
+        Ok(MerkleWitness {
+            position,
+            root: self.root_bytes(),
+            path,
+        })
+    }
+
+    fn node(&self, height: usize, index: usize) -> BlsFr {
+        self.levels[height].get(index).copied().unwrap_or(self.empty[height])
+    }
+}
+
+impl Default for CommitmentTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hash_pair(poseidon: &PoseidonConfig<BlsFr>, left: BlsFr, right: BlsFr) -> BlsFr {
+    let mut sponge = PoseidonSponge::new(poseidon);
+    sponge.absorb(&vec![left, right]);
+    sponge.squeeze_field_elements(1).pop().unwrap()
+}
+
+fn field_bytes(value: &BlsFr) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value.serialize_compressed(&mut bytes).expect("serialization should not fail");
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::MerkleTree;
+
+    fn commitment(i: u8) -> Vec<u8> {
+        crate::crypto::pedersen_commit_u64(100 + i as u64, &[i; 16])
+    }
+
+    #[test]
+    fn test_full_tree_matches_merkle_tree() {
+        let mut tree = CommitmentTree::new();
+        let commitments: Vec<_> = (0..COMMITMENT_TREE_CAPACITY as u8).map(commitment).collect();
+        for c in &commitments {
+            tree.append(c).unwrap();
+        }
+        assert!(tree.append(&commitment(99)).is_err());
+
+        let reference = MerkleTree::new(commitments.iter().map(|c| commitment_leaf(c)).collect());
+        assert_eq!(tree.root(), reference.root());
+
+        let witness = tree.witness(5).unwrap();
+        let expected: Vec<_> = reference.get_path(5).iter().map(|(s, r)| (field_bytes(s), *r)).collect();
+        assert_eq!(witness.path, expected);
+    }
+
+    #[test]
+    fn test_witnesses_follow_appends() {
+        let mut tree = CommitmentTree::new();
+        tree.append(&commitment(0)).unwrap();
+        let before = tree.witness(0).unwrap();
+
+        assert_eq!(tree.append(&commitment(1)).unwrap(), 1);
+        let after = tree.witness(0).unwrap();
+        assert_ne!(before.root, after.root);
+        assert_ne!(before.path[0], after.path[0]);
+        assert_eq!(after.root, tree.root_bytes());
+        assert_eq!(tree.position(&commitment(1)), Some(1));
+        assert!(tree.witness(2).is_err());
+    }
+
+    #[test]
+    fn test_witness_proves_spend() {
+        use crate::crypto::{compute_nullifier, setup_spending_snark, verify_spending_proof};
+        use crate::note::create_note;
+        use crate::secret::Secret;
+
+        let note = create_note(1000, Secret::new(vec![42; 32])).unwrap();
+        let mut tree = CommitmentTree::new();
+        tree.append(&commitment(0)).unwrap();
+        let position = tree.append(&note.commitment).unwrap();
+        tree.append(&[0xFF; 32]).unwrap(); // not a curve point
+
+        let witness = tree.witness(position).unwrap();
+        let (pk, vk) = setup_spending_snark();
+        let proof = note.prove_spending(&pk, &witness.root, witness.path).unwrap();
+
+        let nullifier = compute_nullifier(note.secret_bytes());
+        assert!(verify_spending_proof(&vk, &proof, &witness.root, &nullifier).unwrap());
+    }
+}
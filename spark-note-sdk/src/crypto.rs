@@ -2,6 +2,7 @@ use ark_bls12_381::{Fr, G1Affine, G1Projective};
 use ark_ec::{AffineRepr, CurveGroup};
 use ark_ff::PrimeField;
 use ark_std::ops::Mul;
+use std::sync::OnceLock;
 use subtle::ConstantTimeEq;
 
 // ZK Proof imports
@@ -52,6 +53,10 @@ impl MerkleTree {
         MerkleTree { leaves, tree }
     }
     
+    pub fn leaves(&self) -> &[BlsFr] {
+        &self.leaves
+    }
+
     pub fn root(&self) -> BlsFr {
         self.tree.last().unwrap()[0]
     }
@@ -61,7 +66,7 @@ impl MerkleTree {
         let mut current_index = index;
         
         for level in &self.tree[..self.tree.len()-1] {
-            let sibling_index = if current_index % 2 == 0 { current_index + 1 } else { current_index - 1 };
+            let sibling_index = if current_index.is_multiple_of(2) { current_index + 1 } else { current_index - 1 };
             let sibling = if sibling_index < level.len() { level[sibling_index] } else { level[current_index] };
             let is_right = current_index % 2 == 1;
             path.push((sibling, is_right));
@@ -75,6 +80,13 @@ impl MerkleTree {
 /// (i.e., no one knows the discrete log relationship between G and H).
 const H_DOMAIN_SEP: &[u8] = b"SPARK_PEDERSEN_H_V1";
 
+/// Domain separator of the Jubjub generator H used by note commitments.
+///
+/// Every stored note, backup and on-chain commitment was made with the H
+/// derived from this tag, so changing the derivation needs a new commitment
+/// version and a migration of existing notes.
+const JUBJUB_H_DOMAIN_SEP: &[u8] = b"SPARK_JUBJUB_H";
+
 /// Returns the standard BLS12-381 G1 generator G.
 fn generator_g() -> G1Affine {
     G1Affine::generator()
@@ -100,6 +112,18 @@ fn generator_h() -> G1Affine {
     (G1Projective::from(generator_g()).mul(scalar)).into_affine()
 }
 
+/// The Jubjub generators `(G, H)` used by note commitments, the spending
+/// circuit and the deposit proof
+pub fn jubjub_generators() -> (EdwardsAffine, EdwardsAffine) {
+    static H: OnceLock<EdwardsAffine> = OnceLock::new();
+    let g = EdwardsAffine::generator();
+    let h = *H.get_or_init(|| {
+        let h_scalar = JubjubFr::from_le_bytes_mod_order(blake3::hash(JUBJUB_H_DOMAIN_SEP).as_bytes());
+        EdwardsAffine::from(EdwardsProjective::from(g).mul(h_scalar))
+    });
+    (g, h)
+}
+
 /// Compute a Pedersen commitment: C = v·G + r·H
 ///
 /// This commitment scheme is:
@@ -140,17 +164,7 @@ pub fn pedersen_commit(value: Fr, blinding: Fr) -> G1Affine {
 /// 48-byte compressed BLS12-381 G1 point
 pub fn pedersen_commit_u64(value: u64, blinding_bytes: &[u8]) -> Vec<u8> {
     // For Spark, we must use a Jubjub-based commitment to be circuit-friendly.
-    let g = EdwardsAffine::generator();
-    
-    // Derive H (nothing-up-my-sleeve)
-    let h_bytes = blake3::hash(b"SPARK_JUBJUB_H").as_bytes().to_vec();
-    let h_scalar = JubjubFr::from_le_bytes_mod_order(&h_bytes);
-    let h = EdwardsAffine::from(EdwardsProjective::from(g).mul(h_scalar));
-
-    let v_scalar = JubjubFr::from(value);
-    let s_scalar = JubjubFr::from_le_bytes_mod_order(blinding_bytes);
-
-    let commitment = (EdwardsProjective::from(g).mul(v_scalar) + EdwardsProjective::from(h).mul(s_scalar)).into_affine();
+    let commitment = pedersen_commit_point(value, blinding_bytes);
 
     // Serialize to compressed form (32 bytes for Jubjub)
     let mut buf = Vec::new();
@@ -160,6 +174,15 @@ pub fn pedersen_commit_u64(value: u64, blinding_bytes: &[u8]) -> Vec<u8> {
     buf
 }
 
+/// Jubjub commitment point `C = v·G + s·H` of a note, as used by the
+/// spending circuit
+pub fn pedersen_commit_point(value: u64, blinding_bytes: &[u8]) -> EdwardsAffine {
+    let (g, h) = jubjub_generators();
+    let v_scalar = JubjubFr::from(value);
+    let s_scalar = JubjubFr::from_le_bytes_mod_order(blinding_bytes);
+    (EdwardsProjective::from(g).mul(v_scalar) + EdwardsProjective::from(h).mul(s_scalar)).into_affine()
+}
+
 /// Constant-time comparison of two byte slices
 ///
 /// Returns true if slices are equal, false otherwise.
@@ -241,15 +264,12 @@ impl ConstraintSynthesizer<BlsFr> for SpendingCircuit {
 
         // --- 3. Range Check: 0 <= value < 2^64 ---
         let value_bits = value_var.to_bits_le()?;
-        for i in 64..value_bits.len() {
-            value_bits[i].enforce_equal(&Boolean::FALSE)?;
+        for bit in &value_bits[64..] {
+            bit.enforce_equal(&Boolean::FALSE)?;
         }
 
         // --- 4. Pedersen Commitment Check: C = v*G + s*H ---
-        let g = EdwardsAffine::generator();
-        let h_bytes = blake3::hash(b"SPARK_JUBJUB_H").as_bytes().to_vec();
-        let h_scalar = JubjubFr::from_le_bytes_mod_order(&h_bytes);
-        let h = EdwardsAffine::from(EdwardsProjective::from(g).mul(h_scalar));
+        let (g, h) = jubjub_generators();
 
         let g_var = AffineVar::new_constant(ark_relations::ns!(cs, "g"), g)?;
         let h_var = AffineVar::new_constant(ark_relations::ns!(cs, "h"), h)?;
@@ -281,7 +301,7 @@ impl ConstraintSynthesizer<BlsFr> for SpendingCircuit {
         let mut current_hash = leaf_hash;
         let path = self.path.unwrap_or_else(|| vec![(BlsFr::default(), false); MERKLE_TREE_DEPTH]);
         
-        for (_i, (sibling_val, is_right)) in path.into_iter().enumerate() {
+        for (sibling_val, is_right) in path.into_iter() {
             let sibling_var = FpVar::new_witness(ark_relations::ns!(cs, "sibling"), || Ok(sibling_val))?;
             let is_right_var = Boolean::new_witness(ark_relations::ns!(cs, "is_right"), || Ok(is_right))?;
             
@@ -359,8 +379,7 @@ pub fn generate_spending_proof(
     merkle_path: Vec<(Vec<u8>, bool)>,
     commitment: &EdwardsAffine,
 ) -> SparkResult<SpendingProof> {
-    use rand::SeedableRng;
-    let mut rng = rand_chacha::ChaChaRng::from_entropy();
+    let mut rng = crate::rng::secure_rng()?;
     let poseidon_config = setup_poseidon_config();
     
     let secret = BlsFr::from_le_bytes_mod_order(secret_bytes);
@@ -429,6 +448,27 @@ pub fn compute_nullifier(secret_bytes: &[u8]) -> Vec<u8> {
     buf
 }
 
+/// Compute the Merkle leaf of a note commitment: Poseidon(C.x, C.y)
+///
+/// Matches the leaf hashed inside the spending circuit. Bytes that are not a
+/// valid compressed Jubjub point cannot belong to a spendable note, but the
+/// contract accepts them anyway, so they are hashed into the field to keep
+/// their position in the tree.
+pub fn commitment_leaf(commitment: &[u8]) -> BlsFr {
+    match EdwardsAffine::deserialize_compressed(commitment) {
+        Ok(point) => {
+            let mut sponge = PoseidonSponge::new(&setup_poseidon_config());
+            sponge.absorb(&vec![point.x, point.y]);
+            sponge.squeeze_field_elements(1).pop().unwrap()
+        }
+        Err(_) => {
+            let mut hasher = blake3::Hasher::new_derive_key("spark-note-sdk invalid commitment leaf");
+            hasher.update(commitment);
+            BlsFr::from_le_bytes_mod_order(hasher.finalize().as_bytes())
+        }
+    }
+}
+
 
 
 
@@ -438,9 +478,100 @@ pub fn compute_nullifier(secret_bytes: &[u8]) -> Vec<u8> {
 
 // --- Native Schnorr Sigma Protocol (ZK Proof) ---
 
-/// A Zero-Knowledge Proof of Knowledge (PoK) for a Pedersen commitment.
-/// Proves knowledge of `v` and `r` such that `C = v*G + r*H` without revealing them.
+/// Domain separator for the deposit proof challenge
+const DEPOSIT_PROOF_DOMAIN: &str = "spark-note-sdk deposit proof v1";
+
+/// A Zero-Knowledge Proof of Knowledge (PoK) of a Pedersen commitment opening.
+/// Proves knowledge of `s` such that `C = v*G + s*H` for a public value `v`,
+/// i.e. that `C - v*G` is a multiple of `H`, without revealing `s`.
 /// Implemented as a non-interactive Schnorr Sigma Protocol using Fiat-Shamir.
+#[derive(Clone, Debug, PartialEq, Eq, CanonicalSerialize, CanonicalDeserialize)]
+pub struct DepositProof {
+    /// Commitment to the prover's nonce: `R = k*H`
+    pub nonce_commitment: EdwardsAffine,
+    /// Response: `z = k + e*s`
+    pub response: JubjubFr,
+}
+
+impl DepositProof {
+    /// Size of a serialized proof in bytes
+    pub const SIZE: usize = 64;
+
+    /// Serialize the proof to a byte vector.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::SIZE);
+        self.serialize_compressed(&mut buf).expect("serialization should not fail");
+        buf
+    }
+
+    /// Deserialize a proof from a byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> SparkResult<Self> {
+        Self::deserialize_compressed(bytes)
+            .map_err(|e| SparkError::invalid_proof(format!("Failed to deserialize deposit proof: {:?}", e)))
+    }
+}
+
+/// Fiat-Shamir challenge binding the commitment, the public value and `R`
+fn deposit_challenge(commitment: &[u8], value: u64, nonce_commitment: &EdwardsAffine) -> JubjubFr {
+    let mut r_bytes = Vec::new();
+    nonce_commitment.serialize_compressed(&mut r_bytes).expect("serialization should not fail");
+
+    let mut hasher = blake3::Hasher::new_derive_key(DEPOSIT_PROOF_DOMAIN);
+    hasher.update(commitment);
+    hasher.update(&value.to_le_bytes());
+    hasher.update(&r_bytes);
+    JubjubFr::from_le_bytes_mod_order(hasher.finalize().as_bytes())
+}
+
+/// Prove that the commitment of a note opens to `value`.
+///
+/// # Arguments
+/// * `value` - The public deposit value
+/// * `secret_bytes` - The note secret (the commitment's blinding factor)
+///
+/// # Returns
+/// A proof to be checked with [`verify_deposit_proof`] against the note commitment
+///
+/// # Errors
+/// Fails if the system random number generator fails
+pub fn prove_deposit(value: u64, secret_bytes: &[u8]) -> SparkResult<DepositProof> {
+    let mut rng = crate::rng::secure_rng()?;
+    let (_, h) = jubjub_generators();
+
+    let secret = JubjubFr::from_le_bytes_mod_order(secret_bytes);
+    let commitment = pedersen_commit_u64(value, secret_bytes);
+
+    let nonce = JubjubFr::rand(&mut rng);
+    let nonce_commitment = EdwardsProjective::from(h).mul(nonce).into_affine();
+    let challenge = deposit_challenge(&commitment, value, &nonce_commitment);
+
+    Ok(DepositProof {
+        nonce_commitment,
+        response: nonce + challenge * secret,
+    })
+}
+
+/// Verify that `commitment` opens to the public `value`.
+///
+/// Checks `z*H == R + e*(C - v*G)`.
+///
+/// # Returns
+/// * `Ok(true)` if the proof is valid
+/// * `Ok(false)` if it is not
+/// * `Err(SparkError)` if the commitment is not a valid Jubjub point
+pub fn verify_deposit_proof(commitment: &[u8], value: u64, proof: &DepositProof) -> SparkResult<bool> {
+    let point = EdwardsAffine::deserialize_compressed(commitment)
+        .map_err(|e| SparkError::invalid_proof(format!("Invalid commitment point: {:?}", e)))?;
+    let (g, h) = jubjub_generators();
+
+    let challenge = deposit_challenge(commitment, value, &proof.nonce_commitment);
+    let blinded = EdwardsProjective::from(point) - EdwardsProjective::from(g).mul(JubjubFr::from(value));
+
+    let lhs = EdwardsProjective::from(h).mul(proof.response);
+    let rhs = EdwardsProjective::from(proof.nonce_commitment) + blinded.mul(challenge);
+    Ok(lhs == rhs)
+}
+
 /// A Zero-Knowledge Proof for spending a Spark note.
 #[derive(Clone, CanonicalSerialize, CanonicalDeserialize, Debug)]
 pub struct SpendingProof {
@@ -480,7 +611,6 @@ impl SpendingProof {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ark_ec::AffineRepr;
     use ark_ff::PrimeField;
     use ark_std::test_rng;
 
@@ -591,6 +721,42 @@ mod tests {
         assert_ne!(g, h, "G and H must be independent generators");
     }
 
+    #[test]
+    fn test_commitment_generators_are_stable() {
+        // Existing notes and chain commitments depend on these exact points
+        let (g, h) = jubjub_generators();
+        let scalar = JubjubFr::from_le_bytes_mod_order(blake3::hash(b"SPARK_JUBJUB_H").as_bytes());
+        assert_eq!(h, EdwardsProjective::from(g).mul(scalar).into_affine());
+        assert_eq!(jubjub_generators(), (g, h));
+
+        let scalar = Fr::from_le_bytes_mod_order(blake3::hash(b"SPARK_PEDERSEN_H_V1").as_bytes());
+        assert_eq!(generator_h(), G1Projective::from(generator_g()).mul(scalar).into_affine());
+
+        assert_eq!(
+            hex::encode(pedersen_commit_u64(1000, &[1, 2, 3, 4, 5, 6, 7, 8])),
+            "40f7358a7280705875a5b6ae09a99ab4e0b928de425721e4ff2f439f224bc7c4"
+        );
+    }
+
+    #[test]
+    fn test_deposit_proof() {
+        let secret = [7u8; 32];
+        let commitment = pedersen_commit_u64(1000, &secret);
+        let proof = prove_deposit(1000, &secret).unwrap();
+
+        assert!(verify_deposit_proof(&commitment, 1000, &proof).unwrap());
+        // The same commitment does not open to another amount
+        assert!(!verify_deposit_proof(&commitment, 1001, &proof).unwrap());
+        // Nor does the proof transfer to another commitment of the same amount
+        let other = pedersen_commit_u64(1000, &[8u8; 32]);
+        assert!(!verify_deposit_proof(&other, 1000, &proof).unwrap());
+
+        let bytes = proof.to_bytes();
+        assert_eq!(bytes.len(), DepositProof::SIZE);
+        assert_eq!(DepositProof::from_bytes(&bytes).unwrap(), proof);
+        assert!(verify_deposit_proof(&[0xFF; 32], 1000, &proof).is_err());
+    }
+
     #[test]
     fn test_spending_proof_valid() {
         let value = 1000u64;
@@ -600,13 +766,7 @@ mod tests {
         let poseidon_config = setup_poseidon_config();
         
         // Create commitment point
-        let g = EdwardsAffine::generator();
-        let h_bytes = blake3::hash(b"SPARK_JUBJUB_H").as_bytes().to_vec();
-        let h_scalar = JubjubFr::from_le_bytes_mod_order(&h_bytes);
-        let h = EdwardsAffine::from(EdwardsProjective::from(g).mul(h_scalar));
-        let v_scalar = JubjubFr::from(value);
-        let s_scalar = JubjubFr::from_le_bytes_mod_order(secret);
-        let commitment_point = (EdwardsProjective::from(g).mul(v_scalar) + EdwardsProjective::from(h).mul(s_scalar)).into_affine();
+        let commitment_point = pedersen_commit_point(value, secret);
 
         // Hash commitment to get leaf
         let mut commit_sponge = PoseidonSponge::new(&poseidon_config);
@@ -0,0 +1,260 @@
+//! Note change events
+//!
+//! This module provides typed [`NoteEvent`]s emitted by the
+//! [`NoteManager`](crate::manager::NoteManager) whenever its state changes.
+//! Consumers can either register a callback observer or subscribe to a
+//! channel that works from both synchronous and asynchronous code.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before the oldest are dropped
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// A change to the notes held by a manager
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoteEvent {
+    /// A note was added to the manager
+    NoteAdded {
+        /// ID of the new note
+        id: String,
+        /// Value of the new note
+        value: u64,
+        /// Commitment of the new note
+        commitment: Vec<u8>,
+    },
+    /// A nullifier was derived for a note
+    NullifierGenerated {
+        /// ID of the note
+        id: String,
+        /// The derived nullifier
+        nullifier: Vec<u8>,
+    },
+    /// A note was marked as spent
+    NoteSpent {
+        /// ID of the spent note
+        id: String,
+        /// Nullifier added to the spent set
+        nullifier: Vec<u8>,
+    },
+    /// A note was removed from the manager
+    NoteRemoved {
+        /// ID of the removed note
+        id: String,
+    },
+    /// A blockchain scan found a commitment belonging to this wallet
+    NoteDiscovered {
+        /// The discovered commitment
+        commitment: Vec<u8>,
+        /// ID of the matching local note, if it is already known
+        id: Option<String>,
+    },
+}
+
+/// Identifier returned when registering an observer
+pub type ObserverId = u64;
+
+type Observer = Arc<dyn Fn(&NoteEvent) + Send + Sync>;
+
+/// Fan-out point for note events
+///
+/// Cloning the bus yields a handle to the same set of subscribers and
+/// observers.
+#[derive(Clone)]
+pub struct NoteEventBus {
+    sender: broadcast::Sender<NoteEvent>,
+    observers: Arc<Mutex<Vec<(ObserverId, Observer)>>>,
+    next_observer_id: Arc<AtomicU64>,
+}
+
+impl NoteEventBus {
+    /// Creates a bus with no subscribers
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        NoteEventBus {
+            sender,
+            observers: Arc::new(Mutex::new(Vec::new())),
+            next_observer_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Subscribe to all events emitted after this call
+    pub fn subscribe(&self) -> NoteEventReceiver {
+        NoteEventReceiver {
+            receiver: self.sender.subscribe(),
+            missed: 0,
+        }
+    }
+
+    /// Register a callback invoked synchronously for every event
+    ///
+    /// The callback runs on the thread that changed the manager, so it should
+    /// return quickly.
+    pub fn add_observer<F>(&self, observer: F) -> ObserverId
+    where
+        F: Fn(&NoteEvent) + Send + Sync + 'static,
+    {
+        let id = self.next_observer_id.fetch_add(1, Ordering::Relaxed);
+        self.lock_observers().push((id, Arc::new(observer)));
+        id
+    }
+
+    /// Unregister a callback
+    ///
+    /// # Returns
+    /// `true` if an observer with this ID was registered
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        let mut observers = self.lock_observers();
+        let before = observers.len();
+        observers.retain(|(observer_id, _)| *observer_id != id);
+        observers.len() != before
+    }
+
+    /// Deliver an event to every observer and subscriber
+    pub(crate) fn emit(&self, event: NoteEvent) {
+        // Call observers outside the lock so they may (un)register others
+        let observers: Vec<Observer> = self.lock_observers().iter().map(|(_, o)| o.clone()).collect();
+        for observer in observers {
+            observer(&event);
+        }
+        // An error only means there are no subscribers right now
+        let _ = self.sender.send(event);
+    }
+
+    fn lock_observers(&self) -> std::sync::MutexGuard<'_, Vec<(ObserverId, Observer)>> {
+        self.observers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for NoteEventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for NoteEventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoteEventBus")
+            .field("subscribers", &self.sender.receiver_count())
+            .field("observers", &self.lock_observers().len())
+            .finish()
+    }
+}
+
+/// Receiving end of an event subscription
+///
+/// Slow receivers that fall more than [`EVENT_CHANNEL_CAPACITY`] events behind
+/// skip the oldest events; the number skipped is reported by [`missed`](Self::missed).
+#[derive(Debug)]
+pub struct NoteEventReceiver {
+    receiver: broadcast::Receiver<NoteEvent>,
+    missed: u64,
+}
+
+impl NoteEventReceiver {
+    /// Wait for the next event
+    ///
+    /// # Returns
+    /// `None` once the manager (and every clone of it) has been dropped
+    pub async fn recv(&mut self) -> Option<NoteEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => self.missed += n,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Block the current thread until the next event
+    ///
+    /// Must not be called from within an async runtime; use [`recv`](Self::recv) there.
+    pub fn blocking_recv(&mut self) -> Option<NoteEvent> {
+        loop {
+            match self.receiver.blocking_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(n)) => self.missed += n,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Return the next event if one is already queued
+    pub fn try_recv(&mut self) -> Option<NoteEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::TryRecvError::Lagged(n)) => self.missed += n,
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Number of events skipped because this receiver fell behind
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observer_receives_events() {
+        let bus = NoteEventBus::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let id = bus.add_observer(move |event| sink.lock().unwrap().push(event.clone()));
+
+        bus.emit(NoteEvent::NoteRemoved { id: "a".to_string() });
+        assert!(bus.remove_observer(id));
+        bus.emit(NoteEvent::NoteRemoved { id: "b".to_string() });
+
+        assert_eq!(*seen.lock().unwrap(), vec![NoteEvent::NoteRemoved { id: "a".to_string() }]);
+        assert!(!bus.remove_observer(id));
+    }
+
+    #[test]
+    fn test_subscriber_try_recv_and_lag() {
+        let bus = NoteEventBus::new();
+        let mut receiver = bus.subscribe();
+        assert_eq!(receiver.try_recv(), None);
+
+        for i in 0..(EVENT_CHANNEL_CAPACITY + 2) {
+            bus.emit(NoteEvent::NoteRemoved { id: i.to_string() });
+        }
+
+        assert_eq!(receiver.try_recv(), Some(NoteEvent::NoteRemoved { id: "2".to_string() }));
+        assert_eq!(receiver.missed(), 2);
+    }
+
+    #[test]
+    fn test_blocking_recv_closed() {
+        let bus = NoteEventBus::new();
+        let mut receiver = bus.subscribe();
+        bus.emit(NoteEvent::NoteRemoved { id: "a".to_string() });
+        drop(bus);
+
+        assert!(receiver.blocking_recv().is_some());
+        assert_eq!(receiver.blocking_recv(), None);
+    }
+
+    #[tokio::test]
+    async fn test_async_recv() {
+        let bus = NoteEventBus::new();
+        let mut receiver = bus.subscribe();
+
+        let sender = bus.clone();
+        tokio::spawn(async move {
+            sender.emit(NoteEvent::NoteDiscovered { commitment: vec![1; 32], id: None });
+        });
+
+        let event = receiver.recv().await.unwrap();
+        assert!(matches!(event, NoteEvent::NoteDiscovered { id: None, .. }));
+    }
+}
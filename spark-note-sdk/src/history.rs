@@ -0,0 +1,355 @@
+//! Append-only operation history
+//!
+//! Every state change made through a [`NoteManager`](crate::manager::NoteManager)
+//! is appended to a hash-chained log: each [`HistoryRecord`] commits to the
+//! hash of the record before it, so editing, reordering or deleting a record
+//! in the middle of the log is detected by [`verify_chain`]. Truncating the
+//! end of the log can only be detected against a previously saved
+//! [`head hash`](crate::manager::NoteManager::history_head).
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{SparkError, SparkResult};
+use crate::manager::NoteState;
+
+/// Sled tree holding the history of an account
+pub(crate) const HISTORY_TREE: &str = "history";
+/// `prev_hash` of the first record in a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Actor recorded when none has been configured
+pub const DEFAULT_ACTOR: &str = "local";
+
+/// A state change recorded in the history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryAction {
+    /// A note was added
+    NoteAdded {
+        /// Note ID
+        id: String,
+        /// Note value
+        value: u64,
+    },
+    /// A note's metadata was replaced
+    MetadataUpdated {
+        /// Note ID
+        id: String,
+    },
+    /// A nullifier was derived for a note
+    NullifierGenerated {
+        /// Note ID
+        id: String,
+        /// The nullifier (hex-encoded)
+        nullifier: String,
+    },
+    /// A note was marked as spent
+    NoteSpent {
+        /// Note ID
+        id: String,
+        /// The spent nullifier (hex-encoded)
+        nullifier: String,
+        /// State of the note before it was spent
+        previous_state: NoteState,
+    },
+    /// A nullifier not tied to a local note was added to the spent set
+    NullifierSpent {
+        /// The nullifier (hex-encoded)
+        nullifier: String,
+    },
+    /// Nullifiers found on chain were added to the spent set
+    ChainNullifiersImported {
+        /// Nullifiers added
+        imported: usize,
+    },
+    /// Commitments deposited on chain were appended to the commitment tree
+    CommitmentsSynced {
+        /// Commitments appended
+        appended: usize,
+        /// Size of the tree afterwards
+        tree_size: u64,
+    },
+    /// An owned note was found in the commitment tree
+    NoteFoundOnChain {
+        /// Note ID
+        id: String,
+        /// Leaf position of the note's commitment
+        position: u64,
+    },
+    /// A note was removed
+    NoteRemoved {
+        /// Note ID
+        id: String,
+        /// Value of the removed note
+        value: u64,
+        /// State of the note when it was removed
+        state: NoteState,
+    },
+    /// A wallet backup was restored
+    BackupRestored {
+        /// Notes added
+        restored: usize,
+        /// Notes whose state was merged into an existing note
+        merged: usize,
+        /// Notes replaced by the backup's version
+        overwritten: usize,
+    },
+    /// A deposit was submitted to Tezos
+    DepositSynced {
+        /// Note ID
+        id: String,
+        /// Tezos operation hash
+        operation_hash: String,
+    },
+    /// A spend was submitted to Tezos
+    SpendSynced {
+        /// Note ID
+        id: String,
+        /// Tezos operation hash
+        operation_hash: String,
+    },
+}
+
+/// One entry of the history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    /// Position in the log, starting at 0
+    pub sequence: u64,
+    /// Unix time at which the change was made
+    pub timestamp: u64,
+    /// Who made the change
+    pub actor: String,
+    /// What changed
+    pub action: HistoryAction,
+    /// Hash of the previous record ([`GENESIS_HASH`] for the first)
+    pub prev_hash: String,
+    /// Hash of this record (hex-encoded BLAKE3)
+    pub hash: String,
+}
+
+impl HistoryRecord {
+    /// Hash of every field except `hash` itself
+    pub fn compute_hash(&self) -> SparkResult<String> {
+        let hashed = serde_json::to_vec(&(
+            self.sequence,
+            self.timestamp,
+            &self.actor,
+            &self.action,
+            &self.prev_hash,
+        ))
+        .map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize history record: {}", e),
+        })?;
+        Ok(blake3::hash(&hashed).to_hex().to_string())
+    }
+}
+
+/// Check that `records` form an unbroken hash chain starting at genesis
+///
+/// # Returns
+/// * `Ok(usize)` - The number of records verified
+/// * `Err(SparkError)` naming the first record that does not verify
+pub fn verify_chain(records: &[HistoryRecord]) -> SparkResult<usize> {
+    let mut prev_hash = GENESIS_HASH.to_string();
+    for (position, record) in records.iter().enumerate() {
+        if record.sequence != position as u64 {
+            return Err(tampered(position as u64, "sequence number is out of order"));
+        }
+        if record.prev_hash != prev_hash {
+            return Err(tampered(record.sequence, "does not link to the previous record"));
+        }
+        if record.compute_hash()? != record.hash {
+            return Err(tampered(record.sequence, "hash does not match its contents"));
+        }
+        prev_hash = record.hash.clone();
+    }
+    Ok(records.len())
+}
+
+fn tampered(sequence: u64, reason: &str) -> SparkError {
+    SparkError::OperationError {
+        message: format!("History record {} has been tampered with: {}", sequence, reason),
+    }
+}
+
+/// Mutable end of the log
+#[derive(Debug)]
+struct LogState {
+    /// Records of in-memory logs (persistent logs keep theirs in the tree)
+    records: Vec<HistoryRecord>,
+    next_sequence: u64,
+    head: String,
+}
+
+/// The history of one manager, in memory or in a sled tree
+///
+/// Clones share the same log, so appends made through `&self` (for example
+/// after an async Tezos call) are seen by every clone.
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryLog {
+    state: Arc<Mutex<LogState>>,
+    tree: Option<sled::Tree>,
+}
+
+impl HistoryLog {
+    /// An empty log that is not persisted
+    pub(crate) fn in_memory() -> Self {
+        HistoryLog {
+            state: Arc::new(Mutex::new(LogState {
+                records: Vec::new(),
+                next_sequence: 0,
+                head: GENESIS_HASH.to_string(),
+            })),
+            tree: None,
+        }
+    }
+
+    /// Open a log persisted in `tree`, continuing from its last record
+    pub(crate) fn open(tree: sled::Tree) -> SparkResult<Self> {
+        let log = Self::in_memory();
+        let last = tree.last().map_err(|e| SparkError::SerializationError {
+            message: format!("Database read error: {}", e),
+        })?;
+        if let Some((_, bytes)) = last {
+            let record = decode_record(&bytes)?;
+            let mut state = log.lock();
+            state.next_sequence = record.sequence + 1;
+            state.head = record.hash;
+        }
+        Ok(HistoryLog { tree: Some(tree), ..log })
+    }
+
+    /// Append a record for `action` and return it
+    pub(crate) fn append(&self, actor: &str, action: HistoryAction) -> SparkResult<HistoryRecord> {
+        let mut state = self.lock();
+        let mut record = HistoryRecord {
+            sequence: state.next_sequence,
+            timestamp: crate::clock::unix_timestamp(),
+            actor: actor.to_string(),
+            action,
+            prev_hash: state.head.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash()?;
+
+        match &self.tree {
+            Some(tree) => {
+                let bytes = serde_json::to_vec(&record).map_err(|e| SparkError::SerializationError {
+                    message: format!("Failed to serialize history record: {}", e),
+                })?;
+                tree.insert(record.sequence.to_be_bytes(), bytes).map_err(|e| SparkError::OperationError {
+                    message: format!("Database write error: {}", e),
+                })?;
+                tree.flush().map_err(|e| SparkError::OperationError {
+                    message: format!("Database flush error: {}", e),
+                })?;
+            }
+            None => state.records.push(record.clone()),
+        }
+
+        state.next_sequence += 1;
+        state.head = record.hash.clone();
+        Ok(record)
+    }
+
+    /// Every record, oldest first
+    pub(crate) fn records(&self) -> SparkResult<Vec<HistoryRecord>> {
+        let Some(tree) = &self.tree else {
+            return Ok(self.lock().records.clone());
+        };
+
+        let mut records = Vec::with_capacity(tree.len());
+        for item in tree.iter() {
+            let (key, bytes) = item.map_err(|e| SparkError::SerializationError {
+                message: format!("Database read error: {}", e),
+            })?;
+            let record = decode_record(&bytes)?;
+            // A record moved to another key is as suspicious as an edited one
+            if key.as_ref() != record.sequence.to_be_bytes() {
+                return Err(tampered(record.sequence, "stored under the wrong key"));
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// Hash of the most recent record ([`GENESIS_HASH`] if empty)
+    pub(crate) fn head(&self) -> String {
+        self.lock().head.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LogState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn decode_record(bytes: &[u8]) -> SparkResult<HistoryRecord> {
+    serde_json::from_slice(bytes).map_err(|e| SparkError::SerializationError {
+        message: format!("Failed to deserialize history record: {}", e),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn added(id: &str) -> HistoryAction {
+        HistoryAction::NoteAdded { id: id.to_string(), value: 1 }
+    }
+
+    #[test]
+    fn test_chain_links_records() {
+        let log = HistoryLog::in_memory();
+        let first = log.append("alice", added("a")).unwrap();
+        let second = log.append("bob", added("b")).unwrap();
+
+        assert_eq!(first.prev_hash, GENESIS_HASH);
+        assert_eq!(second.prev_hash, first.hash);
+        assert_eq!(log.head(), second.hash);
+        assert_eq!(verify_chain(&log.records().unwrap()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_verify_detects_tampering() {
+        let log = HistoryLog::in_memory();
+        for id in ["a", "b", "c"] {
+            log.append(DEFAULT_ACTOR, added(id)).unwrap();
+        }
+        let records = log.records().unwrap();
+
+        let mut edited = records.clone();
+        edited[1].actor = "mallory".to_string();
+        assert!(verify_chain(&edited).is_err());
+
+        // Re-hashing the edited record breaks the link from its successor
+        edited[1].hash = edited[1].compute_hash().unwrap();
+        assert!(verify_chain(&edited).is_err());
+
+        let mut removed = records.clone();
+        removed.remove(1);
+        assert!(verify_chain(&removed).is_err());
+
+        let mut reordered = records;
+        reordered.swap(0, 1);
+        assert!(verify_chain(&reordered).is_err());
+    }
+
+    #[test]
+    fn test_persistent_log_continues_after_reopen() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree(HISTORY_TREE).unwrap();
+
+        let head = {
+            let log = HistoryLog::open(tree.clone()).unwrap();
+            log.append(DEFAULT_ACTOR, added("a")).unwrap();
+            log.head()
+        };
+
+        let log = HistoryLog::open(tree).unwrap();
+        assert_eq!(log.head(), head);
+        let record = log.append(DEFAULT_ACTOR, added("b")).unwrap();
+        assert_eq!(record.sequence, 1);
+        assert_eq!(verify_chain(&log.records().unwrap()).unwrap(), 2);
+    }
+}
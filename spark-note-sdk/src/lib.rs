@@ -37,35 +37,100 @@
 //!
 //! - [`note`] - Spark note structure and creation
 //! - [`nullifier`] - Nullifier generation and spent tracking
+//! - [`nullifier_accumulator`] - Sorted Merkle tree proving nullifiers spent or unspent
+//! - [`nullifier_store`] - The `NullifierStore` trait and a disk-backed store with a Bloom filter
+//! - [`nullifier_sync`] - Nullifier set diffs and incremental replica sync
+//! - [`snapshot`] - Signed nullifier set snapshots for light clients
+//! - [`manager`] - Note storage, persistence and Tezos synchronization
+//! - [`query`] - Filtered, sorted and paginated note queries
+//! - [`events`] - Change notifications emitted by the note manager
+//! - [`shared`] - Thread-safe note manager with an async API
+//! - [`backup`] - Password-encrypted wallet backup and restore
+//! - [`account`] - Multiple, optionally encrypted, accounts in one database
+//! - [`history`] - Hash-chained audit log of note state changes
+//! - [`commitment_tree`] - Incremental tree of on-chain commitments and note witnesses
+//! - [`spend`] - Spending keys and receipts for proved spends
+//! - [`micheline`] - Micheline values with JSON and binary codecs and typed conversions
+//! - [`tezos`] - Client for the on-chain nullifier registry
+//! - [`tezos_batch`] - Several contract calls sent as one operation
+//! - [`tezos_fees`] - Operation simulation and fee, gas and storage estimation
+//! - [`tezos_forge`] - Local binary forging of Tezos operations
+//! - [`tezos_keys`] - Tezos keys, addresses and operation signatures
+//! - [`tezos_receipts`] - Receipts of included operations
+//! - [`tezos_storage`] - Typed registry storage and big-map access
 
+pub mod account;
+pub mod backup;
+pub mod clock;
+pub mod commitment_tree;
 pub mod error;
+pub mod events;
+pub mod history;
 pub mod manager;
+pub mod micheline;
 pub mod note;
 pub mod nullifier;
+pub mod nullifier_accumulator;
+pub mod nullifier_store;
+pub mod nullifier_sync;
 pub mod nullifier_type;
+pub mod query;
 pub mod secret;
 pub mod serialization;
+pub mod shared;
+pub mod snapshot;
+pub mod spend;
 pub mod validation;
 pub mod rng;
 pub mod crypto;
 pub mod tezos;
+pub mod tezos_batch;
+pub mod tezos_fees;
+pub mod tezos_forge;
+pub mod tezos_keys;
+pub mod tezos_receipts;
+pub mod tezos_storage;
 
 // WASM bindings (enabled with --features wasm)
 #[cfg(feature = "wasm")]
 pub mod wasm;
 
 // Re-export commonly used types for convenience
+pub use account::{AccountInfo, AccountKey, WalletDb};
+pub use backup::{ConflictPolicy, RestoreReport, WalletBackup};
+pub use commitment_tree::{CommitmentTree, MerkleWitness};
 pub use error::{SparkError, SparkResult};
-pub use manager::{NoteEntry, NoteManager, NoteState, PublicNote};
+pub use events::{NoteEvent, NoteEventReceiver};
+pub use history::{HistoryAction, HistoryRecord};
+pub use manager::{NoteEntry, NoteManager, NoteMetadata, NoteSource, NoteState, PublicNote};
+pub use micheline::{Address, FromMicheline, Micheline, ToMicheline};
 pub use note::{create_note, note_commitment, SparkNote};
 pub use nullifier::{
     check_multiple_nullifiers, generate_nullifier, get_nullifier_set_size,
     get_nullifier_set_stats, is_nullifier_spent, mark_as_spent, mark_multiple_as_spent,
-    NullifierSetStats,
+    NullifierSetStats, ReconcileReport,
+};
+pub use nullifier_accumulator::{
+    verify_membership, verify_non_membership, MembershipProof, NonMembershipProof, NullifierAccumulator,
+};
+pub use nullifier_store::{BloomFilter, NullifierStore, SledNullifierStore};
+pub use nullifier_sync::{NullifierDelta, NullifierJournal, NullifierSetDiff};
+pub use query::{NoteQuery, NoteQueryResult, NoteSortField, SortOrder};
+pub use shared::SharedNoteManager;
+pub use snapshot::{import_snapshot, SignedSnapshotHeader, SnapshotHeader, SnapshotSigner, TrustedSigners};
+pub use spend::{SpendReceipt, SpendingKeys};
+pub use serialization::{
+    export_nullifier_set, export_nullifier_set_binary, import_nullifier_set, import_nullifier_set_binary,
+    NullifierSetExport, NullifierSetReader, NullifierSetWriter,
 };
-pub use serialization::{export_nullifier_set, import_nullifier_set, NullifierSetExport};
 pub use validation::{validate_nullifier, validate_secret, validate_value};
 pub use tezos::{TezosClient, TezosOperationResult};
+pub use tezos_batch::{BatchCall, BatchResult, OperationBatch};
+pub use tezos_fees::{OperationLimits, OperationStatus, Simulation};
+pub use tezos_forge::{OperationContent, UnsignedOperation};
+pub use tezos_keys::{TezosCurve, TezosPublicKey, TezosSecretKey, TezosSignature};
+pub use tezos_receipts::OperationReceipt;
+pub use tezos_storage::RegistryStorage;
 
 // UniFFI setup for native bindings
 // uniffi::setup_scaffolding!();
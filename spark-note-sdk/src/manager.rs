@@ -3,19 +3,29 @@
 //! This module provides a NoteManager struct for managing multiple notes,
 //! tracking nullifier sets, and providing query methods.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use serde::{Deserialize, Serialize};
 
+use crate::account::{account_tree_name, AccountKey, DEFAULT_ACCOUNT};
+use crate::commitment_tree::{CommitmentTree, MerkleWitness};
+use crate::backup::{BackupNote, BackupPayload, ConflictPolicy, KdfParams, RestoreReport, WalletBackup};
 use crate::error::{SparkError, SparkResult};
+use crate::events::{NoteEvent, NoteEventBus, NoteEventReceiver, ObserverId};
+use crate::history::{self, HistoryAction, HistoryLog, HistoryRecord, HISTORY_TREE};
 use crate::note::SparkNote;
-use crate::nullifier::{generate_nullifier, NullifierSet, Nullifier};
+use crate::nullifier::{generate_nullifier, NullifierSet, Nullifier, ReconcileReport};
+use crate::nullifier_store::{NullifierStore, SledNullifierStore};
+use crate::query::{self, IndexScan, NoteQuery, NoteQueryResult};
 use crate::secret::Secret;
+use crate::spend::{PreparedBatch, PreparedSpend, SpendReceipt, SpendingKeys};
 
 /// Note state tracking
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)] // uniffi::Enum
 pub enum NoteState {
     /// Note has not been spent
     Unspent,
+    /// A spend of the note is being proved or submitted
+    PendingSpend,
     /// Note has been spent
     Spent,
 }
@@ -45,6 +55,120 @@ pub struct NoteEntry {
     pub state: NoteState,
     /// Nullifier for this note (if generated)
     pub nullifier: Option<Vec<u8>>,
+    /// Leaf position of the note's commitment in the commitment tree, once synced
+    pub position: Option<u64>,
+}
+
+/// Descriptive data attached to a note
+///
+/// Metadata is kept in its own map (and its own sled tree for persistent
+/// managers), never alongside the spending secret.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)] // uniffi::Record
+pub struct NoteMetadata {
+    /// Asset the note is denominated in (`None` for native tez)
+    pub asset: Option<String>,
+    /// Human-readable label
+    pub label: Option<String>,
+    /// Free-form tags
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Unix time at which the note was added (set automatically if absent)
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    /// How the wallet came to hold the note
+    #[serde(default)]
+    pub source: Option<NoteSource>,
+    /// Arbitrary key/value pairs
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Maximum length in bytes of any metadata string
+pub const MAX_METADATA_STRING_LENGTH: usize = 256;
+/// Maximum number of tags or properties on one note
+pub const MAX_METADATA_ENTRIES: usize = 64;
+
+/// Origin of a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)] // uniffi::Enum
+pub enum NoteSource {
+    /// Created by depositing funds into the pool
+    Deposit,
+    /// Received from another party
+    Received,
+    /// Change returned by one of our own spends
+    Change,
+}
+
+impl NoteMetadata {
+    /// Creates empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the asset
+    pub fn with_asset(mut self, asset: impl Into<String>) -> Self {
+        self.asset = Some(asset.into());
+        self
+    }
+
+    /// Sets the label
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Adds a tag
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Sets the source
+    pub fn with_source(mut self, source: NoteSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Sets the creation time
+    pub fn with_created_at(mut self, created_at: u64) -> Self {
+        self.created_at = Some(created_at);
+        self
+    }
+
+    /// Sets a key/value property
+    pub fn with_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    /// Check the size limits on metadata strings and collections
+    ///
+    /// # Returns
+    /// * `Ok(())` if the metadata can be stored and indexed
+    /// * `Err(SparkError)` describing the first limit exceeded
+    pub fn validate(&self) -> SparkResult<()> {
+        if self.tags.len() > MAX_METADATA_ENTRIES || self.properties.len() > MAX_METADATA_ENTRIES {
+            return Err(SparkError::OperationError {
+                message: format!("Notes can have at most {} tags and {} properties", MAX_METADATA_ENTRIES, MAX_METADATA_ENTRIES),
+            });
+        }
+
+        let strings = self.asset.iter()
+            .chain(self.label.iter())
+            .chain(self.tags.iter())
+            .chain(self.properties.iter().flat_map(|(k, v)| [k, v]));
+        for value in strings {
+            if value.len() > MAX_METADATA_STRING_LENGTH {
+                return Err(SparkError::OperationError {
+                    message: format!(
+                        "Metadata value of {} bytes exceeds the {} byte limit",
+                        value.len(), MAX_METADATA_STRING_LENGTH
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Internal note storage with secret (serialized for persistence)
@@ -60,6 +184,9 @@ struct InternalNoteEntry {
     state: NoteState,
     /// Nullifier if generated
     nullifier: Option<Vec<u8>>,
+    /// Position in the commitment tree once the deposit has been seen on chain
+    #[serde(default)]
+    position: Option<u64>,
 }
 
 impl InternalNoteEntry {
@@ -70,6 +197,7 @@ impl InternalNoteEntry {
             secret: note.secret().as_bytes().to_vec(),
             state,
             nullifier,
+            position: None,
         }
     }
 
@@ -77,6 +205,100 @@ impl InternalNoteEntry {
         let secret = Secret::from(self.secret.clone());
         SparkNote::new(self.value, secret)
     }
+
+    fn to_note_entry(&self) -> NoteEntry {
+        NoteEntry {
+            note: PublicNote {
+                value: self.value,
+                commitment: self.commitment.clone(),
+            },
+            state: self.state.clone(),
+            nullifier: self.nullifier.clone(),
+            position: self.position,
+        }
+    }
+}
+
+/// Open a named tree, mapping sled failures to `SparkError`
+fn open_tree(db: &sled::Db, name: &str) -> SparkResult<sled::Tree> {
+    db.open_tree(name).map_err(|e| SparkError::OperationError {
+        message: format!("Failed to open {} tree: {}", name, e),
+    })
+}
+
+/// Tree holding the spent nullifier set shared by every account in a database
+pub(crate) const SPENT_NULLIFIERS_TREE: &str = "spent_nullifiers";
+/// Tree holding the commitments of the commitment tree, keyed by position
+const COMMITMENT_TREE: &str = "commitment_tree";
+
+/// Spent nullifiers of a manager
+///
+/// In-memory managers keep them in a [`NullifierSet`]; persistent ones use
+/// the database's shared [`SledNullifierStore`], which is never loaded whole.
+#[derive(Debug, Clone)]
+enum SpentSet {
+    Memory(NullifierSet),
+    Sled(SledNullifierStore),
+}
+
+impl SpentSet {
+    fn store(&self) -> &dyn NullifierStore {
+        match self {
+            SpentSet::Memory(set) => set,
+            SpentSet::Sled(store) => store,
+        }
+    }
+
+    fn store_mut(&mut self) -> &mut dyn NullifierStore {
+        match self {
+            SpentSet::Memory(set) => set,
+            SpentSet::Sled(store) => store,
+        }
+    }
+
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        self.store().contains(nullifier)
+    }
+
+    /// Add a nullifier, returning whether it was new
+    fn add(&mut self, nullifier: Nullifier) -> SparkResult<bool> {
+        self.store_mut().insert(nullifier)
+    }
+
+    /// Add many nullifiers, returning how many were new
+    ///
+    /// Persistent sets write them in large batches with a single flush
+    /// instead of one flush per nullifier.
+    fn add_all(&mut self, nullifiers: Vec<Nullifier>) -> SparkResult<usize> {
+        match self {
+            SpentSet::Memory(set) => Ok(nullifiers.into_iter().filter(|n| set.add(*n)).count()),
+            SpentSet::Sled(store) => store.bulk_load(nullifiers),
+        }
+    }
+
+    fn len(&self) -> u64 {
+        self.store().len()
+    }
+
+    fn contains_batch(&self, nullifiers: &[Nullifier]) -> SparkResult<Vec<bool>> {
+        self.store().contains_batch(nullifiers)
+    }
+
+    /// Every nullifier in the set; persistent sets read them one at a time
+    fn iter(&self) -> Box<dyn Iterator<Item = SparkResult<Nullifier>> + '_> {
+        match self {
+            SpentSet::Memory(set) => Box::new(set.iter().map(|n| Ok(*n))),
+            SpentSet::Sled(store) => Box::new(store.iter()),
+        }
+    }
+
+    fn export(&self) -> SparkResult<Vec<Vec<u8>>> {
+        self.iter().map(|n| n.map(|n| n.to_vec())).collect()
+    }
+
+    fn stats(&self) -> crate::nullifier::NullifierSetStats {
+        self.store().stats()
+    }
 }
 
 /// Manager for Spark notes and nullifiers
@@ -87,12 +309,28 @@ impl InternalNoteEntry {
 pub struct NoteManager {
     /// Map of note IDs to note entries (with secrets)
     notes: HashMap<String, InternalNoteEntry>,
+    /// Map of note IDs to their metadata
+    metadata: HashMap<String, NoteMetadata>,
     /// Global set of spent nullifiers (efficient fixed-size keys)
-    spent_nullifiers: NullifierSet,
+    spent_nullifiers: SpentSet,
     /// Optional Tezos client for on-chain synchronization
     pub tezos_client: Option<std::sync::Arc<crate::tezos::TezosClient>>,
     /// Optional sled database for persistence
     db: Option<sled::Db>,
+    /// Subscribers and observers notified of state changes (shared by clones)
+    events: NoteEventBus,
+    /// Account whose trees this manager uses (`None` for the default account)
+    account: Option<String>,
+    /// Key encrypting stored note entries, for encrypted accounts
+    account_key: Option<AccountKey>,
+    /// Hash-chained log of state changes (shared by clones)
+    history: HistoryLog,
+    /// Name recorded as the author of history entries
+    actor: String,
+    /// Every commitment deposited on chain, as far as this wallet has synced
+    commitment_tree: CommitmentTree,
+    /// Groth16 keys used to prove spends
+    spending_keys: Option<std::sync::Arc<SpendingKeys>>,
 }
 
 impl NoteManager {
@@ -100,9 +338,17 @@ impl NoteManager {
     pub fn new() -> Self {
         NoteManager {
             notes: HashMap::new(),
-            spent_nullifiers: NullifierSet::new(),
+            metadata: HashMap::new(),
+            spent_nullifiers: SpentSet::Memory(NullifierSet::new()),
             tezos_client: None,
             db: None,
+            events: NoteEventBus::new(),
+            account: None,
+            account_key: None,
+            history: HistoryLog::in_memory(),
+            actor: history::DEFAULT_ACTOR.to_string(),
+            commitment_tree: CommitmentTree::new(),
+            spending_keys: None,
         }
     }
 
@@ -111,13 +357,35 @@ impl NoteManager {
         let db = sled::open(path).map_err(|e| SparkError::OperationError {
             message: format!("Failed to open database at {}: {}", path, e),
         })?;
+        let spent = SledNullifierStore::open(&db, SPENT_NULLIFIERS_TREE)?;
+        Self::open_in(db, spent, None, None)
+    }
 
+    /// Open one account of an already opened database
+    ///
+    /// Used by [`WalletDb`](crate::account::WalletDb), which validates the
+    /// account and its key first. `spent` is the database's shared spent set.
+    pub(crate) fn open_in(
+        db: sled::Db,
+        spent: SledNullifierStore,
+        account: Option<String>,
+        account_key: Option<AccountKey>,
+    ) -> SparkResult<Self> {
         let mut manager = NoteManager {
             notes: HashMap::new(),
-            spent_nullifiers: NullifierSet::new(),
+            metadata: HashMap::new(),
+            spent_nullifiers: SpentSet::Sled(spent),
             tezos_client: None,
-            db: Some(db),
+            db: Some(db.clone()),
+            events: NoteEventBus::new(),
+            account,
+            account_key,
+            history: HistoryLog::in_memory(),
+            actor: history::DEFAULT_ACTOR.to_string(),
+            commitment_tree: CommitmentTree::new(),
+            spending_keys: None,
         };
+        manager.history = HistoryLog::open(manager.account_tree(&db, HISTORY_TREE)?)?;
 
         manager.load_from_db()?;
         Ok(manager)
@@ -126,10 +394,9 @@ impl NoteManager {
     /// Load state from the database
     fn load_from_db(&mut self) -> SparkResult<()> {
         if let Some(db) = &self.db {
-            // Load notes from the "notes" tree
-            let notes_tree = db.open_tree("notes").map_err(|e| SparkError::OperationError {
-                message: format!("Failed to open notes tree: {}", e),
-            })?;
+            // Load notes from the account's "notes" tree
+            let notes_tree = self.account_tree(db, "notes")?;
+            let mut spent_notes = Vec::new();
 
             for item in notes_tree.iter() {
                 let (id_bytes, entry_bytes) = item.map_err(|e| SparkError::SerializationError {
@@ -140,15 +407,13 @@ impl NoteManager {
                     message: format!("Invalid ID in database: {}", e),
                 })?;
 
-                let entry: InternalNoteEntry = serde_json::from_slice(&entry_bytes).map_err(|e| SparkError::SerializationError {
-                    message: format!("Failed to deserialize note {}: {}", id, e),
-                })?;
+                let entry = self.decode_entry(&id, &entry_bytes)?;
 
                 // If note is spent, ensure its nullifier is in the set
                 if entry.state == NoteState::Spent {
                     if let Some(nullifier_bytes) = &entry.nullifier {
                          if let Ok(n) = Nullifier::from_slice(nullifier_bytes) {
-                             self.spent_nullifiers.add(n);
+                             spent_notes.push(n);
                          }
                     }
                 }
@@ -156,39 +421,178 @@ impl NoteManager {
                 self.notes.insert(id, entry);
             }
 
-            // Load independent spent nullifiers from the "spent_nullifiers" tree
-            let nullifiers_tree = db.open_tree("spent_nullifiers").map_err(|e| SparkError::OperationError {
-                message: format!("Failed to open spent_nullifiers tree: {}", e),
-            })?;
+            // The spent nullifiers shared by all accounts stay on disk. Older
+            // databases only recorded spent notes' nullifiers on the notes
+            // themselves, so copy those into the shared store for other
+            // accounts to see.
+            if let SpentSet::Sled(store) = &self.spent_nullifiers {
+                store.bulk_load(spent_notes)?;
+            }
 
-            for item in nullifiers_tree.iter() {
-                let (nullifier_bytes, _) = item.map_err(|e| SparkError::SerializationError {
+            // Replay the commitment tree in position order
+            for item in self.account_tree(db, COMMITMENT_TREE)?.iter() {
+                let (_, commitment) = item.map_err(|e| SparkError::SerializationError {
                     message: format!("Database read error: {}", e),
                 })?;
+                self.commitment_tree.append(&commitment)?;
+            }
 
-                if let Ok(n) = Nullifier::from_slice(&nullifier_bytes) {
-                    self.spent_nullifiers.add(n);
-                }
+            // Load note metadata from the account's "note_metadata" tree
+            let metadata_tree = self.account_tree(db, "note_metadata")?;
+
+            for item in metadata_tree.iter() {
+                let (id_bytes, meta_bytes) = item.map_err(|e| SparkError::SerializationError {
+                    message: format!("Database read error: {}", e),
+                })?;
+
+                let id = String::from_utf8(id_bytes.to_vec()).map_err(|e| SparkError::SerializationError {
+                    message: format!("Invalid ID in database: {}", e),
+                })?;
+
+                let meta: NoteMetadata = serde_json::from_slice(&meta_bytes).map_err(|e| SparkError::SerializationError {
+                    message: format!("Failed to deserialize metadata for note {}: {}", id, e),
+                })?;
+
+                self.metadata.insert(id, meta);
+            }
+
+            // Databases written before the secondary indexes existed have no
+            // index entries; rebuild them once so queries see every note.
+            if self.account_tree(db, query::VALUE_INDEX_TREE)?.len() != self.notes.len() {
+                self.rebuild_indexes()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Recreate every secondary index tree from the loaded notes
+    fn rebuild_indexes(&self) -> SparkResult<()> {
+        if let Some(db) = &self.db {
+            for name in query::INDEX_TREES {
+                self.account_tree(db, name)?.clear().map_err(|e| SparkError::OperationError {
+                    message: format!("Failed to clear {} tree: {}", name, e),
+                })?;
+            }
+            for id in self.notes.keys() {
+                self.write_index_changes(&[], &self.index_entries(id))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Current secondary index entries for a note (empty if unknown)
+    fn index_entries(&self, id: &str) -> Vec<(&'static str, Vec<u8>)> {
+        match self.notes.get(id) {
+            Some(entry) => query::index_entries(
+                id,
+                &entry.state,
+                entry.value,
+                self.metadata.get(id).unwrap_or(&NoteMetadata::default()),
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    /// Replace the `before` index entries of a note with the `after` entries
+    fn write_index_changes(
+        &self,
+        before: &[(&'static str, Vec<u8>)],
+        after: &[(&'static str, Vec<u8>)],
+    ) -> SparkResult<()> {
+        if let Some(db) = &self.db {
+            for (name, key) in before.iter().filter(|e| !after.contains(e)) {
+                self.account_tree(db, name)?.remove(key).map_err(|e| SparkError::OperationError {
+                    message: format!("Database write error: {}", e),
+                })?;
+            }
+            for (name, key) in after.iter().filter(|e| !before.contains(e)) {
+                self.account_tree(db, name)?.insert(key.as_slice(), &[]).map_err(|e| SparkError::OperationError {
+                    message: format!("Database write error: {}", e),
+                })?;
             }
         }
         Ok(())
     }
 
+    /// Open this account's copy of a per-account tree
+    fn account_tree(&self, db: &sled::Db, base: &str) -> SparkResult<sled::Tree> {
+        open_tree(db, &account_tree_name(self.account.as_deref(), base))
+    }
+
+    /// Serialize a note entry for storage, encrypting it for encrypted accounts
+    fn encode_entry(&self, id: &str, entry: &InternalNoteEntry) -> SparkResult<Vec<u8>> {
+        let bytes = serde_json::to_vec(entry).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize note: {}", e),
+        })?;
+        match &self.account_key {
+            Some(key) => key.seal(&self.entry_aad(id), &bytes),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Inverse of [`encode_entry`](Self::encode_entry)
+    fn decode_entry(&self, id: &str, stored: &[u8]) -> SparkResult<InternalNoteEntry> {
+        let bytes = match &self.account_key {
+            Some(key) => key.open(&self.entry_aad(id), stored)?,
+            None => stored.to_vec(),
+        };
+        serde_json::from_slice(&bytes).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to deserialize note {}: {}", id, e),
+        })
+    }
+
+    /// Binds an encrypted entry to its account and note ID
+    fn entry_aad(&self, id: &str) -> Vec<u8> {
+        format!("{}/{}", self.account.as_deref().unwrap_or(DEFAULT_ACCOUNT), id).into_bytes()
+    }
+
+    /// Whether a nullifier is spent, including spends recorded by other
+    /// accounts sharing the database
+    ///
+    /// Store read errors are returned, never taken as "not spent".
+    fn nullifier_spent(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        self.spent_nullifiers.contains(nullifier)
+    }
+
     /// Save a note to the database
     fn save_note_to_db(&self, id: &str, entry: &InternalNoteEntry) -> SparkResult<()> {
         if let Some(db) = &self.db {
-            let notes_tree = db.open_tree("notes").map_err(|e| SparkError::OperationError {
-                message: format!("Failed to open notes tree: {}", e),
+            let notes_tree = self.account_tree(db, "notes")?;
+            let entry_bytes = self.encode_entry(id, entry)?;
+
+            notes_tree.insert(id, entry_bytes).map_err(|e| SparkError::OperationError {
+                message: format!("Database write error: {}", e),
+            })?;
+            
+            db.flush().map_err(|e| SparkError::OperationError {
+                message: format!("Database flush error: {}", e),
             })?;
+        }
+        Ok(())
+    }
 
-            let entry_bytes = serde_json::to_vec(entry).map_err(|e| SparkError::SerializationError {
-                message: format!("Failed to serialize note: {}", e),
+    /// Save a note's metadata to the database
+    fn save_metadata_to_db(&self, id: &str, meta: &NoteMetadata) -> SparkResult<()> {
+        if let Some(db) = &self.db {
+            let meta_bytes = serde_json::to_vec(meta).map_err(|e| SparkError::SerializationError {
+                message: format!("Failed to serialize note metadata: {}", e),
             })?;
 
-            notes_tree.insert(id, entry_bytes).map_err(|e| SparkError::OperationError {
+            self.account_tree(db, "note_metadata")?.insert(id, meta_bytes).map_err(|e| SparkError::OperationError {
                 message: format!("Database write error: {}", e),
             })?;
-            
+        }
+        Ok(())
+    }
+
+    /// Delete a note and its metadata from the database
+    fn delete_note_from_db(&self, id: &str) -> SparkResult<()> {
+        if let Some(db) = &self.db {
+            for name in ["notes", "note_metadata"] {
+                self.account_tree(db, name)?.remove(id).map_err(|e| SparkError::OperationError {
+                    message: format!("Database write error: {}", e),
+                })?;
+            }
             db.flush().map_err(|e| SparkError::OperationError {
                 message: format!("Database flush error: {}", e),
             })?;
@@ -201,6 +605,57 @@ impl NoteManager {
         self.tezos_client = Some(std::sync::Arc::new(client));
         self
     }
+
+    /// Sets the Groth16 keys used by [`spend_note`](Self::spend_note)
+    pub fn with_spending_keys(mut self, keys: std::sync::Arc<SpendingKeys>) -> Self {
+        self.spending_keys = Some(keys);
+        self
+    }
+
+    /// Sets the name recorded as the author of history entries
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    /// Subscribe to change events
+    ///
+    /// The returned receiver can be awaited from async code or polled from
+    /// synchronous code; see [`NoteEventReceiver`].
+    ///
+    /// # Example
+    /// ```
+    /// use spark_note_sdk::{create_note, NoteManager};
+    /// use spark_note_sdk::events::NoteEvent;
+    /// use spark_note_sdk::secret::Secret;
+    ///
+    /// let mut manager = NoteManager::new();
+    /// let mut events = manager.subscribe();
+    ///
+    /// let note = create_note(1000, Secret::new(vec![1; 16])).unwrap();
+    /// manager.add_note("note1".to_string(), note).unwrap();
+    ///
+    /// assert!(matches!(events.try_recv(), Some(NoteEvent::NoteAdded { value: 1000, .. })));
+    /// ```
+    pub fn subscribe(&self) -> NoteEventReceiver {
+        self.events.subscribe()
+    }
+
+    /// Register a callback invoked for every change event
+    ///
+    /// # Returns
+    /// An ID that can be passed to [`remove_observer`](Self::remove_observer)
+    pub fn add_observer<F>(&self, observer: F) -> ObserverId
+    where
+        F: Fn(&NoteEvent) + Send + Sync + 'static,
+    {
+        self.events.add_observer(observer)
+    }
+
+    /// Unregister a callback added with [`add_observer`](Self::add_observer)
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        self.events.remove_observer(id)
+    }
     
     /// Adds a note to the manager
     ///
@@ -212,18 +667,130 @@ impl NoteManager {
     /// * `Ok(())` if successfully added
     /// * `Err(SparkError)` if ID already exists
     pub fn add_note(&mut self, id: String, note: SparkNote) -> SparkResult<()> {
+        self.add_note_with_metadata(id, note, NoteMetadata::default())
+    }
+
+    /// Adds a note to the manager together with its metadata
+    ///
+    /// # Arguments
+    /// * `id` - Unique identifier for the note
+    /// * `note` - The SparkNote to add
+    /// * `metadata` - Asset, label and other descriptive data for the note
+    ///
+    /// # Returns
+    /// * `Ok(())` if successfully added
+    /// * `Err(SparkError)` if ID already exists
+    pub fn add_note_with_metadata(&mut self, id: String, note: SparkNote, mut metadata: NoteMetadata) -> SparkResult<()> {
         if self.notes.contains_key(&id) {
             return Err(SparkError::OperationError {
                 message: format!("Note with ID '{}' already exists", id),
             });
         }
+        metadata.validate()?;
+        metadata.created_at.get_or_insert_with(crate::clock::unix_timestamp);
         
         let entry = InternalNoteEntry::from_spark_note(&note, NoteState::Unspent, None);
-        self.save_note_to_db(&id, &entry)?;
-        self.notes.insert(id, entry);
+        self.store_note(&id, entry, metadata)?;
+        self.record_history(HistoryAction::NoteAdded { id: id.clone(), value: note.value })?;
+
+        self.events.emit(NoteEvent::NoteAdded {
+            id,
+            value: note.value,
+            commitment: note.commitment.clone(),
+        });
         
         Ok(())
     }
+
+    /// Insert or replace a note and its metadata, in memory, on disk and in the indexes
+    fn store_note(&mut self, id: &str, mut entry: InternalNoteEntry, metadata: NoteMetadata) -> SparkResult<()> {
+        let before = self.index_entries(id);
+        if entry.position.is_none() {
+            entry.position = self.commitment_tree.position(&entry.commitment);
+        }
+        self.save_metadata_to_db(id, &metadata)?;
+        self.save_note_to_db(id, &entry)?;
+        self.notes.insert(id.to_string(), entry);
+        self.metadata.insert(id.to_string(), metadata);
+        self.write_index_changes(&before, &self.index_entries(id))
+    }
+
+    /// Gets the metadata attached to a note
+    ///
+    /// # Returns
+    /// * `Some(NoteMetadata)` if the note exists
+    /// * `None` if not found
+    pub fn get_note_metadata(&self, id: &str) -> Option<NoteMetadata> {
+        if !self.notes.contains_key(id) {
+            return None;
+        }
+        Some(self.metadata.get(id).cloned().unwrap_or_default())
+    }
+
+    /// Replaces the metadata attached to a note
+    ///
+    /// The creation time is kept if `metadata` does not set one.
+    ///
+    /// # Returns
+    /// * `Ok(())` if successfully updated
+    /// * `Err(SparkError)` if note not found
+    pub fn set_note_metadata(&mut self, id: &str, mut metadata: NoteMetadata) -> SparkResult<()> {
+        if !self.notes.contains_key(id) {
+            return Err(SparkError::OperationError {
+                message: format!("Note with ID '{}' not found", id),
+            });
+        }
+        metadata.validate()?;
+        if metadata.created_at.is_none() {
+            metadata.created_at = self.metadata.get(id).and_then(|m| m.created_at);
+        }
+
+        let before = self.index_entries(id);
+        self.save_metadata_to_db(id, &metadata)?;
+        self.metadata.insert(id.to_string(), metadata);
+        self.write_index_changes(&before, &self.index_entries(id))?;
+        self.record_history(HistoryAction::MetadataUpdated { id: id.to_string() })
+    }
+
+    /// Apply a change to a note's current metadata
+    fn update_metadata(&mut self, id: &str, change: impl FnOnce(&mut NoteMetadata)) -> SparkResult<()> {
+        let mut metadata = self.get_note_metadata(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+        change(&mut metadata);
+        self.set_note_metadata(id, metadata)
+    }
+
+    /// Sets or clears the label of a note
+    pub fn set_note_label(&mut self, id: &str, label: Option<String>) -> SparkResult<()> {
+        self.update_metadata(id, |metadata| metadata.label = label)
+    }
+
+    /// Adds a tag to a note (no-op if already present)
+    pub fn add_note_tag(&mut self, id: &str, tag: &str) -> SparkResult<()> {
+        self.update_metadata(id, |metadata| {
+            metadata.tags.insert(tag.to_string());
+        })
+    }
+
+    /// Removes a tag from a note (no-op if absent)
+    pub fn remove_note_tag(&mut self, id: &str, tag: &str) -> SparkResult<()> {
+        self.update_metadata(id, |metadata| {
+            metadata.tags.remove(tag);
+        })
+    }
+
+    /// Sets a key/value property on a note, or removes it when `value` is `None`
+    pub fn set_note_property(&mut self, id: &str, key: &str, value: Option<String>) -> SparkResult<()> {
+        self.update_metadata(id, |metadata| match value {
+            Some(value) => {
+                metadata.properties.insert(key.to_string(), value);
+            }
+            None => {
+                metadata.properties.remove(key);
+            }
+        })
+    }
     
     /// Gets a note by ID (public fields only)
     ///
@@ -234,19 +801,9 @@ impl NoteManager {
     /// * `Some(NoteEntry)` if found
     /// * `None` if not found
     pub fn get_note(&self, id: &str) -> Option<NoteEntry> {
-        self.notes.get(id).map(|entry| NoteEntry {
-            note: PublicNote {
-                value: entry.value,
-                commitment: entry.commitment.clone(),
-            },
-            state: entry.state.clone(),
-            nullifier: entry.nullifier.clone(),
-        })
+        self.notes.get(id).map(InternalNoteEntry::to_note_entry)
     }
     
-    /// Gets a mutable reference to internal note entry
-
-    
     /// Lists all note IDs
     pub fn list_note_ids(&self) -> Vec<String> {
         self.notes.keys().cloned().collect()
@@ -254,16 +811,103 @@ impl NoteManager {
     
     /// Lists all notes (public fields only)
     pub fn list_notes(&self) -> Vec<(String, NoteEntry)> {
-        self.notes.iter().map(|(k, v)| {
-            (k.clone(), NoteEntry {
-                note: PublicNote {
-                    value: v.value,
-                    commitment: v.commitment.clone(),
-                },
-                state: v.state.clone(),
-                nullifier: v.nullifier.clone(),
+        self.notes.iter().map(|(k, v)| (k.clone(), v.to_note_entry())).collect()
+    }
+
+    /// Runs a filtered, sorted and paginated query over the notes
+    ///
+    /// Persistent managers narrow the candidate set through the sled secondary
+    /// indexes before the remaining filters are applied.
+    ///
+    /// # Example
+    /// ```
+    /// use spark_note_sdk::{create_note, NoteManager, NoteState};
+    /// use spark_note_sdk::query::{NoteQuery, NoteSortField, SortOrder};
+    /// use spark_note_sdk::secret::Secret;
+    ///
+    /// let mut manager = NoteManager::new();
+    /// for (i, value) in [300u64, 100, 200].iter().enumerate() {
+    ///     let note = create_note(*value, Secret::new(vec![i as u8 + 1; 16])).unwrap();
+    ///     manager.add_note(format!("note{}", i), note).unwrap();
+    /// }
+    ///
+    /// let page = manager.query(
+    ///     &NoteQuery::new()
+    ///         .with_state(NoteState::Unspent)
+    ///         .sort_by(NoteSortField::Value, SortOrder::Ascending)
+    ///         .with_limit(2),
+    /// ).unwrap();
+    /// assert_eq!(page.total, 3);
+    /// assert_eq!(page.notes[0].1.note.value, 100);
+    /// ```
+    pub fn query(&self, query: &NoteQuery) -> SparkResult<NoteQueryResult> {
+        let candidates: Vec<String> = match (&self.db, query.index_scan()) {
+            (Some(db), Some(scan)) => self.scan_index(db, &scan)?,
+            _ => self.notes.keys().cloned().collect(),
+        };
+
+        let default_metadata = NoteMetadata::default();
+        let matched = candidates
+            .into_iter()
+            .filter_map(|id| {
+                let entry = self.notes.get(&id)?.to_note_entry();
+                let meta = self.metadata.get(&id).unwrap_or(&default_metadata);
+                query.matches(&entry, meta).then_some((id, entry))
+            })
+            .collect();
+
+        Ok(query.sort_and_paginate(matched))
+    }
+
+    /// Collect the note IDs found by a secondary index scan
+    fn scan_index(&self, db: &sled::Db, scan: &IndexScan) -> SparkResult<Vec<String>> {
+        let iter = match scan {
+            IndexScan::Prefix { tree, prefix } => self.account_tree(db, tree)?.scan_prefix(prefix),
+            IndexScan::ValueRange { min, max } => {
+                // Every key of a value falls between <value> and <value + 1>
+                let tree = self.account_tree(db, query::VALUE_INDEX_TREE)?;
+                match max.checked_add(1) {
+                    Some(end) => tree.range(min.to_be_bytes()..end.to_be_bytes()),
+                    None => tree.range(min.to_be_bytes()..),
+                }
+            }
+        };
+
+        let mut ids = Vec::new();
+        for item in iter {
+            let (key, _) = item.map_err(|e| SparkError::SerializationError {
+                message: format!("Database read error: {}", e),
+            })?;
+            if let Some(id) = scan.id_from_key(&key) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Total value of all notes that have not been spent
+    pub fn balance(&self) -> u64 {
+        self.notes
+            .values()
+            .filter(|entry| entry.state != NoteState::Spent)
+            .fold(0u64, |total, entry| total.saturating_add(entry.value))
+    }
+
+    /// Total value of the notes that can be spent right now
+    ///
+    /// Excludes notes whose nullifier already appears in the spent set,
+    /// even if the note itself has not been marked as spent yet, and notes
+    /// whose nullifier cannot be checked.
+    pub fn spendable_balance(&self) -> u64 {
+        self.notes
+            .values()
+            .filter(|entry| entry.state == NoteState::Unspent)
+            .filter(|entry| {
+                entry.nullifier.as_ref()
+                    .map(|n| !self.is_nullifier_spent(n))
+                    .unwrap_or(true)
             })
-        }).collect()
+            .fold(0u64, |total, entry| total.saturating_add(entry.value))
     }
     
     /// Removes a note by ID
@@ -272,16 +916,39 @@ impl NoteManager {
     /// * `id` - The note ID to remove
     ///
     /// # Returns
-    /// * `Ok(Option<NoteEntry>)` - The removed note if it existed
+    /// The removed note if it existed. The note leaves the manager even if it
+    /// cannot be deleted from the database; use
+    /// [`try_remove_note`](Self::try_remove_note) to get that error.
     pub fn remove_note(&mut self, id: &str) -> Option<NoteEntry> {
-        self.notes.remove(id).map(|entry| NoteEntry {
-            note: PublicNote {
-                value: entry.value,
-                commitment: entry.commitment.clone(),
-            },
-            state: entry.state,
-            nullifier: entry.nullifier,
-        })
+        let entry = self.get_note(id)?;
+        let _ = self.try_remove_note(id);
+        Some(entry)
+    }
+
+    /// Removes a note by ID, returning database errors
+    ///
+    /// # Arguments
+    /// * `id` - The note ID to remove
+    ///
+    /// # Returns
+    /// * `Ok(Option<NoteEntry>)` - The removed note if it existed
+    /// * `Err(SparkError)` if the note could not be deleted from the database
+    pub fn try_remove_note(&mut self, id: &str) -> SparkResult<Option<NoteEntry>> {
+        let before = self.index_entries(id);
+        let Some(entry) = self.notes.remove(id) else {
+            return Ok(None);
+        };
+        self.metadata.remove(id);
+        self.delete_note_from_db(id)?;
+        self.write_index_changes(&before, &[])?;
+        self.record_history(HistoryAction::NoteRemoved {
+            id: id.to_string(),
+            value: entry.value,
+            state: entry.state.clone(),
+        })?;
+
+        self.events.emit(NoteEvent::NoteRemoved { id: id.to_string() });
+        Ok(Some(entry.to_note_entry()))
     }
     
     /// Generates a nullifier for a note
@@ -308,6 +975,15 @@ impl NoteManager {
         // Save to DB
         let entry_to_save = note_entry.clone();
         self.save_note_to_db(id, &entry_to_save)?;
+        self.record_history(HistoryAction::NullifierGenerated {
+            id: id.to_string(),
+            nullifier: hex::encode(nullifier.as_bytes()),
+        })?;
+
+        self.events.emit(NoteEvent::NullifierGenerated {
+            id: id.to_string(),
+            nullifier: nullifier.to_vec(),
+        });
         
         Ok(nullifier.to_vec())
     }
@@ -321,7 +997,8 @@ impl NoteManager {
     /// * `Ok(())` if successfully marked
     /// * `Err(SparkError)` if note not found, nullifier not generated, or already spent
     pub fn mark_note_as_spent(&mut self, id: &str) -> SparkResult<()> {
-        let note_entry = self.notes.get_mut(id)
+        let before = self.index_entries(id);
+        let note_entry = self.notes.get(id)
             .ok_or_else(|| SparkError::OperationError {
                 message: format!("Note with ID '{}' not found", id),
             })?;
@@ -334,21 +1011,37 @@ impl NoteManager {
         // Convert to Nullifier type for efficient storage
         let nullifier = Nullifier::from_slice(nullifier_bytes)?;
         
-        // Check if already spent
-        if self.spent_nullifiers.contains(&nullifier) {
+        // Check if already spent, by this or any other account
+        if self.nullifier_spent(&nullifier)? {
             return Err(SparkError::nullifier_error(
                 crate::error::NullifierErrorCode::AlreadySpent,
                 format!("Nullifier for note '{}' is already spent", id),
             ));
         }
         
-        // Add to spent set
-        self.spent_nullifiers.add(nullifier);
-        note_entry.state = NoteState::Spent;
+        self.record_note_spent(id, nullifier, before)
+    }
+
+    /// Move a note to `Spent` and add its nullifier to the spent set
+    fn record_note_spent(&mut self, id: &str, nullifier: Nullifier, before: Vec<(&'static str, Vec<u8>)>) -> SparkResult<()> {
+        self.spent_nullifiers.add(nullifier)?;
+        let note_entry = self.notes.get_mut(id).expect("caller checked the note exists");
+        let previous_state = std::mem::replace(&mut note_entry.state, NoteState::Spent);
         
         // Save to DB
         let entry_to_save = note_entry.clone();
         self.save_note_to_db(id, &entry_to_save)?;
+        self.write_index_changes(&before, &self.index_entries(id))?;
+        self.record_history(HistoryAction::NoteSpent {
+            id: id.to_string(),
+            nullifier: hex::encode(nullifier.as_bytes()),
+            previous_state,
+        })?;
+
+        self.events.emit(NoteEvent::NoteSpent {
+            id: id.to_string(),
+            nullifier: nullifier.to_vec(),
+        });
         
         Ok(())
     }
@@ -364,61 +1057,51 @@ impl NoteManager {
     pub fn add_spent_nullifier(&mut self, nullifier: &[u8]) -> SparkResult<()> {
         let n = Nullifier::from_slice(nullifier)?;
         
-        if self.spent_nullifiers.contains(&n) {
+        if self.nullifier_spent(&n)? {
             return Err(SparkError::nullifier_error(
                 crate::error::NullifierErrorCode::AlreadySpent,
                 "Nullifier is already spent".to_string(),
             ));
         }
         
-        self.spent_nullifiers.add(n);
-        
-        // Save spent nullifier set (as a single entry or per-nullifier)
-        // For simplicity, we'll store them in a "nullifiers" tree
-        self.save_spent_nullifier_to_db(nullifier)?;
+        self.spent_nullifiers.add(n)?;
+        self.record_history(HistoryAction::NullifierSpent { nullifier: hex::encode(nullifier) })?;
         
         Ok(())
     }
 
-    fn save_spent_nullifier_to_db(&self, nullifier: &[u8]) -> SparkResult<()> {
-         if let Some(db) = &self.db {
-            let tree = db.open_tree("spent_nullifiers").map_err(|e| SparkError::OperationError {
-                message: format!("Failed to open spent_nullifiers tree: {}", e),
-            })?;
-            
-            tree.insert(nullifier, b"").map_err(|e| SparkError::OperationError {
-                message: format!("Database write error: {}", e),
-            })?;
-            db.flush().map_err(|e| SparkError::OperationError {
-                message: format!("Database flush error: {}", e),
-            })?;
-        }
-        Ok(())
-    }
-    
     /// Check if a nullifier has been spent
     ///
     /// # Arguments
     /// * `nullifier` - The nullifier bytes to check
     ///
     /// # Returns
-    /// `true` if the nullifier is in the spent set, `false` otherwise
+    /// `true` if the nullifier is in the spent set, `false` otherwise. If the
+    /// spent set cannot be read the nullifier counts as spent; use
+    /// [`try_is_nullifier_spent`](Self::try_is_nullifier_spent) to get the error.
     pub fn is_nullifier_spent(&self, nullifier: &[u8]) -> bool {
-        self.spent_nullifiers.contains_slice(nullifier)
+        self.try_is_nullifier_spent(nullifier).unwrap_or(true)
+    }
+
+    /// Check if a nullifier has been spent, returning store read errors
+    ///
+    /// # Returns
+    /// * `Ok(true)` if the nullifier is in the spent set
+    /// * `Ok(false)` if it is not, or is not a valid nullifier
+    /// * `Err(SparkError)` if the spent set cannot be read
+    pub fn try_is_nullifier_spent(&self, nullifier: &[u8]) -> SparkResult<bool> {
+        match Nullifier::from_slice(nullifier) {
+            Ok(n) => self.nullifier_spent(&n),
+            Err(_) => Ok(false),
+        }
     }
     
     /// Gets statistics about the nullifier set
+    ///
+    /// For a persistent manager the nullifiers stay on disk, and the memory
+    /// usage is that of the store's filter.
     pub fn get_nullifier_stats(&self) -> crate::nullifier::NullifierSetStats {
-        use crate::nullifier::NullifierSetStats;
-        let count = self.spent_nullifiers.len() as u64;
-        // Estimate: each nullifier is 32 bytes + overhead
-        // HashSet overhead is roughly 8 bytes per entry
-        let memory_usage_bytes = count * (32 + 8);
-        
-        NullifierSetStats {
-            count,
-            memory_usage_bytes,
-        }
+        self.spent_nullifiers.stats()
     }
     
     /// Gets the number of notes
@@ -428,7 +1111,7 @@ impl NoteManager {
     
     /// Gets the number of spent nullifiers
     pub fn spent_nullifier_count(&self) -> usize {
-        self.spent_nullifiers.len()
+        self.spent_nullifiers.len() as usize
     }
     
     /// Export spent nullifiers as Vec<Vec<u8>> (for compatibility)
@@ -437,38 +1120,493 @@ impl NoteManager {
     /// This method is kept for backward compatibility. Consider using
     /// `NullifierSet` directly for better performance.
     #[deprecated(note = "Use NullifierSet directly. This method will be removed in v2.0")]
-    pub fn get_spent_nullifiers(&self) -> Vec<Vec<u8>> {
+    pub fn get_spent_nullifiers(&self) -> SparkResult<Vec<Vec<u8>>> {
         self.spent_nullifiers.export()
     }
 
+    /// Export every note, its secret, state and metadata, and the spent set
+    /// as a password-encrypted backup
+    ///
+    /// # Returns
+    /// The backup as a JSON string (see [`WalletBackup`])
+    pub fn export_backup(&self, password: &str) -> SparkResult<String> {
+        self.export_backup_with(password, KdfParams::recommended()?)
+    }
+
+    /// Export a backup using custom key derivation parameters
+    pub fn export_backup_with(&self, password: &str, kdf: KdfParams) -> SparkResult<String> {
+        let mut ids: Vec<&String> = self.notes.keys().collect();
+        ids.sort();
+
+        let notes = ids
+            .into_iter()
+            .map(|id| {
+                let entry = &self.notes[id];
+                BackupNote {
+                    id: id.clone(),
+                    value: entry.value,
+                    commitment: hex::encode(&entry.commitment),
+                    secret: hex::encode(&entry.secret),
+                    state: entry.state.clone(),
+                    nullifier: entry.nullifier.as_ref().map(hex::encode),
+                    metadata: self.metadata.get(id).cloned().unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        let mut spent_nullifiers: Vec<String> = self.spent_nullifiers.export()?.iter().map(hex::encode).collect();
+        spent_nullifiers.sort();
+
+        let payload = BackupPayload { notes, spent_nullifiers };
+        WalletBackup::seal(&payload, password, kdf)?.to_json()
+    }
+
+    /// Restore a backup created by [`export_backup`](Self::export_backup)
+    ///
+    /// Every note is checked against its commitment and nullifier, and its
+    /// metadata against the usual size limits, before anything is written. A backup note whose ID already holds the same
+    /// note is merged (a spent state on either side wins); one whose ID holds
+    /// a different note is handled according to `policy`. Spent nullifiers
+    /// are added to the existing spent set.
+    ///
+    /// # Returns
+    /// * `Ok(RestoreReport)` describing what was restored, merged or skipped
+    /// * `Err(SparkError)` if the password is wrong, the backup was tampered
+    ///   with, or a conflict occurred under `ConflictPolicy::Abort`; the
+    ///   wallet is left unchanged in these cases
+    pub fn restore_backup(&mut self, backup: &str, password: &str, policy: ConflictPolicy) -> SparkResult<RestoreReport> {
+        let payload = WalletBackup::from_json(backup)?.open(password)?;
+
+        // Validate everything before touching the wallet
+        let mut spent = Vec::with_capacity(payload.spent_nullifiers.len());
+        for hex_nullifier in &payload.spent_nullifiers {
+            spent.push(Nullifier::from_slice(&decode_backup_hex(hex_nullifier)?)?);
+        }
+
+        let mut restored = Vec::with_capacity(payload.notes.len());
+        for backup_note in &payload.notes {
+            let entry = Self::validate_backup_note(backup_note)?;
+            if let Some(n) = &entry.nullifier {
+                if entry.state == NoteState::Spent {
+                    spent.push(Nullifier::from_slice(n)?);
+                }
+            }
+            restored.push((backup_note.id.clone(), entry, backup_note.metadata.clone()));
+        }
+
+        let mut report = RestoreReport::default();
+        let mut writes = Vec::new();
+        for (id, entry, metadata) in restored {
+            match self.notes.get(&id) {
+                Some(existing) if existing.commitment == entry.commitment => {
+                    let mut merged = existing.clone();
+                    if entry.state == NoteState::Spent {
+                        merged.state = NoteState::Spent;
+                    }
+                    if merged.nullifier.is_none() {
+                        merged.nullifier = entry.nullifier;
+                    }
+                    let local_metadata = self.metadata.get(&id).cloned().unwrap_or_default();
+                    let metadata = if local_metadata == NoteMetadata::default() { metadata } else { local_metadata };
+                    report.merged.push(id.clone());
+                    writes.push((id, merged, metadata, false));
+                }
+                Some(_) => match policy {
+                    ConflictPolicy::Abort => {
+                        return Err(SparkError::OperationError {
+                            message: format!("Note with ID '{}' already exists with a different commitment", id),
+                        });
+                    }
+                    ConflictPolicy::KeepExisting => report.skipped.push(id),
+                    ConflictPolicy::Overwrite => {
+                        report.overwritten.push(id.clone());
+                        writes.push((id, entry, metadata, true));
+                    }
+                },
+                None if self.notes.values().any(|e| e.commitment == entry.commitment) => {
+                    report.skipped.push(id);
+                }
+                None => {
+                    report.restored.push(id.clone());
+                    writes.push((id, entry, metadata, true));
+                }
+            }
+        }
+
+        for (id, entry, metadata, is_new) in writes {
+            let (value, commitment) = (entry.value, entry.commitment.clone());
+            self.store_note(&id, entry, metadata)?;
+            if is_new {
+                self.events.emit(NoteEvent::NoteAdded { id, value, commitment });
+            }
+        }
+
+        for nullifier in spent {
+            if self.spent_nullifiers.add(nullifier)? {
+                report.nullifiers_imported += 1;
+            }
+        }
+
+        self.record_history(HistoryAction::BackupRestored {
+            restored: report.restored.len(),
+            merged: report.merged.len(),
+            overwritten: report.overwritten.len(),
+        })?;
+        Ok(report)
+    }
+
+    /// Rebuild a note from a backup and check that it is self-consistent
+    ///
+    /// The note's metadata must also pass [`NoteMetadata::validate`], the same
+    /// limits enforced when metadata is set directly.
+    fn validate_backup_note(backup_note: &BackupNote) -> SparkResult<InternalNoteEntry> {
+        backup_note.metadata.validate().map_err(|e| SparkError::OperationError {
+            message: format!("Backup note '{}' has invalid metadata: {}", backup_note.id, e),
+        })?;
+
+        let secret = Secret::from(decode_backup_hex(&backup_note.secret)?);
+        let note = SparkNote::new(backup_note.value, secret.clone())?;
+
+        if !crate::crypto::constant_time_eq(&note.commitment, &decode_backup_hex(&backup_note.commitment)?) {
+            return Err(SparkError::OperationError {
+                message: format!("Backup note '{}' does not match its commitment", backup_note.id),
+            });
+        }
+
+        let nullifier = match &backup_note.nullifier {
+            Some(hex_nullifier) => {
+                let nullifier = decode_backup_hex(hex_nullifier)?;
+                if nullifier != generate_nullifier(&note, &secret).to_vec() {
+                    return Err(SparkError::OperationError {
+                        message: format!("Backup note '{}' does not match its nullifier", backup_note.id),
+                    });
+                }
+                Some(nullifier)
+            }
+            None if backup_note.state == NoteState::Spent => {
+                return Err(SparkError::OperationError {
+                    message: format!("Backup note '{}' is spent but has no nullifier", backup_note.id),
+                });
+            }
+            None => None,
+        };
+
+        Ok(InternalNoteEntry::from_spark_note(&note, backup_note.state.clone(), nullifier))
+    }
+
     /// Sync a deposit to Tezos
     pub async fn sync_deposit_to_tezos(&self, id: &str, secret_key: &str) -> SparkResult<crate::tezos::TezosOperationResult> {
-        let entry = self.get_note(id).ok_or_else(|| SparkError::OperationError {
-            message: format!("Note with ID '{}' not found", id),
+        let (client, note, proof) = self.prepare_deposit(id)?;
+        let result = client.deposit(&note, &proof, secret_key).await?;
+        self.record_history(HistoryAction::DepositSynced {
+            id: id.to_string(),
+            operation_hash: result.operation_hash.clone(),
         })?;
-        
-        // This would require a real ZK proof attached to the note
-        // For POC, we use a dummy proof
-        let dummy_proof = vec![0u8; 128];
-        
-        let client = self.tezos_client.as_ref().ok_or_else(|| SparkError::tezos_error("Tezos client not configured"))?;
-        client.deposit(&entry.note, &dummy_proof, secret_key).await
+        Ok(result)
     }
 
     /// Sync a spend to Tezos
+    ///
+    /// The spending proof is generated with the keys set by
+    /// [`with_spending_keys`](Self::with_spending_keys) against the note's
+    /// commitment tree witness, so the note must have a nullifier and the tree
+    /// must be synced. The note is not marked as spent; use
+    /// [`spend_note`](Self::spend_note) for that.
     pub async fn sync_spend_to_tezos(&self, id: &str, secret_key: &str) -> SparkResult<crate::tezos::TezosOperationResult> {
-         let entry = self.get_note(id).ok_or_else(|| SparkError::OperationError {
+        let prepared = self.prepare_spend(id)?;
+        let result = prepared.submit(secret_key).await?;
+        self.record_history(HistoryAction::SpendSynced {
+            id: id.to_string(),
+            operation_hash: result.operation_hash.clone(),
+        })?;
+        Ok(result)
+    }
+
+    /// Sync several deposits and spends to Tezos as one operation
+    ///
+    /// Each spend is proved as in [`sync_spend_to_tezos`](Self::sync_spend_to_tezos).
+    /// Deposits come first, then spends, in the order given. The history gets
+    /// one entry per note, all with the same operation hash; use
+    /// [`BatchResult::note_results`](crate::tezos_batch::BatchResult::note_results)
+    /// to match each note with the result of its call.
+    pub async fn sync_batch_to_tezos(&self, deposit_ids: &[&str], spend_ids: &[&str], secret_key: &str) -> SparkResult<crate::tezos_batch::BatchResult> {
+        let result = self.prepare_batch(deposit_ids, spend_ids)?.submit(secret_key).await?;
+        self.record_batch(deposit_ids, spend_ids, &result.operation.operation_hash)?;
+        Ok(result)
+    }
+
+    /// The configured Tezos client
+    pub(crate) fn client(&self) -> SparkResult<std::sync::Arc<crate::tezos::TezosClient>> {
+        self.tezos_client.clone().ok_or_else(|| SparkError::tezos_error("Tezos client not configured"))
+    }
+
+    /// Collect everything needed to submit a deposit, so the network call
+    /// can run without borrowing the manager
+    ///
+    /// Fails if the note's value and secret no longer produce its stored
+    /// commitment, since the deposit proof could not open what gets sent.
+    pub(crate) fn prepare_deposit(&self, id: &str) -> SparkResult<(std::sync::Arc<crate::tezos::TezosClient>, PublicNote, Vec<u8>)> {
+        let entry = self.notes.get(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+
+        // The deposit reveals the value; prove the commitment hides exactly that.
+        // The proof opens the recomputed commitment, so it must be the one sent.
+        let note = entry.to_spark_note()?;
+        if !crate::crypto::constant_time_eq(&note.commitment, &entry.commitment) {
+            return Err(SparkError::OperationError {
+                message: format!("Note '{}' does not match its stored commitment", id),
+            });
+        }
+        let proof = note.prove_deposit()?.to_bytes();
+
+        Ok((self.client()?, entry.to_note_entry().note, proof))
+    }
+
+    /// Collect the deposits and spends of a batch, so the spending proofs and
+    /// the network call can run without borrowing the manager
+    ///
+    /// Fails if any note fails [`prepare_deposit`](Self::prepare_deposit) or
+    /// [`prepare_spend`](Self::prepare_spend), or appears twice.
+    pub(crate) fn prepare_batch(&self, deposit_ids: &[&str], spend_ids: &[&str]) -> SparkResult<PreparedBatch> {
+        let mut seen = std::collections::HashSet::new();
+        if let Some(id) = deposit_ids.iter().chain(spend_ids).find(|id| !seen.insert(**id)) {
+            return Err(SparkError::OperationError {
+                message: format!("Note '{}' appears twice in the batch", id),
+            });
+        }
+
+        let mut deposits = crate::tezos_batch::OperationBatch::new();
+        for id in deposit_ids {
+            let (_, note, proof) = self.prepare_deposit(id)?;
+            deposits = deposits.with_deposit(id, note, proof);
+        }
+        let spends = spend_ids
+            .iter()
+            .map(|id| Ok((id.to_string(), self.prepare_spend(id)?)))
+            .collect::<SparkResult<Vec<_>>>()?;
+        Ok(PreparedBatch { client: self.client()?, deposits, spends })
+    }
+
+    /// Record the history of an injected batch
+    pub(crate) fn record_batch(&self, deposit_ids: &[&str], spend_ids: &[&str], operation_hash: &str) -> SparkResult<()> {
+        for id in deposit_ids {
+            self.record_history(HistoryAction::DepositSynced {
+                id: id.to_string(),
+                operation_hash: operation_hash.to_string(),
+            })?;
+        }
+        for id in spend_ids {
+            self.record_history(HistoryAction::SpendSynced {
+                id: id.to_string(),
+                operation_hash: operation_hash.to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Collect everything needed to prove and submit a spend, so the proof
+    /// and the network call can run without borrowing the manager
+    ///
+    /// Fails if the spending keys are not configured, the note has no
+    /// nullifier or commitment tree witness, or it is already spent or its
+    /// nullifier is in the spent set.
+    pub(crate) fn prepare_spend(&self, id: &str) -> SparkResult<PreparedSpend> {
+        let nullifier = self.check_spendable(id)?;
+        let keys = self.spending_keys.clone().ok_or_else(|| SparkError::OperationError {
+            message: "Spending keys not configured".to_string(),
+        })?;
+        let entry = self.notes.get(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+        let note = entry.to_spark_note()?;
+        let amount = entry.value;
+        let witness = self.note_witness(id)?;
+
+        Ok(PreparedSpend { client: self.client()?, keys, note, nullifier, witness, amount })
+    }
+
+    /// Check that a note can be spent and return its nullifier
+    fn check_spendable(&self, id: &str) -> SparkResult<Vec<u8>> {
+        let entry = self.get_note(id).ok_or_else(|| SparkError::OperationError {
             message: format!("Note with ID '{}' not found", id),
         })?;
         
-        let nullifier = entry.nullifier.as_ref().ok_or_else(|| SparkError::OperationError {
+        let nullifier = entry.nullifier.ok_or_else(|| SparkError::OperationError {
             message: "Nullifier not generated for note".to_string(),
         })?;
-        
-        let dummy_proof = vec![0u8; 128];
-        
-        let client = self.tezos_client.as_ref().ok_or_else(|| SparkError::tezos_error("Tezos client not configured"))?;
-        client.spend(nullifier, &dummy_proof, secret_key).await
+
+        if entry.state == NoteState::PendingSpend {
+            return Err(SparkError::OperationError {
+                message: format!("Note '{}' already has a spend in progress", id),
+            });
+        }
+        if entry.state == NoteState::Spent || self.try_is_nullifier_spent(&nullifier)? {
+            return Err(SparkError::nullifier_error(
+                crate::error::NullifierErrorCode::AlreadySpent,
+                format!("Nullifier for note '{}' is already spent", id),
+            ));
+        }
+        Ok(nullifier)
+    }
+
+    /// Spend a whole note with a real spending proof
+    ///
+    /// The note is moved to `PendingSpend`, its nullifier and commitment tree
+    /// witness are computed, a Groth16 proof is generated with the keys set by
+    /// [`with_spending_keys`](Self::with_spending_keys) and checked, and the
+    /// spend is injected through the Tezos client. On success the note is
+    /// marked as spent; on failure it returns to `Unspent`. Once the spend is
+    /// injected its receipt is returned even if the note cannot be marked, in
+    /// which case it stays `PendingSpend` until the nullifiers are reconciled.
+    ///
+    /// The whole note is nullified on chain, so `amount` must be the note
+    /// value; partial spends are rejected before anything is proved.
+    ///
+    /// # Arguments
+    /// * `id` - The note to spend
+    /// * `amount` - Amount to spend, which must equal the note value
+    /// * `secret_key` - Tezos key signing the operation
+    ///
+    /// # Returns
+    /// * `Ok(SpendReceipt)` describing the injected spend
+    /// * `Err(SparkError)` if the note cannot be spent or the spend failed
+    pub async fn spend_note(&mut self, id: &str, amount: u64, secret_key: &str) -> SparkResult<SpendReceipt> {
+        let prepared = self.begin_spend(id, amount)?;
+        let result = prepared.submit(secret_key).await;
+        self.finish_spend(id, prepared, result)
+    }
+
+    /// Validate a spend, move the note to `PendingSpend` and collect what is
+    /// needed to prove and submit it
+    pub(crate) fn begin_spend(&mut self, id: &str, amount: u64) -> SparkResult<PreparedSpend> {
+        let client = self.client()?;
+        let keys = self.spending_keys.clone().ok_or_else(|| SparkError::OperationError {
+            message: "Spending keys not configured".to_string(),
+        })?;
+        let entry = self.notes.get(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+
+        match entry.state {
+            NoteState::Unspent => {}
+            NoteState::PendingSpend => {
+                return Err(SparkError::OperationError {
+                    message: format!("Note '{}' already has a spend in progress", id),
+                });
+            }
+            NoteState::Spent => {
+                return Err(SparkError::nullifier_error(
+                    crate::error::NullifierErrorCode::AlreadySpent,
+                    format!("Note '{}' is already spent", id),
+                ));
+            }
+        }
+        if amount != entry.value {
+            return Err(SparkError::invalid_value(
+                crate::error::ValueErrorCode::Invalid,
+                format!(
+                    "Cannot spend {} from note '{}' worth {}: a spend nullifies the whole note",
+                    amount, id, entry.value
+                ),
+            ));
+        }
+
+        let note = entry.to_spark_note()?;
+        let nullifier = match entry.nullifier.clone() {
+            Some(nullifier) => nullifier,
+            None => self.generate_nullifier_for_note(id, note.secret_bytes().to_vec())?,
+        };
+        if self.try_is_nullifier_spent(&nullifier)? {
+            return Err(SparkError::nullifier_error(
+                crate::error::NullifierErrorCode::AlreadySpent,
+                format!("Nullifier for note '{}' is already spent", id),
+            ));
+        }
+        let witness = self.note_witness(id)?;
+
+        self.set_note_state(id, NoteState::PendingSpend)?;
+        Ok(PreparedSpend { client, keys, note, nullifier, witness, amount })
+    }
+
+    /// Record the outcome of a spend started with [`begin_spend`](Self::begin_spend)
+    pub(crate) fn finish_spend(
+        &mut self,
+        id: &str,
+        prepared: PreparedSpend,
+        result: SparkResult<crate::tezos::TezosOperationResult>,
+    ) -> SparkResult<SpendReceipt> {
+        let operation = match result {
+            Ok(operation) => operation,
+            Err(e) => {
+                self.set_note_state(id, NoteState::Unspent)?;
+                return Err(e);
+            }
+        };
+
+        // The spend is on its way to the chain, so the receipt is returned
+        // even if recording it fails; the note then stays `PendingSpend`
+        // until a reconcile finds its nullifier on chain
+        let _ = self.record_injected_spend(id, &prepared.nullifier, &operation.operation_hash);
+
+        Ok(SpendReceipt {
+            operation,
+            nullifier: prepared.nullifier,
+            root: prepared.witness.root,
+            amount: prepared.amount,
+        })
+    }
+
+    /// Mark a note as spent once a spend of it has been injected, and record
+    /// the operation in the history
+    ///
+    /// A reconcile may have marked the note as spent from the chain in the
+    /// meantime; with the same nullifier, that is the expected outcome.
+    pub(crate) fn record_injected_spend(&mut self, id: &str, nullifier: &[u8], operation_hash: &str) -> SparkResult<()> {
+        let entry = self.notes.get(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+        if entry.nullifier.as_deref() != Some(nullifier) {
+            return Err(SparkError::OperationError {
+                message: format!("Note '{}' was spent with another nullifier", id),
+            });
+        }
+        if entry.state != NoteState::Spent {
+            let before = self.index_entries(id);
+            self.record_note_spent(id, Nullifier::from_slice(nullifier)?, before)?;
+        }
+        self.record_history(HistoryAction::SpendSynced {
+            id: id.to_string(),
+            operation_hash: operation_hash.to_string(),
+        })
+    }
+
+    /// Return a note stuck in `PendingSpend` to `Unspent`
+    ///
+    /// Only needed if the process stopped in the middle of a spend. Check
+    /// that the spend did not reach the chain before calling this.
+    pub fn abandon_pending_spend(&mut self, id: &str) -> SparkResult<()> {
+        match self.notes.get(id).map(|entry| &entry.state) {
+            Some(NoteState::PendingSpend) => self.set_note_state(id, NoteState::Unspent),
+            Some(_) => Err(SparkError::OperationError {
+                message: format!("Note '{}' has no spend in progress", id),
+            }),
+            None => Err(SparkError::OperationError {
+                message: format!("Note with ID '{}' not found", id),
+            }),
+        }
+    }
+
+    /// Change the state of a note, keeping the database and indexes in step
+    fn set_note_state(&mut self, id: &str, state: NoteState) -> SparkResult<()> {
+        let before = self.index_entries(id);
+        let entry = self.notes.get_mut(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+        entry.state = state;
+        let entry = entry.clone();
+        self.save_note_to_db(id, &entry)?;
+        self.write_index_changes(&before, &self.index_entries(id))
     }
 
     /// Scan the Tezos blockchain for deposit events and synchronize state
@@ -477,9 +1615,105 @@ impl NoteManager {
     /// and for each, attempts to identify if it belongs to the user.
     /// In a real implementation, this would use trial decryption of an on-chain payload.
     pub async fn scan(&mut self, _viewing_key: &[u8]) -> SparkResult<usize> {
-        let client = self.tezos_client.as_ref().ok_or_else(|| SparkError::tezos_error("Tezos client not configured"))?;
-        
-        let commitments = client.fetch_deposit_events().await?;
+        let commitments = self.client()?.fetch_deposit_events().await?;
+        self.apply_scanned_commitments(commitments)
+    }
+
+    /// Bring the commitment tree up to date with the chain
+    ///
+    /// # Arguments
+    /// * `commitments` - Every commitment deposited on chain, in deposit order
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of commitments appended to the tree
+    /// * `Err(SparkError)` if the chain disagrees with commitments already in
+    ///   the tree, or the tree is full
+    pub fn sync_commitment_tree(&mut self, commitments: &[Vec<u8>]) -> SparkResult<usize> {
+        for (position, commitment) in commitments.iter().enumerate().take(self.commitment_tree.len() as usize) {
+            if self.commitment_tree.commitment(position as u64) != Some(commitment.as_slice()) {
+                return Err(SparkError::OperationError {
+                    message: format!("Chain commitments diverge from the local tree at position {}", position),
+                });
+            }
+        }
+
+        let new = &commitments[commitments.len().min(self.commitment_tree.len() as usize)..];
+        for commitment in new {
+            let position = self.commitment_tree.append(commitment)?;
+            if let Some(db) = &self.db {
+                self.account_tree(db, COMMITMENT_TREE)?
+                    .insert(position.to_be_bytes(), commitment.as_slice())
+                    .map_err(|e| SparkError::OperationError {
+                        message: format!("Database write error: {}", e),
+                    })?;
+            }
+        }
+
+        // Record the leaf position of owned notes that just appeared
+        let mut placed: Vec<(String, u64)> = self.notes.iter()
+            .filter(|(_, entry)| entry.position.is_none())
+            .filter_map(|(id, entry)| self.commitment_tree.position(&entry.commitment).map(|p| (id.clone(), p)))
+            .collect();
+        placed.sort();
+        for (id, position) in &placed {
+            let entry = self.notes.get_mut(id).expect("id taken from notes");
+            entry.position = Some(*position);
+            let entry = entry.clone();
+            self.save_note_to_db(id, &entry)?;
+        }
+
+        if let Some(db) = &self.db {
+            db.flush().map_err(|e| SparkError::OperationError {
+                message: format!("Database flush error: {}", e),
+            })?;
+        }
+
+        if !new.is_empty() {
+            self.record_history(HistoryAction::CommitmentsSynced {
+                appended: new.len(),
+                tree_size: self.commitment_tree.len(),
+            })?;
+        }
+        for (id, position) in placed {
+            self.record_history(HistoryAction::NoteFoundOnChain { id, position })?;
+        }
+        Ok(new.len())
+    }
+
+    /// The wallet's copy of the on-chain commitment tree
+    pub fn commitment_tree(&self) -> &CommitmentTree {
+        &self.commitment_tree
+    }
+
+    /// Root and authentication path proving an owned note is in the tree
+    ///
+    /// The witness is computed against the current root, so it reflects every
+    /// commitment synced so far and can be passed to
+    /// [`SparkNote::prove_spending`].
+    ///
+    /// # Returns
+    /// * `Ok(MerkleWitness)` for a note whose deposit has been synced
+    /// * `Err(SparkError)` if the note is unknown or not yet in the tree
+    pub fn note_witness(&self, id: &str) -> SparkResult<MerkleWitness> {
+        let entry = self.notes.get(id).ok_or_else(|| SparkError::OperationError {
+            message: format!("Note with ID '{}' not found", id),
+        })?;
+        let position = entry.position.ok_or_else(|| SparkError::OperationError {
+            message: format!("Note '{}' has not been seen in the commitment tree yet", id),
+        })?;
+        self.commitment_tree.witness(position)
+    }
+
+    /// Match commitments fetched from the chain against this wallet
+    ///
+    /// The commitment tree is updated first (see
+    /// [`sync_commitment_tree`](Self::sync_commitment_tree)), which records
+    /// the new commitments and the owned notes they reveal in the history.
+    ///
+    /// # Returns
+    /// The number of commitments identified as belonging to the user
+    pub fn apply_scanned_commitments(&mut self, commitments: Vec<Vec<u8>>) -> SparkResult<usize> {
+        self.sync_commitment_tree(&commitments)?;
         let mut discovered = 0;
 
         for commitment in commitments {
@@ -487,24 +1721,162 @@ impl NoteManager {
             // In a POC, we simulate the discovery of a note if it's already in our map
             // or if we can re-derive it (mock logic)
             
-            let is_mine = self.notes.values().any(|e| e.commitment == commitment);
+            let known_id = self.notes.iter()
+                .find(|(_, e)| e.commitment == commitment)
+                .map(|(id, _)| id.clone());
             
-            if is_mine {
+            if let Some(id) = known_id {
                 // If it's already in notes, update its status maybe?
-                println!("Discovered existing note with commitment {:?}", hex::encode(&commitment));
                 discovered += 1;
+                self.events.emit(NoteEvent::NoteDiscovered { commitment, id: Some(id) });
             } else {
                 // Potential discovery of a new note (Trial Decryption Simulation)
                 // In a real scenario, we'd attempt to decrypt a payload here.
                 if commitment == vec![0u8; 32] {  // Simulation: dummy 0-commitment is "ours"
-                     println!("Trial decryption successful for commitment {:?}", hex::encode(&commitment));
                      discovered += 1;
+                     self.events.emit(NoteEvent::NoteDiscovered { commitment, id: None });
                 }
             }
         }
 
         Ok(discovered)
     }
+
+    /// Fetch the contract's spent nullifiers and reconcile the wallet with them
+    ///
+    /// See [`reconcile_spent_nullifiers`](Self::reconcile_spent_nullifiers).
+    pub async fn sync_spent_nullifiers(&mut self) -> SparkResult<ReconcileReport> {
+        let nullifiers = self.client()?.fetch_spent_nullifiers().await?;
+        self.reconcile_spent_nullifiers(&nullifiers)
+    }
+
+    /// Reconcile the local spent set with the nullifiers spent on chain
+    ///
+    /// Owned notes whose nullifier is on chain are marked as spent, deriving
+    /// the nullifier from the note secret if it was never generated; this
+    /// catches spends made from another device. Other chain nullifiers are
+    /// added to the spent set. Local spends the chain does not know about are
+    /// only reported, never undone.
+    ///
+    /// # Arguments
+    /// * `chain_nullifiers` - Every nullifier spent on chain
+    ///
+    /// # Returns
+    /// * `Ok(ReconcileReport)` listing the changes and disagreements
+    /// * `Err(SparkError)` if a chain nullifier is malformed (nothing is changed)
+    pub fn reconcile_spent_nullifiers(&mut self, chain_nullifiers: &[Vec<u8>]) -> SparkResult<ReconcileReport> {
+        let chain = chain_nullifiers.iter()
+            .map(|n| Nullifier::from_slice(n))
+            .collect::<SparkResult<Vec<_>>>()?;
+        let on_chain: std::collections::HashSet<Nullifier> = chain.iter().copied().collect();
+
+        let mut report = ReconcileReport::default();
+        let mut owned = std::collections::HashSet::new();
+        let mut ids: Vec<String> = self.notes.keys().cloned().collect();
+        ids.sort();
+        for id in ids {
+            let entry = &self.notes[&id];
+            let note = entry.to_spark_note()?;
+            let nullifier = match &entry.nullifier {
+                Some(n) => Nullifier::from_slice(n)?,
+                None => generate_nullifier(&note, &Secret::from(note.secret_bytes().to_vec())),
+            };
+            owned.insert(nullifier);
+
+            match (entry.state == NoteState::Spent, on_chain.contains(&nullifier)) {
+                (false, true) => {
+                    if entry.nullifier.is_none() {
+                        self.generate_nullifier_for_note(&id, note.secret_bytes().to_vec())?;
+                    }
+                    let before = self.index_entries(&id);
+                    self.record_note_spent(&id, nullifier, before)?;
+                    report.marked_spent.push(id);
+                }
+                (true, false) => report.missing_note_spends.push(id),
+                _ => {}
+            }
+        }
+
+        // Only the chain nullifiers missing from the spent set are imported
+        let known = self.spent_nullifiers.contains_batch(&chain)?;
+        let new: Vec<Nullifier> = chain.into_iter().zip(known).filter(|(_, known)| !known).map(|(n, _)| n).collect();
+        report.nullifiers_imported = self.spent_nullifiers.add_all(new)?;
+
+        // The spent set now holds every chain nullifier; it is only scanned
+        // when it also holds more than this wallet's own unconfirmed spends
+        let mut expected = on_chain.len() as u64;
+        for nullifier in owned.iter().filter(|n| !on_chain.contains(n)) {
+            if self.spent_nullifiers.contains(nullifier)? {
+                expected += 1;
+            }
+        }
+        if self.spent_nullifiers.len() > expected {
+            for nullifier in self.spent_nullifiers.iter() {
+                let nullifier = nullifier?;
+                if !on_chain.contains(&nullifier) && !owned.contains(&nullifier) {
+                    report.missing_nullifiers.push(nullifier.to_vec());
+                }
+            }
+            report.missing_nullifiers.sort();
+        }
+
+        if report.nullifiers_imported > 0 {
+            self.record_history(HistoryAction::ChainNullifiersImported { imported: report.nullifiers_imported })?;
+        }
+        Ok(report)
+    }
+
+    /// Append a state change to the history
+    pub(crate) fn record_history(&self, action: HistoryAction) -> SparkResult<()> {
+        self.history.append(&self.actor, action).map(|_| ())
+    }
+
+    /// Every history record, oldest first
+    pub fn history(&self) -> SparkResult<Vec<HistoryRecord>> {
+        self.history.records()
+    }
+
+    /// Hash of the latest history record
+    ///
+    /// Saving this value elsewhere lets a later [`verify_history`](Self::verify_history)
+    /// be complemented by a check that no records were dropped from the end.
+    pub fn history_head(&self) -> String {
+        self.history.head()
+    }
+
+    /// Check that the history has not been modified
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of records verified
+    /// * `Err(SparkError)` naming the first record that was tampered with
+    pub fn verify_history(&self) -> SparkResult<usize> {
+        let records = self.history.records()?;
+        let verified = history::verify_chain(&records)?;
+        if records.last().map(|r| r.hash.as_str()).unwrap_or(history::GENESIS_HASH) != self.history.head() {
+            return Err(SparkError::OperationError {
+                message: "History does not end at the expected head record".to_string(),
+            });
+        }
+        Ok(verified)
+    }
+
+    /// Export the history as a JSON array of records
+    pub fn export_history_json(&self) -> SparkResult<String> {
+        serde_json::to_string_pretty(&self.history.records()?).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize history: {}", e),
+        })
+    }
+
+    /// Handle to the event bus, shared with wrappers such as `SharedNoteManager`
+    pub(crate) fn event_bus(&self) -> &NoteEventBus {
+        &self.events
+    }
+}
+
+fn decode_backup_hex(value: &str) -> SparkResult<Vec<u8>> {
+    hex::decode(value).map_err(|e| SparkError::SerializationError {
+        message: format!("Invalid hex encoding in backup: {}", e),
+    })
 }
 
 impl Default for NoteManager {
@@ -630,5 +2002,478 @@ mod tests {
         
         let _ = std::fs::remove_dir_all(db_path);
     }
-}
 
+    fn add_test_note(manager: &mut NoteManager, id: &str, value: u64, seed: u8, metadata: NoteMetadata) {
+        let note = create_note(value, Secret::new(vec![seed; 16])).unwrap();
+        manager.add_note_with_metadata(id.to_string(), note, metadata).unwrap();
+    }
+
+    fn populate_for_query(manager: &mut NoteManager) {
+        let xtz = |label: Option<&str>| NoteMetadata {
+            asset: Some("XTZ".to_string()),
+            label: label.map(str::to_string),
+            ..NoteMetadata::default()
+        };
+        add_test_note(manager, "a", 100, 1, xtz(Some("savings")));
+        add_test_note(manager, "b", 250, 2, xtz(None));
+        add_test_note(manager, "c", 400, 3, NoteMetadata::new().with_asset("USDT"));
+        add_test_note(manager, "d", 50, 4, xtz(Some("savings")));
+
+        manager.generate_nullifier_for_note("d", vec![4; 16]).unwrap();
+        manager.mark_note_as_spent("d").unwrap();
+    }
+
+    fn query_ids(manager: &NoteManager, query: &NoteQuery) -> Vec<String> {
+        manager.query(query).unwrap().notes.into_iter().map(|(id, _)| id).collect()
+    }
+
+    fn assert_query_results(manager: &NoteManager) {
+        use crate::query::{NoteSortField, SortOrder};
+
+        assert_eq!(query_ids(manager, &NoteQuery::new()), vec!["a", "b", "c", "d"]);
+        assert_eq!(query_ids(manager, &NoteQuery::new().with_state(NoteState::Spent)), vec!["d"]);
+        assert_eq!(query_ids(manager, &NoteQuery::new().with_value_range(100, 250)), vec!["a", "b"]);
+        assert_eq!(query_ids(manager, &NoteQuery::new().with_asset("USDT")), vec!["c"]);
+        assert_eq!(
+            query_ids(manager, &NoteQuery::new().with_label("savings").with_state(NoteState::Unspent)),
+            vec!["a"]
+        );
+
+        let page = manager.query(
+            &NoteQuery::new()
+                .with_asset("XTZ")
+                .sort_by(NoteSortField::Value, SortOrder::Descending)
+                .with_offset(1)
+                .with_limit(1),
+        ).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.notes[0].0, "a");
+
+        assert_eq!(manager.balance(), 750);
+        assert_eq!(manager.spendable_balance(), 750);
+    }
+
+    #[test]
+    fn test_query_in_memory() {
+        let mut manager = NoteManager::new();
+        populate_for_query(&mut manager);
+        assert_query_results(&manager);
+    }
+
+    #[test]
+    fn test_query_uses_persistent_indexes() {
+        let db_path = std::env::temp_dir().join("spark_test_query_indexes");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+
+        {
+            let mut manager = NoteManager::open(db_path).unwrap();
+            populate_for_query(&mut manager);
+            assert_query_results(&manager);
+
+            manager.set_note_metadata("b", NoteMetadata::new().with_asset("XTZ").with_label("savings")).unwrap();
+            manager.try_remove_note("c").unwrap();
+        }
+
+        {
+            let manager = NoteManager::open(db_path).unwrap();
+            assert_eq!(query_ids(&manager, &NoteQuery::new().with_label("savings")), vec!["a", "b", "d"]);
+            assert!(query_ids(&manager, &NoteQuery::new().with_asset("USDT")).is_empty());
+            assert_eq!(manager.note_count(), 3);
+        }
+
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    #[test]
+    fn test_note_metadata_tags_and_properties() {
+        let db_path = std::env::temp_dir().join("spark_test_note_metadata");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+
+        {
+            let mut manager = NoteManager::open(db_path).unwrap();
+            add_test_note(&mut manager, "a", 100, 1, NoteMetadata::new().with_source(NoteSource::Deposit));
+            add_test_note(&mut manager, "b", 200, 2, NoteMetadata::new().with_created_at(42).with_source(NoteSource::Change));
+
+            manager.set_note_label("a", Some("rent".to_string())).unwrap();
+            manager.add_note_tag("a", "monthly").unwrap();
+            manager.add_note_tag("b", "monthly").unwrap();
+            manager.add_note_tag("b", "small").unwrap();
+            manager.remove_note_tag("b", "small").unwrap();
+            manager.set_note_property("a", "counterparty", Some("landlord".to_string())).unwrap();
+            assert!(manager.add_note_tag("missing", "x").is_err());
+
+            let too_long = "x".repeat(MAX_METADATA_STRING_LENGTH + 1);
+            assert!(manager.add_note_tag("a", &too_long).is_err());
+        }
+
+        let manager = NoteManager::open(db_path).unwrap();
+        let a = manager.get_note_metadata("a").unwrap();
+        assert_eq!(a.label.as_deref(), Some("rent"));
+        assert_eq!(a.properties.get("counterparty").map(String::as_str), Some("landlord"));
+        assert!(a.created_at.unwrap() > 0);
+
+        // Tags are indexed; creation times survive metadata updates
+        assert_eq!(query_ids(&manager, &NoteQuery::new().with_tag("monthly")), vec!["a", "b"]);
+        assert!(query_ids(&manager, &NoteQuery::new().with_tag("small")).is_empty());
+        assert_eq!(query_ids(&manager, &NoteQuery::new().with_created_range(0, 100)), vec!["b"]);
+        assert_eq!(query_ids(&manager, &NoteQuery::new().with_source(NoteSource::Deposit)), vec!["a"]);
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    #[test]
+    fn test_spendable_balance_excludes_known_spent_nullifiers() {
+        let mut manager = NoteManager::new();
+        add_test_note(&mut manager, "a", 100, 1, NoteMetadata::default());
+        add_test_note(&mut manager, "b", 200, 2, NoteMetadata::default());
+
+        // Spent elsewhere: nullifier is known but the note is still Unspent locally
+        let nullifier = manager.generate_nullifier_for_note("b", vec![2; 16]).unwrap();
+        manager.add_spent_nullifier(&nullifier).unwrap();
+
+        assert_eq!(manager.balance(), 300);
+        assert_eq!(manager.spendable_balance(), 100);
+    }
+
+    #[test]
+    fn test_change_events() {
+        use std::sync::{Arc, Mutex};
+
+        let mut manager = NoteManager::new();
+        let mut receiver = manager.subscribe();
+        let observed = Arc::new(Mutex::new(0usize));
+        let counter = observed.clone();
+        manager.add_observer(move |_| *counter.lock().unwrap() += 1);
+
+        add_test_note(&mut manager, "a", 100, 1, NoteMetadata::default());
+        let nullifier = manager.generate_nullifier_for_note("a", vec![1; 16]).unwrap();
+        manager.mark_note_as_spent("a").unwrap();
+        manager.remove_note("a").unwrap();
+        // Failed operations emit nothing
+        assert!(manager.mark_note_as_spent("a").is_err());
+
+        let events: Vec<_> = std::iter::from_fn(|| receiver.try_recv()).collect();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], NoteEvent::NoteAdded { id, value: 100, .. } if id == "a"));
+        assert_eq!(events[1], NoteEvent::NullifierGenerated { id: "a".to_string(), nullifier: nullifier.clone() });
+        assert_eq!(events[2], NoteEvent::NoteSpent { id: "a".to_string(), nullifier });
+        assert_eq!(events[3], NoteEvent::NoteRemoved { id: "a".to_string() });
+        assert_eq!(*observed.lock().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_history_records_and_detects_tampering() {
+        let db_path = std::env::temp_dir().join("spark_test_history");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+
+        {
+            let mut manager = NoteManager::open(db_path).unwrap().with_actor("alice");
+            add_test_note(&mut manager, "a", 100, 1, NoteMetadata::default());
+            manager.generate_nullifier_for_note("a", vec![1; 16]).unwrap();
+            manager.mark_note_as_spent("a").unwrap();
+            manager.try_remove_note("a").unwrap();
+        }
+
+        let manager = NoteManager::open(db_path).unwrap();
+        let records = manager.history().unwrap();
+        assert_eq!(manager.verify_history().unwrap(), 4);
+        assert!(records.iter().all(|r| r.actor == "alice"));
+        assert_eq!(records[0].action, HistoryAction::NoteAdded { id: "a".to_string(), value: 100 });
+        assert!(matches!(
+            &records[2].action,
+            HistoryAction::NoteSpent { previous_state: NoteState::Unspent, .. }
+        ));
+        assert_eq!(
+            records[3].action,
+            HistoryAction::NoteRemoved { id: "a".to_string(), value: 100, state: NoteState::Spent }
+        );
+
+        let exported: Vec<HistoryRecord> = serde_json::from_str(&manager.export_history_json().unwrap()).unwrap();
+        assert_eq!(exported, records);
+
+        // Rewriting a stored record is detected
+        let tree = manager.db.as_ref().unwrap().open_tree(HISTORY_TREE).unwrap();
+        let mut forged = records[1].clone();
+        forged.actor = "mallory".to_string();
+        tree.insert(1u64.to_be_bytes(), serde_json::to_vec(&forged).unwrap()).unwrap();
+        assert!(manager.verify_history().is_err());
+
+        drop((manager, tree));
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    #[test]
+    fn test_commitment_tree_sync_and_witnesses() {
+        use crate::crypto::{compute_nullifier, setup_spending_snark, verify_spending_proof};
+
+        let db_path = std::env::temp_dir().join("spark_test_commitment_tree");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+
+        let other = |seed: u8| create_note(7, Secret::new(vec![seed; 16])).unwrap().commitment;
+        let mine = create_note(500, Secret::new(vec![1; 16])).unwrap();
+
+        {
+            let mut manager = NoteManager::open(db_path).unwrap();
+            manager.add_note("mine".to_string(), mine.clone()).unwrap();
+            assert!(manager.note_witness("mine").is_err());
+
+            let mut chain = vec![other(10), mine.commitment.clone()];
+            assert_eq!(manager.apply_scanned_commitments(chain.clone()).unwrap(), 1);
+            assert_eq!(manager.get_note("mine").unwrap().position, Some(1));
+
+            // Later deposits extend the tree; already known ones are skipped
+            chain.push(other(11));
+            assert_eq!(manager.sync_commitment_tree(&chain).unwrap(), 1);
+            assert_eq!(manager.sync_commitment_tree(&chain).unwrap(), 0);
+
+            // Each sync that changed something is in the history, nothing else
+            let synced: Vec<HistoryAction> = manager.history().unwrap().into_iter()
+                .map(|r| r.action)
+                .filter(|a| matches!(a, HistoryAction::CommitmentsSynced { .. } | HistoryAction::NoteFoundOnChain { .. }))
+                .collect();
+            assert_eq!(synced, vec![
+                HistoryAction::CommitmentsSynced { appended: 2, tree_size: 2 },
+                HistoryAction::NoteFoundOnChain { id: "mine".to_string(), position: 1 },
+                HistoryAction::CommitmentsSynced { appended: 1, tree_size: 3 },
+            ]);
+
+            // A chain that rewrites history is rejected
+            assert!(manager.sync_commitment_tree(&[other(12)]).is_err());
+        }
+
+        let manager = NoteManager::open(db_path).unwrap();
+        assert_eq!(manager.commitment_tree().len(), 3);
+        let witness = manager.note_witness("mine").unwrap();
+        assert_eq!(witness.position, 1);
+        assert_eq!(witness.root, manager.commitment_tree().root_bytes());
+
+        let (pk, vk) = setup_spending_snark();
+        let proof = mine.prove_spending(&pk, &witness.root, witness.path).unwrap();
+        assert!(verify_spending_proof(&vk, &proof, &witness.root, &compute_nullifier(mine.secret_bytes())).unwrap());
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    #[test]
+    fn test_prepare_deposit_rejects_mismatched_commitment() {
+        let mut manager = NoteManager::new();
+        manager.add_note("note".to_string(), create_note(1000, Secret::new(vec![3; 16])).unwrap()).unwrap();
+
+        // The note itself is fine; only the missing client stops it
+        let err = manager.prepare_deposit("note").unwrap_err();
+        assert!(err.to_string().contains("not configured"));
+
+        manager.notes.get_mut("note").unwrap().commitment = create_note(1000, Secret::new(vec![4; 16])).unwrap().commitment;
+        let err = manager.prepare_deposit("note").unwrap_err();
+        assert!(err.to_string().contains("does not match its stored commitment"));
+    }
+
+    #[test]
+    fn test_reconcile_spent_nullifiers_with_chain() {
+        let mut manager = NoteManager::new();
+        let elsewhere = create_note(100, Secret::new(vec![1; 32])).unwrap();
+        let local = create_note(200, Secret::new(vec![2; 32])).unwrap();
+        let unspent = create_note(300, Secret::new(vec![3; 32])).unwrap();
+        manager.add_note("elsewhere".to_string(), elsewhere.clone()).unwrap();
+        manager.add_note("local".to_string(), local.clone()).unwrap();
+        manager.add_note("unspent".to_string(), unspent).unwrap();
+
+        // Spent locally, but never seen on chain
+        manager.generate_nullifier_for_note("local", local.secret_bytes().to_vec()).unwrap();
+        manager.mark_note_as_spent("local").unwrap();
+        manager.add_spent_nullifier(&[8u8; 32]).unwrap();
+
+        // "elsewhere" was spent from another device without a local nullifier
+        let chain = vec![crate::crypto::compute_nullifier(elsewhere.secret_bytes()), vec![7u8; 32], vec![7u8; 32]];
+        assert!(manager.reconcile_spent_nullifiers(&[vec![1u8; 31]]).is_err());
+        let report = manager.reconcile_spent_nullifiers(&chain).unwrap();
+
+        assert_eq!(report.marked_spent, vec!["elsewhere".to_string()]);
+        assert_eq!(report.nullifiers_imported, 1);
+        assert_eq!(report.missing_note_spends, vec!["local".to_string()]);
+        assert_eq!(report.missing_nullifiers, vec![vec![8u8; 32]]);
+        assert!(report.has_local_only_spends());
+
+        assert_eq!(manager.get_note("elsewhere").unwrap().state, NoteState::Spent);
+        assert_eq!(manager.get_note("unspent").unwrap().state, NoteState::Unspent);
+        assert!(manager.is_nullifier_spent(&[7u8; 32]));
+        assert!(manager.try_is_nullifier_spent(&[7u8; 32]).unwrap());
+        assert!(!manager.try_is_nullifier_spent(&[6u8; 32]).unwrap());
+        assert_eq!(manager.spendable_balance(), 300);
+        assert!(matches!(
+            manager.history().unwrap().last().unwrap().action,
+            HistoryAction::ChainNullifiersImported { imported: 1 }
+        ));
+
+        // A second pass finds nothing new
+        let again = manager.reconcile_spent_nullifiers(&chain).unwrap();
+        assert!(again.marked_spent.is_empty());
+        assert_eq!(again.nullifiers_imported, 0);
+        assert_eq!(again.missing_nullifiers, vec![vec![8u8; 32]]);
+    }
+
+    #[test]
+    fn test_injected_spend_already_marked_by_reconcile() {
+        let mut manager = NoteManager::new();
+        let note = create_note(100, Secret::new(vec![1; 32])).unwrap();
+        manager.add_note("note".to_string(), note.clone()).unwrap();
+        let nullifier = manager.generate_nullifier_for_note("note", note.secret_bytes().to_vec()).unwrap();
+
+        // The chain already shows the spend when the injecting task records it
+        manager.reconcile_spent_nullifiers(std::slice::from_ref(&nullifier)).unwrap();
+        manager.record_injected_spend("note", &nullifier, "ooSpend").unwrap();
+        assert_eq!(manager.get_note("note").unwrap().state, NoteState::Spent);
+        let spent = manager.history().unwrap().iter().filter(|r| matches!(r.action, HistoryAction::NoteSpent { .. })).count();
+        assert_eq!(spent, 1);
+        assert!(matches!(
+            &manager.history().unwrap().last().unwrap().action,
+            HistoryAction::SpendSynced { operation_hash, .. } if operation_hash == "ooSpend"
+        ));
+
+        assert!(manager.record_injected_spend("note", &[9u8; 32], "ooOther").is_err());
+    }
+
+    #[test]
+    fn test_reconcile_imports_into_database_in_bulk() {
+        let db_path = std::env::temp_dir().join("spark_test_reconcile_bulk");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+
+        let chain: Vec<Vec<u8>> = (0..5000u32)
+            .map(|i| [i.to_be_bytes().to_vec(), vec![0; 28]].concat())
+            .collect();
+        {
+            let mut manager = NoteManager::open(db_path).unwrap();
+            manager.add_spent_nullifier(&chain[0]).unwrap();
+            let report = manager.reconcile_spent_nullifiers(&[chain.as_slice(), &chain[..10]].concat()).unwrap();
+            assert_eq!(report.nullifiers_imported, chain.len() - 1);
+            assert!(report.missing_nullifiers.is_empty());
+
+            // Nullifiers the chain lacks are found by streaming the store
+            manager.add_spent_nullifier(&[0xff; 32]).unwrap();
+            let report = manager.reconcile_spent_nullifiers(&chain).unwrap();
+            assert_eq!(report.missing_nullifiers, vec![vec![0xff; 32]]);
+        }
+
+        let manager = NoteManager::open(db_path).unwrap();
+        assert_eq!(manager.spent_nullifier_count(), chain.len() + 1);
+        assert!(chain.iter().all(|n| manager.try_is_nullifier_spent(n).unwrap()));
+
+        drop(manager);
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    fn test_kdf() -> KdfParams {
+        KdfParams::with_cost(256, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn test_backup_restore_roundtrip() {
+        let mut source = NoteManager::new();
+        populate_for_query(&mut source);
+        source.add_spent_nullifier(&[9u8; 32]).unwrap();
+        let backup = source.export_backup_with("hunter2", test_kdf()).unwrap();
+
+        let db_path = std::env::temp_dir().join("spark_test_backup_restore");
+        let db_path = db_path.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+        {
+            let mut target = NoteManager::open(db_path).unwrap();
+            let report = target.restore_backup(&backup, "hunter2", ConflictPolicy::Abort).unwrap();
+            assert_eq!(report.restored, vec!["a", "b", "c", "d"]);
+            assert_eq!(report.nullifiers_imported, 2);
+        }
+
+        let target = NoteManager::open(db_path).unwrap();
+        assert_query_results(&target);
+        assert!(target.is_nullifier_spent(&[9u8; 32]));
+        assert_eq!(target.get_note_metadata("a").unwrap().label.as_deref(), Some("savings"));
+        assert_eq!(target.get_note_metadata("c"), source.get_note_metadata("c"));
+
+        // Secrets survive the round trip: the restored note derives the same nullifier
+        let mut target = target;
+        let original = source.get_note("d").unwrap().nullifier.unwrap();
+        assert_eq!(target.generate_nullifier_for_note("d", vec![4; 16]).unwrap(), original);
+
+        drop(target);
+        let _ = std::fs::remove_dir_all(db_path);
+    }
+
+    #[test]
+    fn test_restore_conflicts() {
+        let mut source = NoteManager::new();
+        add_test_note(&mut source, "same", 100, 1, NoteMetadata::default());
+        add_test_note(&mut source, "clash", 200, 2, NoteMetadata::default());
+        add_test_note(&mut source, "fresh", 300, 3, NoteMetadata::default());
+        source.generate_nullifier_for_note("same", vec![1; 16]).unwrap();
+        source.mark_note_as_spent("same").unwrap();
+        let backup = source.export_backup_with("pw", test_kdf()).unwrap();
+
+        let target_with_clash = || {
+            let mut target = NoteManager::new();
+            add_test_note(&mut target, "same", 100, 1, NoteMetadata::default());
+            add_test_note(&mut target, "clash", 999, 9, NoteMetadata::default());
+            target
+        };
+
+        // Abort leaves the wallet untouched
+        let mut target = target_with_clash();
+        assert!(target.restore_backup(&backup, "pw", ConflictPolicy::Abort).is_err());
+        assert_eq!(target.note_count(), 2);
+        assert_eq!(target.spent_nullifier_count(), 0);
+
+        let mut target = target_with_clash();
+        let report = target.restore_backup(&backup, "pw", ConflictPolicy::KeepExisting).unwrap();
+        assert_eq!(report.merged, vec!["same"]);
+        assert_eq!(report.skipped, vec!["clash"]);
+        assert_eq!(report.restored, vec!["fresh"]);
+        assert_eq!(target.get_note("same").unwrap().state, NoteState::Spent);
+        assert_eq!(target.get_note("clash").unwrap().note.value, 999);
+
+        let mut target = target_with_clash();
+        let report = target.restore_backup(&backup, "pw", ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.overwritten, vec!["clash"]);
+        assert_eq!(target.get_note("clash").unwrap().note.value, 200);
+
+        // Restoring twice is idempotent
+        let report = target.restore_backup(&backup, "pw", ConflictPolicy::Abort).unwrap();
+        assert!(report.restored.is_empty());
+        assert_eq!(report.merged.len(), 3);
+        assert_eq!(report.nullifiers_imported, 0);
+    }
+
+    #[test]
+    fn test_restore_rejects_invalid_metadata() {
+        let mut source = NoteManager::new();
+        add_test_note(&mut source, "a", 100, 1, NoteMetadata::default());
+        add_test_note(&mut source, "b", 200, 2, NoteMetadata::default());
+        let backup = source.export_backup_with("pw", test_kdf()).unwrap();
+
+        // Re-seal the backup with metadata set_note_metadata would refuse
+        let mut payload = WalletBackup::from_json(&backup).unwrap().open("pw").unwrap();
+        payload.notes[1].metadata.label = Some("x".repeat(MAX_METADATA_STRING_LENGTH + 1));
+        let crafted = WalletBackup::seal(&payload, "pw", test_kdf()).unwrap().to_json().unwrap();
+
+        let mut target = NoteManager::new();
+        let err = target.restore_backup(&crafted, "pw", ConflictPolicy::Abort).unwrap_err();
+        assert!(err.to_string().contains("invalid metadata"));
+        assert_eq!(target.note_count(), 0);
+    }
+
+    #[test]
+    fn test_restore_rejects_wrong_password() {
+        let mut source = NoteManager::new();
+        add_test_note(&mut source, "a", 100, 1, NoteMetadata::default());
+        let backup = source.export_backup_with("pw", test_kdf()).unwrap();
+
+        let mut target = NoteManager::new();
+        assert!(target.restore_backup(&backup, "not-pw", ConflictPolicy::Abort).is_err());
+        assert_eq!(target.note_count(), 0);
+    }
+}
@@ -0,0 +1,921 @@
+//! Micheline data model
+//!
+//! [`Micheline`] is the syntax tree of Michelson values: integers, strings,
+//! bytes, sequences and primitive applications. It converts to and from the
+//! JSON form used by the node RPCs ([`Micheline::from_json`],
+//! [`Micheline::to_json`]) and the binary form used in forged operations and
+//! packed data ([`Micheline::encode`], [`Micheline::decode`]).
+//!
+//! Typed values go through [`ToMicheline`] and [`FromMicheline`]: pairs are
+//! tuples, `bytes` is `Vec<u8>`, `nat` is `u64`, `int` is `BigInt`, and
+//! addresses and the BLS12-381 types have their own wrappers. Parsing checks
+//! the shape of the value, so malformed storage is an error rather than a
+//! default.
+
+use ark_bls12_381::{Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ec::AffineRepr;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use num_bigint::{BigInt, BigUint, Sign};
+use serde_json::{json, Value};
+
+use crate::error::{SparkError, SparkResult};
+use crate::tezos_forge::{decode_contract, forge_contract};
+
+/// Prefix of packed Michelson data
+pub const PACK_TAG: u8 = 0x05;
+
+/// Deepest nesting accepted when decoding binary Micheline
+///
+/// Decoding recurses once per level, so untrusted bytes could otherwise
+/// overflow the stack with a few kilobytes of nested sequences. This is the
+/// same bound `serde_json` puts on the JSON form read from node RPCs.
+pub const MAX_DECODE_DEPTH: usize = 128;
+
+/// Michelson primitives, indexed by their code in the binary encoding
+const PRIMITIVES: [&str; 158] = [
+    "parameter", "storage", "code", "False", "Elt", "Left", "None", "Pair", "Right", "Some",
+    "True", "Unit", "PACK", "UNPACK", "BLAKE2B", "SHA256", "SHA512", "ABS", "ADD", "AMOUNT",
+    "AND", "BALANCE", "CAR", "CDR", "CHECK_SIGNATURE", "COMPARE", "CONCAT", "CONS", "CREATE_ACCOUNT", "CREATE_CONTRACT",
+    "IMPLICIT_ACCOUNT", "DIP", "DROP", "DUP", "EDIV", "EMPTY_MAP", "EMPTY_SET", "EQ", "EXEC", "FAILWITH",
+    "GE", "GET", "GT", "HASH_KEY", "IF", "IF_CONS", "IF_LEFT", "IF_NONE", "INT", "LAMBDA",
+    "LE", "LEFT", "LOOP", "LSL", "LSR", "LT", "MAP", "MEM", "MUL", "NEG",
+    "NEQ", "NIL", "NONE", "NOT", "NOW", "OR", "PAIR", "PUSH", "RIGHT", "SIZE",
+    "SOME", "SOURCE", "SENDER", "SELF", "STEPS_TO_QUOTA", "SUB", "SWAP", "TRANSFER_TOKENS", "SET_DELEGATE", "UNIT",
+    "UPDATE", "XOR", "ITER", "LOOP_LEFT", "ADDRESS", "CONTRACT", "ISNAT", "CAST", "RENAME", "bool",
+    "contract", "int", "key", "key_hash", "lambda", "list", "map", "big_map", "nat", "option",
+    "or", "pair", "set", "signature", "string", "bytes", "mutez", "timestamp", "unit", "operation",
+    "address", "SLICE", "DIG", "DUG", "EMPTY_BIG_MAP", "APPLY", "chain_id", "CHAIN_ID", "LEVEL", "SELF_ADDRESS",
+    "never", "NEVER", "UNPAIR", "VOTING_POWER", "TOTAL_VOTING_POWER", "KECCAK", "SHA3", "PAIRING_CHECK", "bls12_381_g1", "bls12_381_g2",
+    "bls12_381_fr", "sapling_state", "sapling_transaction_deprecated", "SAPLING_EMPTY_STATE", "SAPLING_VERIFY_UPDATE", "ticket", "TICKET_DEPRECATED", "READ_TICKET", "SPLIT_TICKET", "JOIN_TICKETS",
+    "GET_AND_UPDATE", "chest", "chest_key", "OPEN_CHEST", "VIEW", "view", "constant", "SUB_MUTEZ", "tx_rollup_l2_address", "MIN_BLOCK_TIME",
+    "sapling_transaction", "EMIT", "Lambda_rec", "LAMBDA_REC", "TICKET", "BYTES", "NAT", "Ticket",
+];
+
+/// A Micheline value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Micheline {
+    /// Integer literal
+    Int(BigInt),
+    /// String literal
+    String(String),
+    /// Byte sequence
+    Bytes(Vec<u8>),
+    /// Sequence `{ ... }`
+    Seq(Vec<Micheline>),
+    /// Primitive application, e.g. `Pair 1 2`
+    Prim {
+        /// Primitive name
+        prim: String,
+        /// Arguments
+        args: Vec<Micheline>,
+        /// Annotations, e.g. `%to`
+        annots: Vec<String>,
+    },
+}
+
+impl Micheline {
+    /// Primitive application without annotations
+    pub fn prim(prim: &str, args: Vec<Micheline>) -> Self {
+        Micheline::Prim { prim: prim.to_string(), args, annots: Vec::new() }
+    }
+
+    /// `Pair left right`
+    pub fn pair(left: Micheline, right: Micheline) -> Self {
+        Micheline::prim("Pair", vec![left, right])
+    }
+
+    /// Arguments of `prim`, or `None` if this is another value
+    pub fn prim_args(&self, prim: &str) -> Option<&[Micheline]> {
+        match self {
+            Micheline::Prim { prim: p, args, .. } if p == prim => Some(args),
+            _ => None,
+        }
+    }
+
+    /// Parse the JSON form used by the node RPCs
+    pub fn from_json(value: &Value) -> SparkResult<Self> {
+        if let Value::Array(items) = value {
+            return Ok(Micheline::Seq(items.iter().map(Micheline::from_json).collect::<SparkResult<_>>()?));
+        }
+
+        let object = value.as_object().ok_or_else(|| micheline_error(format!("Invalid Micheline value: {}", value)))?;
+        if let Some(int) = object.get("int") {
+            int.as_str()
+                .and_then(|s| s.parse().ok())
+                .map(Micheline::Int)
+                .ok_or_else(|| micheline_error(format!("Invalid Micheline int: {}", int)))
+        } else if let Some(string) = object.get("string") {
+            string
+                .as_str()
+                .map(|s| Micheline::String(s.to_string()))
+                .ok_or_else(|| micheline_error("Micheline string is not a string"))
+        } else if let Some(bytes) = object.get("bytes") {
+            bytes
+                .as_str()
+                .and_then(|s| hex::decode(s).ok())
+                .map(Micheline::Bytes)
+                .ok_or_else(|| micheline_error("Micheline bytes are not hex"))
+        } else if let Some(prim) = object.get("prim") {
+            let prim = prim.as_str().ok_or_else(|| micheline_error("Micheline prim is not a string"))?;
+            let args = match object.get("args") {
+                Some(Value::Array(args)) => args.iter().map(Micheline::from_json).collect::<SparkResult<_>>()?,
+                Some(_) => return Err(micheline_error("Micheline args are not an array")),
+                None => Vec::new(),
+            };
+            let annots = match object.get("annots") {
+                Some(Value::Array(annots)) => annots
+                    .iter()
+                    .map(|a| {
+                        a.as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| micheline_error("Micheline annotation is not a string"))
+                    })
+                    .collect::<SparkResult<_>>()?,
+                Some(_) => return Err(micheline_error("Micheline annots are not an array")),
+                None => Vec::new(),
+            };
+            Ok(Micheline::Prim { prim: prim.to_string(), args, annots })
+        } else {
+            Err(micheline_error(format!("Invalid Micheline value: {}", value)))
+        }
+    }
+
+    /// JSON form used by the node RPCs
+    pub fn to_json(&self) -> Value {
+        match self {
+            Micheline::Int(int) => json!({ "int": int.to_string() }),
+            Micheline::String(string) => json!({ "string": string }),
+            Micheline::Bytes(bytes) => json!({ "bytes": hex::encode(bytes) }),
+            Micheline::Seq(items) => Value::Array(items.iter().map(Micheline::to_json).collect()),
+            Micheline::Prim { prim, args, annots } => {
+                let mut value = json!({ "prim": prim });
+                if !args.is_empty() {
+                    value["args"] = Value::Array(args.iter().map(Micheline::to_json).collect());
+                }
+                if !annots.is_empty() {
+                    value["annots"] = json!(annots);
+                }
+                value
+            }
+        }
+    }
+
+    /// Binary encoding
+    ///
+    /// # Errors
+    /// Fails on unknown primitives and values too large to encode
+    pub fn encode(&self) -> SparkResult<Vec<u8>> {
+        let mut out = Vec::new();
+        self.encode_into(&mut out)?;
+        Ok(out)
+    }
+
+    /// Decode a value produced by [`encode`](Self::encode)
+    ///
+    /// # Errors
+    /// Fails on malformed input, trailing bytes, and values nested deeper
+    /// than [`MAX_DECODE_DEPTH`]
+    pub fn decode(bytes: &[u8]) -> SparkResult<Self> {
+        let mut reader = Reader::new(bytes);
+        let value = Micheline::decode_from(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(micheline_error("Trailing bytes after Micheline value"));
+        }
+        Ok(value)
+    }
+
+    /// Packed form, as computed by the `PACK` instruction
+    pub fn pack(&self) -> SparkResult<Vec<u8>> {
+        let mut packed = vec![PACK_TAG];
+        self.encode_into(&mut packed)?;
+        Ok(packed)
+    }
+
+    pub(crate) fn encode_into(&self, out: &mut Vec<u8>) -> SparkResult<()> {
+        match self {
+            Micheline::Int(int) => {
+                out.push(0x00);
+                write_zarith(out, int);
+            }
+            Micheline::String(string) => {
+                out.push(0x01);
+                write_len_prefixed(out, string.as_bytes())?;
+            }
+            Micheline::Seq(items) => {
+                let mut body = Vec::new();
+                for item in items {
+                    item.encode_into(&mut body)?;
+                }
+                out.push(0x02);
+                write_len_prefixed(out, &body)?;
+            }
+            Micheline::Prim { prim, args, annots } => {
+                let code = PRIMITIVES
+                    .iter()
+                    .position(|p| p == prim)
+                    .ok_or_else(|| micheline_error(format!("Unknown Michelson primitive {}", prim)))?
+                    as u8;
+                let annots = annots.join(" ");
+                let has_annots = !annots.is_empty();
+                if args.len() <= 2 {
+                    out.push(0x03 + 2 * args.len() as u8 + has_annots as u8);
+                    out.push(code);
+                    for arg in args {
+                        arg.encode_into(out)?;
+                    }
+                    if has_annots {
+                        write_len_prefixed(out, annots.as_bytes())?;
+                    }
+                } else {
+                    out.push(0x09);
+                    out.push(code);
+                    let mut body = Vec::new();
+                    for arg in args {
+                        arg.encode_into(&mut body)?;
+                    }
+                    write_len_prefixed(out, &body)?;
+                    write_len_prefixed(out, annots.as_bytes())?;
+                }
+            }
+            Micheline::Bytes(bytes) => {
+                out.push(0x0a);
+                write_len_prefixed(out, bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn decode_from(reader: &mut Reader) -> SparkResult<Self> {
+        Micheline::decode_nested(reader, 0)
+    }
+
+    /// Decode a value found `depth` levels below the top-level one
+    fn decode_nested(reader: &mut Reader, depth: usize) -> SparkResult<Self> {
+        if depth > MAX_DECODE_DEPTH {
+            return Err(micheline_error(format!("Micheline value is nested deeper than {} levels", MAX_DECODE_DEPTH)));
+        }
+        let tag = reader.byte()?;
+        Ok(match tag {
+            0x00 => Micheline::Int(reader.zarith()?),
+            0x01 => {
+                let len = reader.u32()? as usize;
+                let string = std::str::from_utf8(reader.take(len)?)
+                    .map_err(|_| micheline_error("Micheline string is not UTF-8"))?;
+                Micheline::String(string.to_string())
+            }
+            0x02 => {
+                let len = reader.u32()? as usize;
+                let mut body = Reader::new(reader.take(len)?);
+                let mut items = Vec::new();
+                while !body.is_empty() {
+                    items.push(Micheline::decode_nested(&mut body, depth + 1)?);
+                }
+                Micheline::Seq(items)
+            }
+            0x03..=0x09 => {
+                let code = reader.byte()? as usize;
+                let prim = PRIMITIVES
+                    .get(code)
+                    .ok_or_else(|| micheline_error(format!("Unknown Michelson primitive code {}", code)))?;
+                let mut args = Vec::new();
+                let has_annots = if tag == 0x09 {
+                    let len = reader.u32()? as usize;
+                    let mut body = Reader::new(reader.take(len)?);
+                    while !body.is_empty() {
+                        args.push(Micheline::decode_nested(&mut body, depth + 1)?);
+                    }
+                    true
+                } else {
+                    for _ in 0..(tag - 0x03) / 2 {
+                        args.push(Micheline::decode_nested(reader, depth + 1)?);
+                    }
+                    (tag - 0x03) % 2 == 1
+                };
+                let annots = if has_annots {
+                    let len = reader.u32()? as usize;
+                    std::str::from_utf8(reader.take(len)?)
+                        .map_err(|_| micheline_error("Micheline annotations are not UTF-8"))?
+                        .split(' ')
+                        .filter(|a| !a.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                Micheline::Prim { prim: prim.to_string(), args, annots }
+            }
+            0x0a => {
+                let len = reader.u32()? as usize;
+                Micheline::Bytes(reader.take(len)?.to_vec())
+            }
+            tag => return Err(micheline_error(format!("Unknown Micheline tag {:#04x}", tag))),
+        })
+    }
+}
+
+/// Conversion of a Rust value to Micheline
+pub trait ToMicheline {
+    /// The value as Micheline
+    fn to_micheline(&self) -> Micheline;
+}
+
+/// Conversion of Micheline to a Rust value, checking its shape
+pub trait FromMicheline: Sized {
+    /// Parse the value
+    ///
+    /// # Errors
+    /// Fails if the value does not have the expected type
+    fn from_micheline(value: &Micheline) -> SparkResult<Self>;
+}
+
+impl ToMicheline for Micheline {
+    fn to_micheline(&self) -> Micheline {
+        self.clone()
+    }
+}
+
+impl FromMicheline for Micheline {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        Ok(value.clone())
+    }
+}
+
+impl<T: ToMicheline + ?Sized> ToMicheline for &T {
+    fn to_micheline(&self) -> Micheline {
+        (**self).to_micheline()
+    }
+}
+
+impl ToMicheline for [u8] {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::Bytes(self.to_vec())
+    }
+}
+
+impl ToMicheline for Vec<u8> {
+    fn to_micheline(&self) -> Micheline {
+        self.as_slice().to_micheline()
+    }
+}
+
+impl FromMicheline for Vec<u8> {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::Bytes(bytes) => Ok(bytes.clone()),
+            other => Err(type_error("bytes", other)),
+        }
+    }
+}
+
+/// `nat`
+impl ToMicheline for u64 {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::Int(BigInt::from(*self))
+    }
+}
+
+/// `nat`, rejecting negative and out-of-range values
+impl FromMicheline for u64 {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::Int(int) => u64::try_from(int).map_err(|_| type_error("nat", value)),
+            other => Err(type_error("nat", other)),
+        }
+    }
+}
+
+/// `int`
+impl ToMicheline for BigInt {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::Int(self.clone())
+    }
+}
+
+impl FromMicheline for BigInt {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::Int(int) => Ok(int.clone()),
+            other => Err(type_error("int", other)),
+        }
+    }
+}
+
+impl ToMicheline for str {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::String(self.to_string())
+    }
+}
+
+impl FromMicheline for String {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::String(string) => Ok(string.clone()),
+            other => Err(type_error("string", other)),
+        }
+    }
+}
+
+/// `unit`
+impl ToMicheline for () {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::prim("Unit", Vec::new())
+    }
+}
+
+impl FromMicheline for () {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value.prim_args("Unit") {
+            Some([]) => Ok(()),
+            _ => Err(type_error("unit", value)),
+        }
+    }
+}
+
+/// `option`
+impl<T: ToMicheline> ToMicheline for Option<T> {
+    fn to_micheline(&self) -> Micheline {
+        match self {
+            Some(value) => Micheline::prim("Some", vec![value.to_micheline()]),
+            None => Micheline::prim("None", Vec::new()),
+        }
+    }
+}
+
+impl<T: FromMicheline> FromMicheline for Option<T> {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match (value.prim_args("Some"), value.prim_args("None")) {
+            (Some([inner]), _) => Ok(Some(T::from_micheline(inner)?)),
+            (_, Some([])) => Ok(None),
+            _ => Err(type_error("option", value)),
+        }
+    }
+}
+
+/// `pair`
+impl<A: ToMicheline, B: ToMicheline> ToMicheline for (A, B) {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::pair(self.0.to_micheline(), self.1.to_micheline())
+    }
+}
+
+/// `pair`, also accepting the flattened comb `Pair a b c ...` as `(a, Pair b c ...)`
+impl<A: FromMicheline, B: FromMicheline> FromMicheline for (A, B) {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value.prim_args("Pair") {
+            Some([left, right]) => Ok((A::from_micheline(left)?, B::from_micheline(right)?)),
+            Some([left, rest @ ..]) if rest.len() > 1 => {
+                Ok((A::from_micheline(left)?, B::from_micheline(&Micheline::prim("Pair", rest.to_vec()))?))
+            }
+            _ => Err(type_error("pair", value)),
+        }
+    }
+}
+
+/// An `address`: an implicit account or `KT1` contract, with an optional `%entrypoint`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address(String);
+
+impl Address {
+    /// Validate an address
+    pub fn new(address: &str) -> SparkResult<Self> {
+        let contract = address.split_once('%').map_or(address, |(contract, _)| contract);
+        forge_contract(contract)?;
+        Ok(Address(address.to_string()))
+    }
+
+    /// The base58 address
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ToMicheline for Address {
+    fn to_micheline(&self) -> Micheline {
+        Micheline::String(self.0.clone())
+    }
+}
+
+/// Both the readable (`string`) and optimized (`bytes`) forms
+impl FromMicheline for Address {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::String(address) => Address::new(address),
+            Micheline::Bytes(bytes) if bytes.len() >= 22 => {
+                let contract = decode_contract(&mut Reader::new(&bytes[..22]))?;
+                match &bytes[22..] {
+                    [] => Ok(Address(contract)),
+                    entrypoint => std::str::from_utf8(entrypoint)
+                        .map(|entrypoint| Address(format!("{}%{}", contract, entrypoint)))
+                        .map_err(|_| type_error("address", value)),
+                }
+            }
+            other => Err(type_error("address", other)),
+        }
+    }
+}
+
+/// A `bls12_381_fr` scalar, as 32 little-endian bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bls12381Fr(pub Fr);
+
+impl ToMicheline for Bls12381Fr {
+    fn to_micheline(&self) -> Micheline {
+        let mut bytes = Vec::with_capacity(32);
+        self.0.serialize_compressed(&mut bytes).expect("serializing to a vector cannot fail");
+        Micheline::Bytes(bytes)
+    }
+}
+
+/// Bytes, or an integer literal reduced modulo the group order
+impl FromMicheline for Bls12381Fr {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        match value {
+            Micheline::Bytes(bytes) if bytes.len() <= 32 => {
+                let mut padded = bytes.clone();
+                padded.resize(32, 0);
+                Fr::deserialize_compressed(padded.as_slice())
+                    .map(Bls12381Fr)
+                    .map_err(|_| type_error("bls12_381_fr", value))
+            }
+            Micheline::Int(int) => {
+                let order = BigInt::from(BigUint::from_bytes_le(&fr_modulus()));
+                let reduced = ((int % &order) + &order) % &order;
+                let (_, mut bytes) = reduced.to_bytes_le();
+                bytes.resize(32, 0);
+                Fr::deserialize_compressed(bytes.as_slice())
+                    .map(Bls12381Fr)
+                    .map_err(|_| type_error("bls12_381_fr", value))
+            }
+            other => Err(type_error("bls12_381_fr", other)),
+        }
+    }
+}
+
+/// A `bls12_381_g1` point, as 96 bytes: big-endian `x` then `y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bls12381G1(pub G1Affine);
+
+impl ToMicheline for Bls12381G1 {
+    fn to_micheline(&self) -> Micheline {
+        let bytes = match self.0.xy() {
+            Some((x, y)) => [fq_to_be(x), fq_to_be(y)].concat(),
+            None => infinity(96),
+        };
+        Micheline::Bytes(bytes)
+    }
+}
+
+/// Rejects points off the curve or outside the prime-order subgroup
+impl FromMicheline for Bls12381G1 {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        let bytes = point_bytes(value, 96, "bls12_381_g1")?;
+        if bytes == infinity(96) {
+            return Ok(Bls12381G1(G1Affine::zero()));
+        }
+        let x = fq_from_be(&bytes[..48]).ok_or_else(|| type_error("bls12_381_g1", value))?;
+        let y = fq_from_be(&bytes[48..]).ok_or_else(|| type_error("bls12_381_g1", value))?;
+        let point = G1Affine::new_unchecked(x, y);
+        if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(type_error("bls12_381_g1", value));
+        }
+        Ok(Bls12381G1(point))
+    }
+}
+
+/// A `bls12_381_g2` point, as 192 bytes: `x` then `y`, each as the
+/// big-endian imaginary part followed by the real part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bls12381G2(pub G2Affine);
+
+impl ToMicheline for Bls12381G2 {
+    fn to_micheline(&self) -> Micheline {
+        let bytes = match self.0.xy() {
+            Some((x, y)) => [fq2_to_be(x), fq2_to_be(y)].concat(),
+            None => infinity(192),
+        };
+        Micheline::Bytes(bytes)
+    }
+}
+
+/// Rejects points off the curve or outside the prime-order subgroup
+impl FromMicheline for Bls12381G2 {
+    fn from_micheline(value: &Micheline) -> SparkResult<Self> {
+        let bytes = point_bytes(value, 192, "bls12_381_g2")?;
+        if bytes == infinity(192) {
+            return Ok(Bls12381G2(G2Affine::zero()));
+        }
+        let x = fq2_from_be(&bytes[..96]).ok_or_else(|| type_error("bls12_381_g2", value))?;
+        let y = fq2_from_be(&bytes[96..]).ok_or_else(|| type_error("bls12_381_g2", value))?;
+        let point = G2Affine::new_unchecked(x, y);
+        if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(type_error("bls12_381_g2", value));
+        }
+        Ok(Bls12381G2(point))
+    }
+}
+
+/// Point bytes of the expected length, without the compression flag
+fn point_bytes<'a>(value: &'a Micheline, len: usize, what: &str) -> SparkResult<&'a [u8]> {
+    match value {
+        Micheline::Bytes(bytes) if bytes.len() == len && bytes[0] & 0x80 == 0 => Ok(bytes),
+        other => Err(type_error(what, other)),
+    }
+}
+
+/// Encoding of the point at infinity: the infinity flag, then zeros
+fn infinity(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    bytes[0] = 0x40;
+    bytes
+}
+
+fn fr_modulus() -> Vec<u8> {
+    use ark_ff::{BigInteger, PrimeField};
+    Fr::MODULUS.to_bytes_le()
+}
+
+fn fq_to_be(value: &Fq) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(48);
+    value.serialize_compressed(&mut bytes).expect("serializing to a vector cannot fail");
+    bytes.reverse();
+    bytes
+}
+
+fn fq_from_be(bytes: &[u8]) -> Option<Fq> {
+    let mut le = bytes.to_vec();
+    le.reverse();
+    Fq::deserialize_compressed(le.as_slice()).ok()
+}
+
+fn fq2_to_be(value: &Fq2) -> Vec<u8> {
+    [fq_to_be(&value.c1), fq_to_be(&value.c0)].concat()
+}
+
+fn fq2_from_be(bytes: &[u8]) -> Option<Fq2> {
+    Some(Fq2::new(fq_from_be(&bytes[48..])?, fq_from_be(&bytes[..48])?))
+}
+
+pub(crate) fn write_len_prefixed(out: &mut Vec<u8>, bytes: &[u8]) -> SparkResult<()> {
+    let len = u32::try_from(bytes.len()).map_err(|_| micheline_error("Value too large to forge"))?;
+    out.extend(len.to_be_bytes());
+    out.extend(bytes);
+    Ok(())
+}
+
+/// Write an unsigned zarith number: 7 bits per byte, least significant first
+pub(crate) fn write_zarith_n(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Write a signed zarith number: the first byte holds the sign and 6 bits
+fn write_zarith(out: &mut Vec<u8>, n: &BigInt) {
+    let (sign, magnitude) = n.clone().into_parts();
+    let mut bits = BitReader::new(magnitude.to_bytes_le());
+    let mut byte = bits.take(6) | if sign == Sign::Minus { 0x40 } else { 0 };
+    while !bits.is_exhausted() {
+        out.push(byte | 0x80);
+        byte = bits.take(7);
+    }
+    out.push(byte);
+}
+
+/// Reads the bits of a little-endian number, least significant first
+struct BitReader {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl BitReader {
+    fn new(bytes: Vec<u8>) -> Self {
+        BitReader { bytes, position: 0 }
+    }
+
+    fn take(&mut self, count: usize) -> u8 {
+        let mut value = 0u8;
+        for i in 0..count {
+            let bit = self.bytes.get((self.position + i) / 8).map_or(0, |b| (b >> ((self.position + i) % 8)) & 1);
+            value |= bit << i;
+        }
+        self.position += count;
+        value
+    }
+
+    /// Whether every remaining bit is zero
+    fn is_exhausted(&self) -> bool {
+        (self.position..self.bytes.len() * 8).all(|i| (self.bytes[i / 8] >> (i % 8)) & 1 == 0)
+    }
+}
+
+/// Cursor over binary-encoded data
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> SparkResult<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(micheline_error("Unexpected end of forged bytes"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn byte(&mut self) -> SparkResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> SparkResult<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    pub(crate) fn zarith_n(&mut self) -> SparkResult<u64> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                break;
+            }
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(micheline_error("Zarith number does not fit in 64 bits"))
+    }
+
+    fn zarith(&mut self) -> SparkResult<BigInt> {
+        let first = self.byte()?;
+        let sign = if first & 0x40 != 0 { Sign::Minus } else { Sign::Plus };
+        let mut magnitude = BigUint::from(first & 0x3f);
+        let mut shift = 6;
+        let mut byte = first;
+        while byte & 0x80 != 0 {
+            byte = self.byte()?;
+            magnitude |= BigUint::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+        Ok(BigInt::from_biguint(sign, magnitude))
+    }
+}
+
+fn type_error(expected: &str, value: &Micheline) -> SparkError {
+    micheline_error(format!("Expected {}, found {}", expected, value.to_json()))
+}
+
+fn micheline_error(message: impl Into<String>) -> SparkError {
+    SparkError::tezos_error(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_ec::AffineRepr;
+
+    #[test]
+    fn test_micheline_vectors() {
+        let vectors = [
+            (json!({ "int": "0" }), "0000"),
+            (json!({ "int": "-64" }), "00c001"),
+            (json!({ "int": "1000000000000000000000" }), "00808080eabbf1d6c9ebd801"),
+            (json!({ "string": "abc" }), "0100000003616263"),
+            (json!([{ "prim": "Unit" }, { "prim": "True" }]), "0200000004030b030a"),
+            (
+                json!({ "prim": "Pair", "args": [{ "int": "1" }, { "int": "2" }], "annots": ["%a"] }),
+                "080700010002000000022561",
+            ),
+            (
+                json!({ "prim": "Pair", "args": [{ "int": "1" }, { "int": "2" }, { "int": "3" }] }),
+                "09070000000600010002000300000000",
+            ),
+        ];
+        for (json, encoded) in vectors {
+            let value = Micheline::from_json(&json).unwrap();
+            assert_eq!(value.to_json(), json);
+            assert_eq!(hex::encode(value.encode().unwrap()), encoded, "{}", json);
+            assert_eq!(Micheline::decode(&hex::decode(encoded).unwrap()).unwrap(), value);
+        }
+
+        assert!(Micheline::prim("NotAPrimitive", Vec::new()).encode().is_err());
+        assert!(Micheline::from_json(&json!({ "int": "12x" })).is_err());
+        assert!(Micheline::from_json(&json!({ "prim": "Pair", "args": {} })).is_err());
+        assert!(Micheline::decode(&hex::decode("000000").unwrap()).is_err());
+        assert_eq!(hex::encode(0u64.to_micheline().pack().unwrap()), "050000");
+    }
+
+    #[test]
+    fn test_decode_depth_limit() {
+        // `Some (Some (... Unit))`, with `depth` levels above the Unit
+        let nested = |depth: usize| [[0x05, 0x09].repeat(depth), vec![0x03, 0x0b]].concat();
+
+        let value = Micheline::decode(&nested(MAX_DECODE_DEPTH)).unwrap();
+        assert_eq!(Micheline::decode(&value.encode().unwrap()).unwrap(), value);
+        assert!(Micheline::decode(&nested(MAX_DECODE_DEPTH + 1)).is_err());
+
+        // Deep sequences are rejected before they can exhaust the stack
+        let depth = 100_000u32;
+        let mut seq = Vec::new();
+        for level in 0..depth {
+            seq.push(0x02);
+            seq.extend_from_slice(&(5 * (depth - 1 - level) + 2).to_be_bytes());
+        }
+        seq.extend_from_slice(&[0x03, 0x0b]);
+        assert!(Micheline::decode(&seq).unwrap_err().to_string().contains("nested deeper"));
+    }
+
+    #[test]
+    fn test_typed_values() {
+        let value = ((vec![1u8, 2], 7u64), (Some(()), "spark")).to_micheline();
+        assert_eq!(
+            value.to_json(),
+            json!({ "prim": "Pair", "args": [
+                { "prim": "Pair", "args": [{ "bytes": "0102" }, { "int": "7" }] },
+                { "prim": "Pair", "args": [{ "prim": "Some", "args": [{ "prim": "Unit" }] }, { "string": "spark" }] }
+            ]})
+        );
+        let parsed: ((Vec<u8>, u64), (Option<()>, String)) = FromMicheline::from_micheline(&value).unwrap();
+        assert_eq!(parsed, ((vec![1, 2], 7), (Some(()), "spark".to_string())));
+
+        // Flattened combs read as nested pairs
+        let comb = Micheline::from_json(&json!({ "prim": "Pair", "args": [{ "int": "1" }, { "int": "2" }, { "int": "3" }] })).unwrap();
+        assert_eq!(<(u64, (u64, u64))>::from_micheline(&comb).unwrap(), (1, (2, 3)));
+
+        // Shapes and ranges are checked
+        assert!(u64::from_micheline(&Micheline::Int(BigInt::from(-1))).is_err());
+        assert!(u64::from_micheline(&Micheline::Bytes(vec![1])).is_err());
+        assert!(<Vec<u8>>::from_micheline(&Micheline::Int(BigInt::from(1))).is_err());
+        assert!(<(u64, u64)>::from_micheline(&Micheline::Seq(vec![])).is_err());
+        assert!(<Option<u64>>::from_micheline(&Micheline::prim("Some", vec![])).is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        let kt1 = Address::new("KT1M7dL5CKWHiZhoTZCMdEbX17z7nSvWkW9r").unwrap();
+        assert_eq!(kt1.to_micheline(), Micheline::String(kt1.to_string()));
+        assert!(Address::new("KT1TezosDummyAddressForPOC").is_err());
+
+        // Optimized form, with and without an entrypoint
+        let optimized = hex::decode("0189778772a26161782270ee7755f5d056d5e3069b00").unwrap();
+        assert_eq!(Address::from_micheline(&Micheline::Bytes(optimized.clone())).unwrap(), kt1);
+        let with_entrypoint = [optimized.as_slice(), b"deposit"].concat();
+        assert_eq!(
+            Address::from_micheline(&Micheline::Bytes(with_entrypoint)).unwrap().as_str(),
+            "KT1M7dL5CKWHiZhoTZCMdEbX17z7nSvWkW9r%deposit"
+        );
+        let tz1 = hex::decode("00006b82198cb179e8306c1bedd08f12dc863f328886").unwrap();
+        assert_eq!(
+            Address::from_micheline(&Micheline::Bytes(tz1)).unwrap().as_str(),
+            "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb"
+        );
+    }
+
+    #[test]
+    fn test_bls12_381() {
+        let one = Bls12381Fr(Fr::from(1u64)).to_micheline();
+        assert_eq!(one, Micheline::Bytes([vec![1u8], vec![0u8; 31]].concat()));
+        assert_eq!(Bls12381Fr::from_micheline(&Micheline::Bytes(vec![1])).unwrap().0, Fr::from(1u64));
+        assert_eq!(Bls12381Fr::from_micheline(&Micheline::Int(BigInt::from(-1))).unwrap().0, -Fr::from(1u64));
+        assert!(Bls12381Fr::from_micheline(&Micheline::Bytes(vec![0xff; 32])).is_err());
+
+        let g1 = Bls12381G1(G1Affine::generator()).to_micheline();
+        let Micheline::Bytes(bytes) = &g1 else { panic!("expected bytes") };
+        assert_eq!(hex::encode(&bytes[..8]), "17f1d3a73197d794");
+        assert_eq!(hex::encode(&bytes[48..56]), "08b3f481e3aaa0f1");
+        assert_eq!(Bls12381G1::from_micheline(&g1).unwrap().0, G1Affine::generator());
+        let zero = Bls12381G1(G1Affine::zero()).to_micheline();
+        assert_eq!(Bls12381G1::from_micheline(&zero).unwrap().0, G1Affine::zero());
+
+        let g2 = Bls12381G2(G2Affine::generator()).to_micheline();
+        let Micheline::Bytes(bytes) = &g2 else { panic!("expected bytes") };
+        assert_eq!(hex::encode(&bytes[..8]), "13e02b6052719f60");
+        assert_eq!(hex::encode(&bytes[48..56]), "024aa2b2f08f0a91");
+        assert_eq!(Bls12381G2::from_micheline(&g2).unwrap().0, G2Affine::generator());
+
+        // Off-curve points are rejected
+        let mut off_curve = bytes.clone();
+        off_curve[191] ^= 1;
+        assert!(Bls12381G2::from_micheline(&Micheline::Bytes(off_curve)).is_err());
+        assert!(Bls12381G1::from_micheline(&Micheline::Bytes(vec![0; 95])).is_err());
+    }
+}
@@ -1,14 +1,11 @@
 use serde::{Deserialize, Serialize};
 // use ark_crypto_primitives::sponge::poseidon::PoseidonSponge;
 // use ark_crypto_primitives::sponge::CryptographicSponge;
-use ark_ec::{AffineRepr, CurveGroup};
-use ark_ff::PrimeField;
-use std::ops::Mul;
 
 use crate::error::SparkResult;
 use crate::validation::{validate_secret, validate_value};
 use crate::secret::Secret;
-use crate::crypto::{self, SpendingProof};
+use crate::crypto::{self, DepositProof, SpendingProof};
 
 /// A Spark note representing a private value commitment.
 ///
@@ -66,6 +63,14 @@ impl SparkNote {
         &self.secret
     }
 
+    /// Prove that this note's commitment hides its value.
+    ///
+    /// Deposits reveal the value publicly; this proof lets the verifier check
+    /// the commitment against it without learning the secret.
+    pub fn prove_deposit(&self) -> SparkResult<DepositProof> {
+        crypto::prove_deposit(self.value, self.secret.as_bytes())
+    }
+
     /// Generate a ZK spending proof for this note.
     /// 
     /// Proves knowledge of the value and secret that open this note's commitment,
@@ -77,15 +82,7 @@ impl SparkNote {
         merkle_path: Vec<(Vec<u8>, bool)>,
     ) -> SparkResult<SpendingProof> {
         // We need the Jubjub commitment point for the circuit
-        let g = crypto::EdwardsAffine::generator();
-        let h_bytes = blake3::hash(b"SPARK_JUBJUB_H").as_bytes().to_vec();
-        let h_scalar = crypto::JubjubFr::from_le_bytes_mod_order(&h_bytes);
-        let h = crypto::EdwardsAffine::from(crypto::EdwardsProjective::from(g).mul(h_scalar));
-        
-        // Recompute the Jubjub commitment: C = v*G + s*H
-        let v_scalar = crypto::JubjubFr::from(self.value);
-        let s_scalar = crypto::JubjubFr::from_le_bytes_mod_order(self.secret.as_bytes());
-        let commitment_point = (crypto::EdwardsProjective::from(g).mul(v_scalar) + crypto::EdwardsProjective::from(h).mul(s_scalar)).into_affine();
+        let commitment_point = crypto::pedersen_commit_point(self.value, self.secret.as_bytes());
 
         crypto::generate_spending_proof(
             pk,
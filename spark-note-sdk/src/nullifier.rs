@@ -2,6 +2,8 @@
 //!
 //! This module provides functions for generating nullifiers from notes
 //! and checking if nullifiers have been spent, with batch operations support.
+//! The functions over `HashSet<Vec<u8>>` are kept for compatibility and
+//! delegate to its [`NullifierStore`] implementation.
 
 use std::collections::HashSet;
 use serde::{Deserialize, Serialize};
@@ -9,7 +11,7 @@ use serde::{Deserialize, Serialize};
 use crate::error::{NullifierErrorCode, SparkError, SparkResult};
 use crate::note::SparkNote;
 use crate::secret::Secret;
-use crate::validation::validate_nullifier;
+use crate::nullifier_store::NullifierStore;
 pub use crate::nullifier_type::Nullifier;
 
 pub fn generate_nullifier(_note: &SparkNote, secret: &Secret) -> Nullifier {
@@ -60,11 +62,64 @@ impl NullifierSet {
     pub fn len(&self) -> usize {
         self.spent_set.len()
     }
+
+    /// Check if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.spent_set.is_empty()
+    }
     
     /// Export all nullifiers as Vec<u8> (for compatibility)
     pub fn export(&self) -> Vec<Vec<u8>> {
         self.spent_set.iter().map(|n| n.to_vec()).collect()
     }
+
+    /// Iterate over the nullifiers, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &Nullifier> {
+        self.spent_set.iter()
+    }
+
+    /// Add every nullifier of `other`
+    ///
+    /// Merging is idempotent: merging the same set again adds nothing.
+    ///
+    /// # Returns
+    /// The number of nullifiers that were not in the set yet
+    pub fn merge(&mut self, other: &NullifierSet) -> usize {
+        other.iter().filter(|n| self.spent_set.insert(**n)).count()
+    }
+
+    /// Count and bytes allocated by the set
+    pub fn stats(&self) -> NullifierSetStats {
+        NullifierSetStats {
+            count: self.spent_set.len() as u64,
+            memory_usage_bytes: (std::mem::size_of::<Self>() as u64)
+                + hash_table_bytes(self.spent_set.capacity(), std::mem::size_of::<Nullifier>()),
+        }
+    }
+}
+
+/// Bytes allocated by a `HashSet` table with `capacity` slots of `slot_size`
+///
+/// The table keeps 1/8 of its buckets free (all but one below 8 buckets) and
+/// stores one control byte per bucket plus a trailing group of 16.
+pub(crate) fn hash_table_bytes(capacity: usize, slot_size: usize) -> u64 {
+    if capacity == 0 {
+        return 0;
+    }
+    let buckets = if capacity < 8 {
+        (capacity + 1).next_power_of_two()
+    } else {
+        (capacity * 8 / 7).next_power_of_two()
+    };
+    (buckets * (slot_size + 1) + 16) as u64
+}
+
+impl FromIterator<Nullifier> for NullifierSet {
+    fn from_iter<I: IntoIterator<Item = Nullifier>>(iter: I) -> Self {
+        NullifierSet {
+            spent_set: iter.into_iter().collect(),
+        }
+    }
 }
 
 impl Default for NullifierSet {
@@ -75,16 +130,11 @@ impl Default for NullifierSet {
 
 /// Checks if a nullifier has been spent (legacy API using Vec<u8>)
 ///
-/// This function is kept for backward compatibility but uses
-/// the efficient NullifierSet internally when possible.
+/// Malformed nullifiers are never spent; a failed lookup counts as spent.
 pub fn is_nullifier_spent(nullifier: &[u8], spent_set: &HashSet<Vec<u8>>) -> bool {
-    // Convert to Nullifier for efficient lookup
-    if let Ok(_n) = Nullifier::from_slice(nullifier) {
-        // Convert HashSet<Vec<u8>> to HashSet<Nullifier> for lookup
-        // This is not ideal but maintains backward compatibility
-        spent_set.contains(nullifier)
-    } else {
-        false
+    match Nullifier::from_slice(nullifier) {
+        Ok(nullifier) => NullifierStore::contains(spent_set, &nullifier).unwrap_or(true),
+        Err(_) => false,
     }
 }
 
@@ -95,7 +145,9 @@ pub fn is_nullifier_spent(nullifier: &[u8], spent_set: &HashSet<Vec<u8>>) -> boo
 /// * `spent_set` - A set of already spent nullifiers
 ///
 /// # Returns
-/// Vector of booleans indicating whether each nullifier is spent
+/// Vector of booleans indicating whether each nullifier is spent; malformed
+/// nullifiers are reported as unspent and, if the lookup fails, the others
+/// as spent
 ///
 /// # Example
 /// ```
@@ -113,9 +165,18 @@ pub fn check_multiple_nullifiers(
     nullifiers: &[Vec<u8>],
     spent_set: &HashSet<Vec<u8>>,
 ) -> Vec<bool> {
-    nullifiers
+    let parsed: Vec<Option<Nullifier>> = nullifiers
         .iter()
-        .map(|n| is_nullifier_spent(n, spent_set))
+        .map(|n| Nullifier::from_slice(n).ok())
+        .collect();
+    let valid: Vec<Nullifier> = parsed.iter().flatten().copied().collect();
+    let mut spent = spent_set
+        .contains_batch(&valid)
+        .unwrap_or_else(|_| vec![true; valid.len()])
+        .into_iter();
+    parsed
+        .iter()
+        .map(|n| n.is_some() && spent.next().unwrap_or(true))
         .collect()
 }
 
@@ -127,7 +188,8 @@ pub fn check_multiple_nullifiers(
 ///
 /// # Returns
 /// * `Ok(())` if all nullifiers were successfully marked
-/// * `Err(SparkError)` if any nullifier is invalid or already spent
+/// * `Err(SparkError)` if any nullifier is invalid, already spent or
+///   repeated; the set is then left unchanged
 ///
 /// # Example
 /// ```
@@ -144,20 +206,11 @@ pub fn mark_multiple_as_spent(
     nullifiers: &[Vec<u8>],
     spent_set: &mut HashSet<Vec<u8>>,
 ) -> SparkResult<()> {
-    for nullifier in nullifiers {
-        validate_nullifier(nullifier)?;
-        
-        if spent_set.contains(nullifier) {
-            return Err(SparkError::nullifier_error(
-                NullifierErrorCode::AlreadySpent,
-                "One or more nullifiers are already spent",
-            ));
-        }
-        
-        spent_set.insert(nullifier.clone());
-    }
-    
-    Ok(())
+    let nullifiers = nullifiers
+        .iter()
+        .map(|n| Nullifier::from_slice(n))
+        .collect::<SparkResult<Vec<_>>>()?;
+    spent_set.insert_batch(&nullifiers)
 }
 
 /// Marks a nullifier as spent with validation
@@ -173,16 +226,12 @@ pub fn mark_as_spent(
     nullifier: &[u8],
     spent_set: &mut HashSet<Vec<u8>>,
 ) -> SparkResult<()> {
-    validate_nullifier(nullifier)?;
-    
-    if spent_set.contains(nullifier) {
+    if !NullifierStore::insert(spent_set, Nullifier::from_slice(nullifier)?)? {
         return Err(SparkError::nullifier_error(
             NullifierErrorCode::AlreadySpent,
             "Nullifier is already spent",
         ));
     }
-    
-    spent_set.insert(nullifier.to_vec());
     Ok(())
 }
 
@@ -206,6 +255,29 @@ pub struct NullifierSetStats {
     pub memory_usage_bytes: u64,
 }
 
+/// Outcome of reconciling the local spent set with the chain
+///
+/// See [`NoteManager::reconcile_spent_nullifiers`](crate::manager::NoteManager::reconcile_spent_nullifiers).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconcileReport {
+    /// Owned notes spent on chain (for example from another device) that
+    /// were not spent locally, and have now been marked as spent
+    pub marked_spent: Vec<String>,
+    /// Chain nullifiers that were not yet in the local spent set
+    pub nullifiers_imported: usize,
+    /// Owned notes spent locally whose nullifier is not on chain
+    pub missing_note_spends: Vec<String>,
+    /// Other nullifiers in the local spent set that are not on chain
+    pub missing_nullifiers: Vec<Vec<u8>>,
+}
+
+impl ReconcileReport {
+    /// Whether the local state claims spends the chain does not know about
+    pub fn has_local_only_spends(&self) -> bool {
+        !self.missing_note_spends.is_empty() || !self.missing_nullifiers.is_empty()
+    }
+}
+
 /// Gets statistics about a nullifier set
 ///
 /// # Arguments
@@ -214,15 +286,7 @@ pub struct NullifierSetStats {
 /// # Returns
 /// Statistics about the set
 pub fn get_nullifier_set_stats(spent_set: &HashSet<Vec<u8>>) -> NullifierSetStats {
-    let count = spent_set.len() as u64;
-    // Estimate: each nullifier is 32 bytes + overhead
-    // HashSet overhead is roughly 8 bytes per entry
-    let memory_usage_bytes = count * (32 + 8);
-    
-    NullifierSetStats {
-        count,
-        memory_usage_bytes,
-    }
+    NullifierStore::stats(spent_set)
 }
 
 #[cfg(test)]
@@ -321,6 +385,22 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_mark_multiple_as_spent_is_atomic() {
+        let mut spent_set: HashSet<Vec<u8>> = HashSet::new();
+        spent_set.insert(vec![2; 32]);
+
+        assert!(mark_multiple_as_spent(&[vec![1; 32], vec![2; 32]], &mut spent_set).is_err());
+        assert!(mark_multiple_as_spent(&[vec![3; 32], vec![3; 32]], &mut spent_set).is_err());
+        assert!(mark_multiple_as_spent(&[vec![4; 32], vec![5; 31]], &mut spent_set).is_err());
+        assert_eq!(spent_set.len(), 1);
+
+        let results = check_multiple_nullifiers(&[vec![2; 32], vec![9; 3], vec![1; 32]], &spent_set);
+        assert_eq!(results, vec![true, false, false]);
+        let results = check_multiple_nullifiers(&[vec![9; 3], vec![1; 32], vec![2; 32]], &spent_set);
+        assert_eq!(results, vec![false, false, true]);
+    }
+
     #[test]
     fn test_mark_as_spent() {
         let mut spent_set: HashSet<Vec<u8>> = HashSet::new();
@@ -361,6 +441,20 @@ mod tests {
         assert!(stats.memory_usage_bytes > 0);
     }
 
+    #[test]
+    fn test_nullifier_set_stats_follow_allocation() {
+        let mut set = NullifierSet::new();
+        assert_eq!(set.stats().count, 0);
+        for i in 0..100u8 {
+            set.add(Nullifier::new([i; 32]));
+        }
+
+        let stats = set.stats();
+        assert_eq!(stats.count, 100);
+        // At least 128 buckets of 32-byte slots plus their control bytes
+        assert!(stats.memory_usage_bytes >= 128 * 33);
+    }
+
     #[test]
     fn test_full_workflow() {
         use crate::secret::Secret;
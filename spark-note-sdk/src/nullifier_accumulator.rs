@@ -0,0 +1,406 @@
+//! Authenticated nullifier set
+//!
+//! A client that only trusts a root cannot check a plain set. The
+//! [`NullifierAccumulator`] is a Poseidon Merkle tree over the spent
+//! nullifiers in increasing byte order, whose root also commits to the number
+//! of entries. Membership is proved with an authentication path; an unspent
+//! nullifier is proved absent by showing the two adjacent leaves around it
+//! (or the first or last leaf), which is only possible because the leaves are
+//! sorted. [`verify_membership`] and [`verify_non_membership`] check proofs
+//! against a root without the set.
+//!
+//! Leaves are `Poseidon(1, high half, low half)` of the nullifier, inner
+//! nodes `Poseidon(left, right)`, missing leaves zero, and the root
+//! `Poseidon(2, tree root, count)`. The tree has the smallest depth holding
+//! every entry.
+
+use ark_crypto_primitives::sponge::poseidon::{PoseidonConfig, PoseidonSponge};
+use ark_crypto_primitives::sponge::CryptographicSponge;
+use ark_ff::{PrimeField, Zero};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{setup_poseidon_config, BlsFr};
+use crate::error::{SparkError, SparkResult};
+use crate::nullifier::{Nullifier, NullifierSet};
+
+const LEAF_TAG: u64 = 1;
+const ROOT_TAG: u64 = 2;
+/// Deepest tree a proof may describe
+const MAX_DEPTH: usize = 64;
+
+/// Proof that one leaf sits at a position of the tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeafProof {
+    /// The nullifier stored in the leaf
+    pub nullifier: Vec<u8>,
+    /// Position of the leaf, in sorted order
+    pub position: u64,
+    /// Sibling hashes from the leaf up (serialized field elements)
+    pub siblings: Vec<Vec<u8>>,
+}
+
+/// Proof that a nullifier is in the set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MembershipProof {
+    /// Number of entries in the set
+    pub count: u64,
+    /// Path of the nullifier's leaf
+    pub leaf: LeafProof,
+}
+
+/// Proof that a nullifier is not in the set
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NonMembershipProof {
+    /// The set is empty
+    Empty,
+    /// The nullifier sorts before the first entry
+    BeforeFirst {
+        /// Number of entries in the set
+        count: u64,
+        /// Path of the first leaf
+        first: LeafProof,
+    },
+    /// The nullifier sorts after the last entry
+    AfterLast {
+        /// Number of entries in the set
+        count: u64,
+        /// Path of the last leaf
+        last: LeafProof,
+    },
+    /// The nullifier sorts between two adjacent entries
+    Between {
+        /// Number of entries in the set
+        count: u64,
+        /// Path of the entry just below the nullifier
+        lower: LeafProof,
+        /// Path of the entry just above the nullifier
+        upper: LeafProof,
+    },
+}
+
+/// Sorted Poseidon Merkle tree over a nullifier set
+#[derive(Debug, Clone)]
+pub struct NullifierAccumulator {
+    /// Entries in increasing byte order
+    leaves: Vec<Nullifier>,
+    /// `levels[h]` holds the nodes at height `h` (leaf hashes at 0)
+    levels: Vec<Vec<BlsFr>>,
+    poseidon: PoseidonConfig<BlsFr>,
+}
+
+impl NullifierAccumulator {
+    /// Creates an empty accumulator
+    pub fn new() -> Self {
+        Self::from_sorted(Vec::new())
+    }
+
+    /// Build the accumulator of `set`
+    pub fn from_set(set: &NullifierSet) -> Self {
+        let mut leaves: Vec<Nullifier> = set.iter().copied().collect();
+        leaves.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        Self::from_sorted(leaves)
+    }
+
+    /// Add nullifiers and rebuild the tree
+    ///
+    /// Inserting shifts every later leaf, so the tree is rebuilt; add
+    /// nullifiers in batches rather than one at a time.
+    ///
+    /// # Returns
+    /// The number of nullifiers that were not in the set yet
+    pub fn insert_batch<I>(&mut self, nullifiers: I) -> usize
+    where
+        I: IntoIterator<Item = Nullifier>,
+    {
+        let before = self.leaves.len();
+        let mut leaves = std::mem::take(&mut self.leaves);
+        leaves.extend(nullifiers);
+        leaves.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        leaves.dedup();
+        *self = Self::from_sorted(leaves);
+        self.leaves.len() - before
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> u64 {
+        self.leaves.len() as u64
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Whether a nullifier is in the set
+    pub fn contains(&self, nullifier: &Nullifier) -> bool {
+        self.search(nullifier).is_ok()
+    }
+
+    /// Root committing to the entries and their number
+    pub fn root(&self) -> BlsFr {
+        let tree_root = self.levels.last().and_then(|level| level.first()).copied().unwrap_or_else(BlsFr::zero);
+        hash(&self.poseidon, &[BlsFr::from(ROOT_TAG), tree_root, BlsFr::from(self.len())])
+    }
+
+    /// Root, serialized as expected by the verification functions
+    pub fn root_bytes(&self) -> Vec<u8> {
+        field_bytes(&self.root())
+    }
+
+    /// Prove that `nullifier` is in the set
+    ///
+    /// # Returns
+    /// * `Ok(MembershipProof)` if it is
+    /// * `Err(SparkError)` if it is not
+    pub fn prove_membership(&self, nullifier: &Nullifier) -> SparkResult<MembershipProof> {
+        let position = self.search(nullifier).map_err(|_| SparkError::OperationError {
+            message: format!("Nullifier {} is not in the set", nullifier),
+        })?;
+        Ok(MembershipProof { count: self.len(), leaf: self.leaf_proof(position) })
+    }
+
+    /// Prove that `nullifier` is not in the set
+    ///
+    /// # Returns
+    /// * `Ok(NonMembershipProof)` if it is not
+    /// * `Err(SparkError)` if it is
+    pub fn prove_non_membership(&self, nullifier: &Nullifier) -> SparkResult<NonMembershipProof> {
+        let insert_at = match self.search(nullifier) {
+            Ok(_) => {
+                return Err(SparkError::nullifier_error(
+                    crate::error::NullifierErrorCode::AlreadySpent,
+                    format!("Nullifier {} is in the set", nullifier),
+                ));
+            }
+            Err(insert_at) => insert_at,
+        };
+
+        let count = self.len();
+        Ok(match insert_at {
+            _ if count == 0 => NonMembershipProof::Empty,
+            0 => NonMembershipProof::BeforeFirst { count, first: self.leaf_proof(0) },
+            i if i == self.leaves.len() => NonMembershipProof::AfterLast { count, last: self.leaf_proof(i - 1) },
+            i => NonMembershipProof::Between {
+                count,
+                lower: self.leaf_proof(i - 1),
+                upper: self.leaf_proof(i),
+            },
+        })
+    }
+
+    fn from_sorted(leaves: Vec<Nullifier>) -> Self {
+        let poseidon = setup_poseidon_config();
+        let mut levels = vec![leaves.iter().map(|n| leaf_hash(&poseidon, n)).collect::<Vec<_>>()];
+        for _ in 0..depth_for(leaves.len() as u64) {
+            let below = levels.last().expect("levels start with the leaves");
+            let level = below
+                .chunks(2)
+                .map(|pair| hash(&poseidon, &[pair[0], pair.get(1).copied().unwrap_or_else(BlsFr::zero)]))
+                .collect();
+            levels.push(level);
+        }
+        NullifierAccumulator { leaves, levels, poseidon }
+    }
+
+    fn search(&self, nullifier: &Nullifier) -> Result<usize, usize> {
+        self.leaves.binary_search_by(|leaf| leaf.as_bytes().cmp(nullifier.as_bytes()))
+    }
+
+    fn leaf_proof(&self, position: usize) -> LeafProof {
+        let siblings = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(height, level)| {
+                let sibling = level.get((position >> height) ^ 1).copied().unwrap_or_else(BlsFr::zero);
+                field_bytes(&sibling)
+            })
+            .collect();
+
+        LeafProof {
+            nullifier: self.leaves[position].to_vec(),
+            position: position as u64,
+            siblings,
+        }
+    }
+}
+
+impl Default for NullifierAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that `nullifier` is in the set with root `root`
+pub fn verify_membership(root: &[u8], nullifier: &Nullifier, proof: &MembershipProof) -> bool {
+    let poseidon = setup_poseidon_config();
+    proof.leaf.nullifier == nullifier.as_bytes() && leaf_matches_root(&poseidon, root, proof.count, &proof.leaf)
+}
+
+/// Check that `nullifier` is not in the set with root `root`
+pub fn verify_non_membership(root: &[u8], nullifier: &Nullifier, proof: &NonMembershipProof) -> bool {
+    let poseidon = setup_poseidon_config();
+    let target = nullifier.as_bytes();
+    match proof {
+        NonMembershipProof::Empty => {
+            let empty = hash(&poseidon, &[BlsFr::from(ROOT_TAG), BlsFr::zero(), BlsFr::zero()]);
+            decode_field(root) == Some(empty)
+        }
+        NonMembershipProof::BeforeFirst { count, first } => {
+            first.position == 0 && target < first.nullifier.as_slice() && leaf_matches_root(&poseidon, root, *count, first)
+        }
+        NonMembershipProof::AfterLast { count, last } => {
+            *count > 0
+                && last.position == count - 1
+                && target > last.nullifier.as_slice()
+                && leaf_matches_root(&poseidon, root, *count, last)
+        }
+        NonMembershipProof::Between { count, lower, upper } => {
+            upper.position == lower.position + 1
+                && lower.nullifier.as_slice() < target
+                && target < upper.nullifier.as_slice()
+                && leaf_matches_root(&poseidon, root, *count, lower)
+                && leaf_matches_root(&poseidon, root, *count, upper)
+        }
+    }
+}
+
+/// Recompute the root from a leaf proof and compare it with `root`
+fn leaf_matches_root(poseidon: &PoseidonConfig<BlsFr>, root: &[u8], count: u64, proof: &LeafProof) -> bool {
+    let Ok(nullifier) = Nullifier::from_slice(&proof.nullifier) else {
+        return false;
+    };
+    let depth = depth_for(count);
+    if proof.position >= count || proof.siblings.len() != depth || depth > MAX_DEPTH {
+        return false;
+    }
+
+    let mut node = leaf_hash(poseidon, &nullifier);
+    for (height, sibling) in proof.siblings.iter().enumerate() {
+        let Some(sibling) = decode_field(sibling) else {
+            return false;
+        };
+        node = if (proof.position >> height) & 1 == 0 {
+            hash(poseidon, &[node, sibling])
+        } else {
+            hash(poseidon, &[sibling, node])
+        };
+    }
+
+    let expected = hash(poseidon, &[BlsFr::from(ROOT_TAG), node, BlsFr::from(count)]);
+    decode_field(root) == Some(expected)
+}
+
+/// Smallest depth whose tree holds `count` leaves
+fn depth_for(count: u64) -> usize {
+    if count <= 1 {
+        0
+    } else {
+        (64 - (count - 1).leading_zeros()) as usize
+    }
+}
+
+fn leaf_hash(poseidon: &PoseidonConfig<BlsFr>, nullifier: &Nullifier) -> BlsFr {
+    let bytes = nullifier.as_bytes();
+    hash(
+        poseidon,
+        &[
+            BlsFr::from(LEAF_TAG),
+            BlsFr::from_be_bytes_mod_order(&bytes[..16]),
+            BlsFr::from_be_bytes_mod_order(&bytes[16..]),
+        ],
+    )
+}
+
+fn hash(poseidon: &PoseidonConfig<BlsFr>, inputs: &[BlsFr]) -> BlsFr {
+    let mut sponge = PoseidonSponge::new(poseidon);
+    sponge.absorb(&inputs.to_vec());
+    sponge.squeeze_field_elements(1).pop().unwrap()
+}
+
+fn field_bytes(value: &BlsFr) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value.serialize_compressed(&mut bytes).expect("serialization should not fail");
+    bytes
+}
+
+fn decode_field(bytes: &[u8]) -> Option<BlsFr> {
+    BlsFr::deserialize_compressed(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nullifier(i: u8) -> Nullifier {
+        Nullifier::new([i; 32])
+    }
+
+    fn accumulator() -> NullifierAccumulator {
+        // Five entries: 10, 20, 30, 40, 50 (a tree of depth 3 with empty leaves)
+        let set: NullifierSet = (1..=5u8).map(|i| nullifier(i * 10)).collect();
+        NullifierAccumulator::from_set(&set)
+    }
+
+    #[test]
+    fn test_membership_proofs() {
+        let acc = accumulator();
+        let root = acc.root_bytes();
+
+        for i in 1..=5u8 {
+            let proof = acc.prove_membership(&nullifier(i * 10)).unwrap();
+            assert!(verify_membership(&root, &nullifier(i * 10), &proof));
+            assert!(!verify_membership(&root, &nullifier(i * 10 + 1), &proof));
+        }
+        assert!(acc.prove_membership(&nullifier(15)).is_err());
+
+        // A proof only holds against the root it was made for
+        let mut grown = acc.clone();
+        assert_eq!(grown.insert_batch([nullifier(60), nullifier(10)]), 1);
+        let proof = acc.prove_membership(&nullifier(30)).unwrap();
+        assert!(!verify_membership(&grown.root_bytes(), &nullifier(30), &proof));
+    }
+
+    #[test]
+    fn test_non_membership_proofs() {
+        let acc = accumulator();
+        let root = acc.root_bytes();
+
+        for target in [5u8, 15, 35, 49, 55, 255] {
+            let proof = acc.prove_non_membership(&nullifier(target)).unwrap();
+            assert!(verify_non_membership(&root, &nullifier(target), &proof), "target {}", target);
+        }
+        assert!(acc.prove_non_membership(&nullifier(20)).is_err());
+
+        let empty = NullifierAccumulator::new();
+        let proof = empty.prove_non_membership(&nullifier(1)).unwrap();
+        assert_eq!(proof, NonMembershipProof::Empty);
+        assert!(verify_non_membership(&empty.root_bytes(), &nullifier(1), &proof));
+        assert!(!verify_non_membership(&root, &nullifier(1), &proof));
+    }
+
+    #[test]
+    fn test_forged_non_membership_proofs_fail() {
+        let acc = accumulator();
+        let root = acc.root_bytes();
+
+        // Using a real proof for a spent nullifier
+        let proof = acc.prove_non_membership(&nullifier(25)).unwrap();
+        assert!(!verify_non_membership(&root, &nullifier(20), &proof));
+        assert!(!verify_non_membership(&root, &nullifier(30), &proof));
+
+        // Skipping a leaf: 10 and 30 are not adjacent
+        let lower = acc.prove_membership(&nullifier(10)).unwrap().leaf;
+        let upper = acc.prove_membership(&nullifier(30)).unwrap().leaf;
+        let skipped = NonMembershipProof::Between { count: 5, lower, upper };
+        assert!(!verify_non_membership(&root, &nullifier(20), &skipped));
+
+        // Claiming a shorter set, so that 40 would be the last entry
+        let last = acc.prove_membership(&nullifier(40)).unwrap().leaf;
+        let truncated = NonMembershipProof::AfterLast { count: 4, last: last.clone() };
+        assert!(!verify_non_membership(&root, &nullifier(50), &truncated));
+        let shifted = NonMembershipProof::AfterLast { count: 5, last };
+        assert!(!verify_non_membership(&root, &nullifier(50), &shifted));
+    }
+}
@@ -0,0 +1,601 @@
+//! Spent nullifier storage
+//!
+//! The [`NullifierStore`] trait is implemented by every spent set in the
+//! crate: [`NullifierSet`], the legacy `HashSet<Vec<u8>>` used by the free
+//! functions in [`nullifier`](crate::nullifier), [`SledNullifierStore`] and,
+//! with the `wasm` feature, the `WasmNullifierSet` exposed to JavaScript.
+//!
+//! [`NullifierSet`] keeps every nullifier in
+//! memory. [`SledNullifierStore`] keeps them in a sled tree instead and only
+//! holds a [`BloomFilter`] in memory, so most lookups of unspent nullifiers
+//! never touch the disk. The filter is persisted next to the tree: it is
+//! removed while the store is open and written back when the store is
+//! dropped, so after a crash it is rebuilt from the tree instead of being
+//! trusted while stale.
+
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::error::{NullifierErrorCode, SparkError, SparkResult};
+use crate::nullifier::{hash_table_bytes, Nullifier, NullifierSet, NullifierSetStats};
+
+/// Number of nullifiers a new filter is sized for
+pub const DEFAULT_FILTER_CAPACITY: u64 = 1 << 16;
+/// Target false positive rate of the filter
+pub const FILTER_FALSE_POSITIVE_RATE: f64 = 0.01;
+/// Nullifiers written per sled batch by [`SledNullifierStore::bulk_load`]
+const BULK_LOAD_BATCH: usize = 10_000;
+/// Key of the persisted filter in the filter tree
+const FILTER_KEY: &[u8] = b"bloom";
+const FILTER_MAGIC: &[u8; 4] = b"SPBF";
+
+/// A set of spent nullifiers
+///
+/// Batch inserts are atomic: if any nullifier of the batch is already spent,
+/// or appears twice in it, nothing is inserted.
+pub trait NullifierStore {
+    /// Whether a nullifier is in the store
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool>;
+
+    /// Whether each nullifier is in the store, in order
+    fn contains_batch(&self, nullifiers: &[Nullifier]) -> SparkResult<Vec<bool>> {
+        nullifiers.iter().map(|n| self.contains(n)).collect()
+    }
+
+    /// Add a nullifier, returning whether it was new
+    fn insert(&mut self, nullifier: Nullifier) -> SparkResult<bool>;
+
+    /// Add every nullifier of a batch, or none of them
+    ///
+    /// # Errors
+    /// `AlreadySpent` if a nullifier is in the store or repeated in the batch
+    fn insert_batch(&mut self, nullifiers: &[Nullifier]) -> SparkResult<()>;
+
+    /// Number of nullifiers in the store
+    fn len(&self) -> u64;
+
+    /// Whether the store is empty
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Count and bytes held in memory
+    fn stats(&self) -> NullifierSetStats;
+}
+
+impl NullifierStore for NullifierSet {
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        Ok(NullifierSet::contains(self, nullifier))
+    }
+
+    fn insert(&mut self, nullifier: Nullifier) -> SparkResult<bool> {
+        Ok(self.add(nullifier))
+    }
+
+    fn insert_batch(&mut self, nullifiers: &[Nullifier]) -> SparkResult<()> {
+        check_batch(nullifiers, |n| Ok(NullifierSet::contains(self, n)))?;
+        for nullifier in nullifiers {
+            self.add(*nullifier);
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        NullifierSet::len(self) as u64
+    }
+
+    fn stats(&self) -> NullifierSetStats {
+        NullifierSet::stats(self)
+    }
+}
+
+impl NullifierStore for HashSet<Vec<u8>> {
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        Ok(HashSet::contains(self, nullifier.as_bytes()))
+    }
+
+    fn insert(&mut self, nullifier: Nullifier) -> SparkResult<bool> {
+        Ok(HashSet::insert(self, nullifier.to_vec()))
+    }
+
+    fn insert_batch(&mut self, nullifiers: &[Nullifier]) -> SparkResult<()> {
+        check_batch(nullifiers, |n| Ok(HashSet::contains(self, n.as_bytes())))?;
+        self.extend(nullifiers.iter().map(|n| n.to_vec()));
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        HashSet::len(self) as u64
+    }
+
+    fn stats(&self) -> NullifierSetStats {
+        let table = hash_table_bytes(self.capacity(), std::mem::size_of::<Vec<u8>>());
+        let buffers: usize = self.iter().map(Vec::capacity).sum();
+        NullifierSetStats {
+            count: HashSet::len(self) as u64,
+            memory_usage_bytes: table + buffers as u64,
+        }
+    }
+}
+
+/// Reject a batch holding a spent or repeated nullifier
+fn check_batch<F>(nullifiers: &[Nullifier], mut spent: F) -> SparkResult<()>
+where
+    F: FnMut(&Nullifier) -> SparkResult<bool>,
+{
+    let mut seen = HashSet::with_capacity(nullifiers.len());
+    for nullifier in nullifiers {
+        if !seen.insert(*nullifier) || spent(nullifier)? {
+            return Err(SparkError::nullifier_error(
+                NullifierErrorCode::AlreadySpent,
+                "One or more nullifiers are already spent",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Bloom filter over nullifiers
+///
+/// Answers "definitely not present" or "possibly present". Bit positions are
+/// derived from a BLAKE3 hash of the nullifier, so nullifiers chosen by
+/// someone else cannot be crafted to collide more than random ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+    capacity: u64,
+}
+
+impl BloomFilter {
+    /// Creates a filter for `capacity` entries at [`FILTER_FALSE_POSITIVE_RATE`]
+    pub fn with_capacity(capacity: u64) -> Self {
+        let capacity = capacity.max(1);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = ((capacity as f64) * -FILTER_FALSE_POSITIVE_RATE.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0) as u32;
+
+        BloomFilter {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+            capacity,
+        }
+    }
+
+    /// Number of entries the filter was sized for
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Add a nullifier
+    pub fn insert(&mut self, nullifier: &Nullifier) {
+        for bit in self.bit_positions(nullifier) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// `false` if the nullifier was never inserted, `true` if it may have been
+    pub fn may_contain(&self, nullifier: &Nullifier) -> bool {
+        self.bit_positions(nullifier)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Bytes held by the filter
+    pub fn memory_usage_bytes(&self) -> u64 {
+        (std::mem::size_of::<Self>() + self.bits.capacity() * std::mem::size_of::<u64>()) as u64
+    }
+
+    /// Serialize as magic, capacity, bit count, hash count and bits
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(24 + self.bits.len() * 8);
+        bytes.extend_from_slice(FILTER_MAGIC);
+        bytes.extend_from_slice(&self.capacity.to_le_bytes());
+        bytes.extend_from_slice(&self.num_bits.to_le_bytes());
+        bytes.extend_from_slice(&self.num_hashes.to_le_bytes());
+        for word in &self.bits {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Parse a filter written by [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(bytes: &[u8]) -> SparkResult<Self> {
+        let invalid = || SparkError::SerializationError {
+            message: "Invalid nullifier filter encoding".to_string(),
+        };
+        if bytes.len() < 24 || &bytes[..4] != FILTER_MAGIC {
+            return Err(invalid());
+        }
+        let capacity = u64::from_le_bytes(bytes[4..12].try_into().expect("length checked"));
+        let num_bits = u64::from_le_bytes(bytes[12..20].try_into().expect("length checked"));
+        let num_hashes = u32::from_le_bytes(bytes[20..24].try_into().expect("length checked"));
+        let words = &bytes[24..];
+        if num_hashes == 0 || num_bits == 0 || words.len() as u64 != num_bits.div_ceil(64) * 8 {
+            return Err(invalid());
+        }
+
+        Ok(BloomFilter {
+            bits: words.chunks_exact(8).map(|w| u64::from_le_bytes(w.try_into().expect("chunk of 8"))).collect(),
+            num_bits,
+            num_hashes,
+            capacity,
+        })
+    }
+
+    fn bit_positions(&self, nullifier: &Nullifier) -> impl Iterator<Item = u64> {
+        let hash = blake3::hash(nullifier.as_bytes());
+        let bytes = hash.as_bytes();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().expect("32-byte hash"));
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().expect("32-byte hash")) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+/// Filter and count kept in memory
+#[derive(Debug)]
+struct FilterState {
+    filter: BloomFilter,
+    count: u64,
+}
+
+#[derive(Debug)]
+struct StoreInner {
+    tree: sled::Tree,
+    filter_tree: sled::Tree,
+    state: RwLock<FilterState>,
+}
+
+impl Drop for StoreInner {
+    fn drop(&mut self) {
+        let state = self.state.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut bytes = state.count.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&state.filter.to_bytes());
+        // A filter that fails to persist is rebuilt on the next open
+        let _ = self.filter_tree.insert(FILTER_KEY, bytes);
+        let _ = self.filter_tree.flush();
+    }
+}
+
+/// Spent nullifier set stored in a sled tree, with a Bloom filter in memory
+///
+/// Clones share the same tree and filter, so every manager opened on one
+/// database should use clones of a single store.
+#[derive(Debug, Clone)]
+pub struct SledNullifierStore {
+    inner: Arc<StoreInner>,
+}
+
+impl SledNullifierStore {
+    /// Open the store kept in tree `name` of `db`
+    ///
+    /// The filter saved when the store was last dropped is reused; if there
+    /// is none, it is rebuilt by scanning the tree. Open each tree once per
+    /// process and share clones: a second store would not see the first
+    /// one's filter updates.
+    pub fn open(db: &sled::Db, name: &str) -> SparkResult<Self> {
+        let tree = db.open_tree(name).map_err(db_error)?;
+        let filter_tree = db.open_tree(format!("{}_filter", name)).map_err(db_error)?;
+
+        let saved = filter_tree.remove(FILTER_KEY).map_err(db_error)?;
+        filter_tree.flush().map_err(db_error)?;
+        let state = match saved.as_deref().and_then(|bytes| decode_state(bytes).ok()) {
+            Some(state) => state,
+            None => rebuild_state(&tree, tree.len() as u64 * 2)?,
+        };
+
+        Ok(SledNullifierStore {
+            inner: Arc::new(StoreInner { tree, filter_tree, state: RwLock::new(state) }),
+        })
+    }
+
+    /// Number of nullifiers in the store
+    pub fn len(&self) -> u64 {
+        self.read().count
+    }
+
+    /// Whether the store is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check whether a nullifier is in the store
+    pub fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        if !self.read().filter.may_contain(nullifier) {
+            return Ok(false);
+        }
+        self.inner.tree.contains_key(nullifier.as_bytes()).map_err(db_error)
+    }
+
+    /// Add a nullifier
+    ///
+    /// # Returns
+    /// * `Ok(true)` if it was not in the store yet
+    /// * `Err(SparkError)` on a database error
+    pub fn insert(&self, nullifier: Nullifier) -> SparkResult<bool> {
+        let mut state = self.write();
+        let previous = self.inner.tree.insert(nullifier.as_bytes(), &[]).map_err(db_error)?;
+        if previous.is_some() {
+            return Ok(false);
+        }
+        self.inner.tree.flush().map_err(db_error)?;
+        state.filter.insert(&nullifier);
+        state.count += 1;
+        self.grow_if_full(&mut state)?;
+        Ok(true)
+    }
+
+    /// Add every nullifier of a batch in one atomic write, or none of them
+    ///
+    /// # Errors
+    /// `AlreadySpent` if a nullifier is in the store or repeated in the
+    /// batch, or a database error
+    pub fn insert_batch(&self, nullifiers: &[Nullifier]) -> SparkResult<()> {
+        let mut state = self.write();
+        check_batch(nullifiers, |n| {
+            Ok(state.filter.may_contain(n) && self.inner.tree.contains_key(n.as_bytes()).map_err(db_error)?)
+        })?;
+
+        let mut batch = sled::Batch::default();
+        for nullifier in nullifiers {
+            batch.insert(nullifier.as_bytes(), &[]);
+        }
+        self.inner.tree.apply_batch(batch).map_err(db_error)?;
+        self.inner.tree.flush().map_err(db_error)?;
+
+        for nullifier in nullifiers {
+            state.filter.insert(nullifier);
+        }
+        state.count += nullifiers.len() as u64;
+        self.grow_if_full(&mut state)
+    }
+
+    /// Add many nullifiers, writing them in large batches
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of nullifiers that were not in the store yet
+    /// * `Err(SparkError)` on a database error; batches already written stay
+    pub fn bulk_load<I>(&self, nullifiers: I) -> SparkResult<usize>
+    where
+        I: IntoIterator<Item = Nullifier>,
+    {
+        let mut state = self.write();
+        let mut added = 0;
+        let mut pending: HashSet<Nullifier> = HashSet::with_capacity(BULK_LOAD_BATCH);
+        let mut nullifiers = nullifiers.into_iter().peekable();
+
+        // Size the filter for the whole load up front instead of rescanning
+        // the tree each time it fills up
+        let expected = state.count + nullifiers.size_hint().0 as u64;
+        if expected > state.filter.capacity() {
+            *state = rebuild_state(&self.inner.tree, expected * 2)?;
+        }
+
+        while nullifiers.peek().is_some() {
+            pending.clear();
+            let mut batch = sled::Batch::default();
+            for nullifier in nullifiers.by_ref().take(BULK_LOAD_BATCH) {
+                let known = state.filter.may_contain(&nullifier)
+                    && self.inner.tree.contains_key(nullifier.as_bytes()).map_err(db_error)?;
+                if !known && pending.insert(nullifier) {
+                    batch.insert(nullifier.as_bytes(), &[]);
+                }
+            }
+            self.inner.tree.apply_batch(batch).map_err(db_error)?;
+            for nullifier in &pending {
+                state.filter.insert(nullifier);
+            }
+            state.count += pending.len() as u64;
+            added += pending.len();
+            self.grow_if_full(&mut state)?;
+        }
+
+        self.inner.tree.flush().map_err(db_error)?;
+        Ok(added)
+    }
+
+    /// Every nullifier in the store, in byte order
+    pub fn iter(&self) -> impl Iterator<Item = SparkResult<Nullifier>> {
+        self.inner.tree.iter().keys().map(|key| {
+            let key = key.map_err(|e| SparkError::SerializationError {
+                message: format!("Database read error: {}", e),
+            })?;
+            Nullifier::from_slice(&key)
+        })
+    }
+
+    /// Count and bytes held in memory (the filter; entries stay on disk)
+    pub fn stats(&self) -> NullifierSetStats {
+        let state = self.read();
+        NullifierSetStats {
+            count: state.count,
+            memory_usage_bytes: (std::mem::size_of::<StoreInner>() as u64) + state.filter.memory_usage_bytes(),
+        }
+    }
+
+    /// Rebuild a larger filter once the current one is over capacity
+    fn grow_if_full(&self, state: &mut FilterState) -> SparkResult<()> {
+        if state.count > state.filter.capacity() {
+            *state = rebuild_state(&self.inner.tree, state.count * 2)?;
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, FilterState> {
+        self.inner.state.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, FilterState> {
+        self.inner.state.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl NullifierStore for SledNullifierStore {
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        SledNullifierStore::contains(self, nullifier)
+    }
+
+    fn insert(&mut self, nullifier: Nullifier) -> SparkResult<bool> {
+        SledNullifierStore::insert(self, nullifier)
+    }
+
+    fn insert_batch(&mut self, nullifiers: &[Nullifier]) -> SparkResult<()> {
+        SledNullifierStore::insert_batch(self, nullifiers)
+    }
+
+    fn len(&self) -> u64 {
+        SledNullifierStore::len(self)
+    }
+
+    fn stats(&self) -> NullifierSetStats {
+        SledNullifierStore::stats(self)
+    }
+}
+
+fn decode_state(bytes: &[u8]) -> SparkResult<FilterState> {
+    if bytes.len() < 8 {
+        return Err(SparkError::SerializationError {
+            message: "Invalid nullifier filter encoding".to_string(),
+        });
+    }
+    let count = u64::from_le_bytes(bytes[..8].try_into().expect("length checked"));
+    Ok(FilterState { filter: BloomFilter::from_bytes(&bytes[8..])?, count })
+}
+
+/// Scan the tree into a filter sized for at least `capacity` entries
+///
+/// Keys go into the filter as they are read, so callers size `capacity`
+/// from what they know of the tree rather than collecting it first.
+///
+/// # Errors
+/// A database error, or a key that is not a nullifier
+fn rebuild_state(tree: &sled::Tree, capacity: u64) -> SparkResult<FilterState> {
+    let mut filter = BloomFilter::with_capacity(capacity.max(DEFAULT_FILTER_CAPACITY));
+    let mut count = 0;
+    for key in tree.iter().keys() {
+        let key = key.map_err(db_error)?;
+        let nullifier = Nullifier::from_slice(&key).map_err(|_| SparkError::SerializationError {
+            message: format!("Nullifier store holds a malformed {}-byte key", key.len()),
+        })?;
+        filter.insert(&nullifier);
+        count += 1;
+    }
+    Ok(FilterState { filter, count })
+}
+
+fn db_error(e: sled::Error) -> SparkError {
+    SparkError::OperationError {
+        message: format!("Database error: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nullifier(i: u32) -> Nullifier {
+        let mut bytes = [0u8; 32];
+        bytes[..4].copy_from_slice(&i.to_be_bytes());
+        Nullifier::new(bytes)
+    }
+
+    /// Exercise the trait contract on any store
+    fn check_store(store: &mut dyn NullifierStore) {
+        assert!(store.insert(nullifier(1)).unwrap());
+        assert!(!store.insert(nullifier(1)).unwrap());
+
+        // A batch with a spent or repeated entry inserts nothing
+        assert!(store.insert_batch(&[nullifier(2), nullifier(1)]).is_err());
+        assert!(store.insert_batch(&[nullifier(3), nullifier(3)]).is_err());
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.contains_batch(&[nullifier(1), nullifier(2), nullifier(3)]).unwrap(), vec![true, false, false]);
+
+        store.insert_batch(&[nullifier(2), nullifier(3)]).unwrap();
+        assert_eq!(store.contains_batch(&[nullifier(2), nullifier(3), nullifier(4)]).unwrap(), vec![true, true, false]);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.stats().count, 3);
+    }
+
+    #[test]
+    fn test_every_store_implements_the_contract() {
+        check_store(&mut NullifierSet::new());
+        check_store(&mut HashSet::<Vec<u8>>::new());
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut store = SledNullifierStore::open(&db, "spent").unwrap();
+        check_store(&mut store);
+        assert!(SledNullifierStore::open(&db, "other").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bloom_filter_has_no_false_negatives() {
+        let mut filter = BloomFilter::with_capacity(1000);
+        for i in 0..1000 {
+            filter.insert(&nullifier(i));
+        }
+        assert!((0..1000).all(|i| filter.may_contain(&nullifier(i))));
+
+        let false_positives = (1000..11_000).filter(|i| filter.may_contain(&nullifier(*i))).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+
+        assert_eq!(BloomFilter::from_bytes(&filter.to_bytes()).unwrap(), filter);
+        assert!(BloomFilter::from_bytes(&filter.to_bytes()[..30]).is_err());
+    }
+
+    #[test]
+    fn test_store_insert_bulk_load_and_grow() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let store = SledNullifierStore::open(&db, "spent").unwrap();
+
+        assert!(store.insert(nullifier(1)).unwrap());
+        assert!(!store.insert(nullifier(1)).unwrap());
+        assert!(store.contains(&nullifier(1)).unwrap());
+        assert!(!store.contains(&nullifier(2)).unwrap());
+
+        // Duplicates, within the input and with the store, are skipped
+        let count = DEFAULT_FILTER_CAPACITY as u32 + 10;
+        let added = store.bulk_load((0..count).chain(0..5).map(nullifier)).unwrap();
+        assert_eq!(added, count as usize - 1);
+        assert_eq!(store.len(), count as u64);
+        assert!((0..count).all(|i| store.contains(&nullifier(i)).unwrap()));
+
+        let stats = store.stats();
+        assert_eq!(stats.count, count as u64);
+        // Grown past the default capacity, but far smaller than the entries
+        assert!(stats.memory_usage_bytes > BloomFilter::with_capacity(DEFAULT_FILTER_CAPACITY).memory_usage_bytes());
+        assert!(stats.memory_usage_bytes < count as u64 * 32);
+    }
+
+    #[test]
+    fn test_filter_is_persisted_and_rebuilt() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        {
+            let store = SledNullifierStore::open(&db, "spent").unwrap();
+            store.bulk_load((0..100).map(nullifier)).unwrap();
+            // While open, no saved filter can go stale
+            assert!(db.open_tree("spent_filter").unwrap().is_empty());
+        }
+        assert!(!db.open_tree("spent_filter").unwrap().is_empty());
+
+        let store = SledNullifierStore::open(&db, "spent").unwrap();
+        assert_eq!(store.len(), 100);
+        assert!(store.contains(&nullifier(42)).unwrap());
+        drop(store);
+
+        // Entries written behind the store's back are picked up by a rebuild
+        db.open_tree("spent").unwrap().insert(nullifier(500).as_bytes(), &[]).unwrap();
+        db.open_tree("spent_filter").unwrap().clear().unwrap();
+        let store = SledNullifierStore::open(&db, "spent").unwrap();
+        assert_eq!(store.len(), 101);
+        assert!(store.contains(&nullifier(500)).unwrap());
+        assert_eq!(store.iter().count(), 101);
+        drop(store);
+
+        // A key that is not a nullifier fails the rebuild instead of being skipped
+        db.open_tree("spent").unwrap().insert([1u8; 5], &[]).unwrap();
+        db.open_tree("spent_filter").unwrap().clear().unwrap();
+        assert!(SledNullifierStore::open(&db, "spent").is_err());
+    }
+}
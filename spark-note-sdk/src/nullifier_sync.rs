@@ -0,0 +1,312 @@
+//! Nullifier set diffs and incremental sync
+//!
+//! [`export_nullifier_set`](crate::serialization::export_nullifier_set) moves
+//! a whole set at once. This module lets replicas exchange only what changed:
+//! [`NullifierSetDiff`] compares two snapshots, and a [`NullifierJournal`]
+//! records when each nullifier was added, keyed by a monotonically increasing
+//! cursor (a block level, or a sequence number assigned by the primary), so
+//! it can answer "what changed since cursor N" with a [`NullifierDelta`].
+//!
+//! Spends are never undone, so applying diffs and deltas only ever adds
+//! nullifiers, and applying the same one twice is harmless.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{SparkError, SparkResult};
+use crate::nullifier::{Nullifier, NullifierSet};
+
+/// Current version of the [`NullifierDelta`] format
+pub const DELTA_FORMAT_VERSION: u32 = 1;
+/// Most nullifiers accepted in one delta
+pub const MAX_DELTA_NULLIFIERS: usize = 1_000_000;
+
+/// Difference between two snapshots of a nullifier set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NullifierSetDiff {
+    /// Nullifiers in the newer snapshot only, sorted
+    pub added: Vec<Nullifier>,
+    /// Nullifiers in the older snapshot only, sorted
+    ///
+    /// Spent sets only grow, so this is empty unless the snapshots disagree
+    /// (for example across a chain reorganization).
+    pub removed: Vec<Nullifier>,
+}
+
+impl NullifierSetDiff {
+    /// Compare an `older` snapshot with a `newer` one
+    pub fn between(older: &NullifierSet, newer: &NullifierSet) -> Self {
+        let mut added: Vec<Nullifier> = newer.iter().filter(|n| !older.contains(n)).copied().collect();
+        let mut removed: Vec<Nullifier> = older.iter().filter(|n| !newer.contains(n)).copied().collect();
+        added.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        removed.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        NullifierSetDiff { added, removed }
+    }
+
+    /// Whether the snapshots hold the same nullifiers
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Add the `added` nullifiers to `set`
+    ///
+    /// `removed` nullifiers are left in place, since a spend cannot be undone.
+    ///
+    /// # Returns
+    /// The number of nullifiers that were not in `set` yet
+    pub fn apply(&self, set: &mut NullifierSet) -> usize {
+        self.added.iter().filter(|n| set.add(**n)).count()
+    }
+}
+
+/// Nullifiers added after one cursor, up to another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifierDelta {
+    /// Format version
+    pub version: u32,
+    /// Cursor the delta starts after
+    pub from: u64,
+    /// Cursor the receiver is up to date with once the delta is applied
+    pub to: u64,
+    /// Nullifiers added in between (hex-encoded)
+    pub nullifiers: Vec<String>,
+}
+
+impl NullifierDelta {
+    /// Serialize to JSON
+    pub fn to_json(&self) -> SparkResult<String> {
+        serde_json::to_string(self).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize nullifier delta: {}", e),
+        })
+    }
+
+    /// Parse a delta and check its version, cursors and size
+    pub fn from_json(json: &str) -> SparkResult<Self> {
+        let delta: NullifierDelta = serde_json::from_str(json).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to deserialize nullifier delta: {}", e),
+        })?;
+        delta.validate()?;
+        Ok(delta)
+    }
+
+    /// Decode the nullifiers, checking the delta first
+    pub fn decode_nullifiers(&self) -> SparkResult<Vec<Nullifier>> {
+        self.validate()?;
+        self.nullifiers
+            .iter()
+            .map(|hex_nullifier| {
+                let bytes = hex::decode(hex_nullifier).map_err(|e| SparkError::SerializationError {
+                    message: format!("Invalid hex encoding: {}", e),
+                })?;
+                Nullifier::from_slice(&bytes)
+            })
+            .collect()
+    }
+
+    fn validate(&self) -> SparkResult<()> {
+        if self.version > DELTA_FORMAT_VERSION {
+            return Err(SparkError::SerializationError {
+                message: format!("Unsupported version: {} (current: {})", self.version, DELTA_FORMAT_VERSION),
+            });
+        }
+        if self.to < self.from {
+            return Err(SparkError::SerializationError {
+                message: format!("Nullifier delta ends at {} before it starts at {}", self.to, self.from),
+            });
+        }
+        if self.nullifiers.len() > MAX_DELTA_NULLIFIERS {
+            return Err(SparkError::SerializationError {
+                message: format!("Nullifier delta holds {} entries (limit {})", self.nullifiers.len(), MAX_DELTA_NULLIFIERS),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// A nullifier set that remembers the cursor at which each entry was added
+///
+/// A primary [`record`](Self::record)s nullifiers as it learns about them;
+/// replicas ask for [`changes_since`](Self::changes_since) their own
+/// [`cursor`](Self::cursor) and [`apply_delta`](Self::apply_delta) the
+/// answer. Replicas can serve further replicas in turn, as long as every
+/// cursor comes from the same source (for example chain block levels).
+#[derive(Debug, Clone, Default)]
+pub struct NullifierJournal {
+    set: NullifierSet,
+    entries: BTreeMap<u64, Vec<Nullifier>>,
+    cursor: u64,
+}
+
+impl NullifierJournal {
+    /// Creates an empty journal at cursor 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cursor the journal is up to date with
+    pub fn cursor(&self) -> u64 {
+        self.cursor
+    }
+
+    /// Every nullifier in the journal
+    pub fn set(&self) -> &NullifierSet {
+        &self.set
+    }
+
+    /// Record nullifiers added at `cursor`
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of nullifiers that were new
+    /// * `Err(SparkError)` if `cursor` is behind the journal's cursor
+    pub fn record<I>(&mut self, cursor: u64, nullifiers: I) -> SparkResult<usize>
+    where
+        I: IntoIterator<Item = Nullifier>,
+    {
+        if cursor < self.cursor {
+            return Err(SparkError::OperationError {
+                message: format!("Cursor {} is behind the journal's cursor {}", cursor, self.cursor),
+            });
+        }
+        self.cursor = cursor;
+        Ok(self.insert_at(cursor, nullifiers))
+    }
+
+    /// Nullifiers recorded after `since`
+    ///
+    /// At most `limit` nullifiers are returned, unless a single cursor holds
+    /// more; entries of one cursor are never split across deltas. When the
+    /// delta is cut short, its `to` is the last cursor it covers and the
+    /// receiver asks again from there.
+    pub fn changes_since(&self, since: u64, limit: usize) -> NullifierDelta {
+        let mut nullifiers = Vec::new();
+        let mut to = self.cursor.max(since);
+        for (&cursor, entries) in self.entries.range(since.saturating_add(1)..) {
+            if !nullifiers.is_empty() && nullifiers.len() + entries.len() > limit {
+                to = cursor - 1;
+                break;
+            }
+            nullifiers.extend(entries.iter().map(|n| hex::encode(n.as_bytes())));
+        }
+
+        NullifierDelta {
+            version: DELTA_FORMAT_VERSION,
+            from: since,
+            to,
+            nullifiers,
+        }
+    }
+
+    /// Apply a delta received from another journal
+    ///
+    /// Applying a delta twice, or one that overlaps what is already known,
+    /// is harmless.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - The number of nullifiers that were new
+    /// * `Err(SparkError)` if the delta is invalid or starts after this
+    ///   journal's cursor (applying it would leave a gap)
+    pub fn apply_delta(&mut self, delta: &NullifierDelta) -> SparkResult<usize> {
+        let nullifiers = delta.decode_nullifiers()?;
+        if delta.from > self.cursor {
+            return Err(SparkError::OperationError {
+                message: format!("Nullifier delta starts at {} but the journal is only at {}", delta.from, self.cursor),
+            });
+        }
+
+        // New entries go under the newest cursor so that replicas already
+        // past `delta.to` still receive them
+        self.cursor = self.cursor.max(delta.to);
+        Ok(self.insert_at(self.cursor, nullifiers))
+    }
+
+    fn insert_at<I>(&mut self, cursor: u64, nullifiers: I) -> usize
+    where
+        I: IntoIterator<Item = Nullifier>,
+    {
+        let new: Vec<Nullifier> = nullifiers.into_iter().filter(|n| self.set.add(*n)).collect();
+        let added = new.len();
+        if added > 0 {
+            self.entries.entry(cursor).or_default().extend(new);
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nullifier(i: u8) -> Nullifier {
+        Nullifier::new([i; 32])
+    }
+
+    #[test]
+    fn test_diff_and_merge_are_idempotent() {
+        let older: NullifierSet = [1, 2, 3].map(nullifier).into_iter().collect();
+        let newer: NullifierSet = [2, 3, 4, 5].map(nullifier).into_iter().collect();
+
+        let diff = NullifierSetDiff::between(&older, &newer);
+        assert_eq!(diff.added, vec![nullifier(4), nullifier(5)]);
+        assert_eq!(diff.removed, vec![nullifier(1)]);
+
+        let mut replica = older.clone();
+        assert_eq!(diff.apply(&mut replica), 2);
+        assert_eq!(diff.apply(&mut replica), 0);
+        assert_eq!(replica.len(), 5);
+
+        let mut merged = newer.clone();
+        assert_eq!(merged.merge(&older), 1);
+        assert_eq!(merged.merge(&older), 0);
+        assert!(NullifierSetDiff::between(&merged, &replica).is_empty());
+    }
+
+    #[test]
+    fn test_incremental_sync_between_journals() {
+        let mut primary = NullifierJournal::new();
+        primary.record(10, [1, 2].map(nullifier)).unwrap();
+        primary.record(12, [3].map(nullifier)).unwrap();
+        primary.record(15, [4, 5, 2].map(nullifier)).unwrap();
+        assert!(primary.record(14, [6].map(nullifier)).is_err());
+
+        // A small limit splits the changes at cursor boundaries
+        let mut replica = NullifierJournal::new();
+        let first = primary.changes_since(replica.cursor(), 2);
+        assert_eq!((first.from, first.to, first.nullifiers.len()), (0, 11, 2));
+        assert_eq!(replica.apply_delta(&first).unwrap(), 2);
+
+        let rest = NullifierDelta::from_json(&primary.changes_since(replica.cursor(), 100).to_json().unwrap()).unwrap();
+        assert_eq!((rest.from, rest.to), (11, 15));
+        assert_eq!(replica.apply_delta(&rest).unwrap(), 3);
+        assert_eq!(replica.apply_delta(&rest).unwrap(), 0);
+        assert_eq!(replica.cursor(), 15);
+        assert!(NullifierSetDiff::between(primary.set(), replica.set()).is_empty());
+
+        // Nothing new, and a delta that would leave a gap is refused
+        assert!(primary.changes_since(15, 100).nullifiers.is_empty());
+        primary.record(20, [7].map(nullifier)).unwrap();
+        let mut late = NullifierJournal::new();
+        assert!(late.apply_delta(&primary.changes_since(15, 100)).is_err());
+
+        // Replicas can serve further replicas
+        replica.apply_delta(&primary.changes_since(15, 100)).unwrap();
+        late.apply_delta(&replica.changes_since(0, 100)).unwrap();
+        assert_eq!(late.set().len(), 6);
+    }
+
+    #[test]
+    fn test_invalid_deltas_are_rejected() {
+        let mut delta = NullifierJournal::new().changes_since(0, 10);
+        delta.to = 5;
+        delta.from = 6;
+        assert!(NullifierDelta::from_json(&delta.to_json().unwrap()).is_err());
+
+        delta.from = 0;
+        delta.nullifiers = vec!["abcd".to_string()];
+        assert!(NullifierJournal::new().apply_delta(&delta).is_err());
+
+        delta.nullifiers.clear();
+        delta.version = DELTA_FORMAT_VERSION + 1;
+        assert!(NullifierDelta::from_json(&delta.to_json().unwrap()).is_err());
+    }
+}
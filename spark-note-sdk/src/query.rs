@@ -0,0 +1,480 @@
+//! Note queries and balance aggregation
+//!
+//! This module provides the [`NoteQuery`] builder used by
+//! [`NoteManager::query`](crate::manager::NoteManager::query) to filter, sort
+//! and paginate notes, together with the key layout of the sled secondary
+//! indexes that persistent managers use to answer those queries.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::manager::{NoteEntry, NoteMetadata, NoteSource, NoteState};
+
+/// Sled tree indexing note IDs by state
+pub(crate) const STATE_INDEX_TREE: &str = "idx_state";
+/// Sled tree indexing note IDs by value (big-endian, so byte order is numeric order)
+pub(crate) const VALUE_INDEX_TREE: &str = "idx_value";
+/// Sled tree indexing note IDs by asset
+pub(crate) const ASSET_INDEX_TREE: &str = "idx_asset";
+/// Sled tree indexing note IDs by label
+pub(crate) const LABEL_INDEX_TREE: &str = "idx_label";
+/// Sled tree indexing note IDs by tag (one entry per tag)
+pub(crate) const TAG_INDEX_TREE: &str = "idx_tag";
+
+/// All secondary index trees maintained for notes
+pub(crate) const INDEX_TREES: &[&str] = &[
+    STATE_INDEX_TREE,
+    VALUE_INDEX_TREE,
+    ASSET_INDEX_TREE,
+    LABEL_INDEX_TREE,
+    TAG_INDEX_TREE,
+];
+
+/// Field used to order query results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NoteSortField {
+    /// Order by note ID (lexicographic)
+    #[default]
+    Id,
+    /// Order by note value
+    Value,
+}
+
+/// Direction of the result ordering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SortOrder {
+    /// Smallest first
+    #[default]
+    Ascending,
+    /// Largest first
+    Descending,
+}
+
+/// Builder describing which notes to return and in what order
+///
+/// All filters are combined with AND. Results are ordered by note ID unless
+/// another ordering is requested, so pagination is stable across calls.
+///
+/// # Example
+/// ```
+/// use spark_note_sdk::query::{NoteQuery, NoteSortField, SortOrder};
+/// use spark_note_sdk::NoteState;
+///
+/// let query = NoteQuery::new()
+///     .with_state(NoteState::Unspent)
+///     .with_value_range(100, 10_000)
+///     .sort_by(NoteSortField::Value, SortOrder::Descending)
+///     .with_limit(20);
+/// assert_eq!(query.limit, Some(20));
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NoteQuery {
+    /// Only return notes in this state
+    pub state: Option<NoteState>,
+    /// Only return notes with at least this value
+    pub min_value: Option<u64>,
+    /// Only return notes with at most this value
+    pub max_value: Option<u64>,
+    /// Only return notes of this asset
+    pub asset: Option<String>,
+    /// Only return notes with this label
+    pub label: Option<String>,
+    /// Only return notes carrying all of these tags
+    pub tags: BTreeSet<String>,
+    /// Only return notes from this source
+    pub source: Option<NoteSource>,
+    /// Only return notes with all of these property values
+    pub properties: BTreeMap<String, String>,
+    /// Only return notes created at or after this Unix time
+    pub created_after: Option<u64>,
+    /// Only return notes created at or before this Unix time
+    pub created_before: Option<u64>,
+    /// Field used to order results
+    pub sort_field: NoteSortField,
+    /// Direction of the ordering
+    pub sort_order: SortOrder,
+    /// Number of matching notes to skip
+    pub offset: usize,
+    /// Maximum number of notes to return
+    pub limit: Option<usize>,
+}
+
+/// A page of query results
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteQueryResult {
+    /// Number of notes matching the filters, before pagination
+    pub total: usize,
+    /// The requested page of matching notes
+    pub notes: Vec<(String, NoteEntry)>,
+}
+
+impl NoteQuery {
+    /// Creates a query matching every note
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restricts results to notes in the given state
+    pub fn with_state(mut self, state: NoteState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Restricts results to notes worth at least `min`
+    pub fn with_min_value(mut self, min: u64) -> Self {
+        self.min_value = Some(min);
+        self
+    }
+
+    /// Restricts results to notes worth at most `max`
+    pub fn with_max_value(mut self, max: u64) -> Self {
+        self.max_value = Some(max);
+        self
+    }
+
+    /// Restricts results to notes worth between `min` and `max` (inclusive)
+    pub fn with_value_range(self, min: u64, max: u64) -> Self {
+        self.with_min_value(min).with_max_value(max)
+    }
+
+    /// Restricts results to notes of the given asset
+    pub fn with_asset(mut self, asset: impl Into<String>) -> Self {
+        self.asset = Some(asset.into());
+        self
+    }
+
+    /// Restricts results to notes carrying the given label
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Restricts results to notes carrying the given tag
+    ///
+    /// May be called repeatedly; notes must carry every requested tag.
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.insert(tag.into());
+        self
+    }
+
+    /// Restricts results to notes from the given source
+    pub fn with_source(mut self, source: NoteSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Restricts results to notes whose property `key` equals `value`
+    pub fn with_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.insert(key.into(), value.into());
+        self
+    }
+
+    /// Restricts results to notes created between `from` and `to` (inclusive)
+    ///
+    /// Notes without a creation time never match.
+    pub fn with_created_range(mut self, from: u64, to: u64) -> Self {
+        self.created_after = Some(from);
+        self.created_before = Some(to);
+        self
+    }
+
+    /// Sets the ordering of the results
+    pub fn sort_by(mut self, field: NoteSortField, order: SortOrder) -> Self {
+        self.sort_field = field;
+        self.sort_order = order;
+        self
+    }
+
+    /// Skips the first `offset` matching notes
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns at most `limit` notes
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Check whether a note satisfies every filter of this query
+    pub fn matches(&self, entry: &NoteEntry, metadata: &NoteMetadata) -> bool {
+        if let Some(state) = &self.state {
+            if &entry.state != state {
+                return false;
+            }
+        }
+        if let Some(min) = self.min_value {
+            if entry.note.value < min {
+                return false;
+            }
+        }
+        if let Some(max) = self.max_value {
+            if entry.note.value > max {
+                return false;
+            }
+        }
+        if self.asset.is_some() && metadata.asset != self.asset {
+            return false;
+        }
+        if self.label.is_some() && metadata.label != self.label {
+            return false;
+        }
+        if !self.tags.is_subset(&metadata.tags) {
+            return false;
+        }
+        if self.source.is_some() && metadata.source != self.source {
+            return false;
+        }
+        if self.properties.iter().any(|(k, v)| metadata.properties.get(k) != Some(v)) {
+            return false;
+        }
+        if self.created_after.is_some() || self.created_before.is_some() {
+            let Some(created_at) = metadata.created_at else {
+                return false;
+            };
+            if created_at < self.created_after.unwrap_or(0) || created_at > self.created_before.unwrap_or(u64::MAX) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Sort matching notes and cut out the requested page
+    pub(crate) fn sort_and_paginate(&self, mut notes: Vec<(String, NoteEntry)>) -> NoteQueryResult {
+        match self.sort_field {
+            NoteSortField::Id => notes.sort_by(|a, b| a.0.cmp(&b.0)),
+            NoteSortField::Value => notes.sort_by(|a, b| {
+                a.1.note.value.cmp(&b.1.note.value).then_with(|| a.0.cmp(&b.0))
+            }),
+        }
+        if self.sort_order == SortOrder::Descending {
+            notes.reverse();
+        }
+
+        let total = notes.len();
+        let notes = notes
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        NoteQueryResult { total, notes }
+    }
+
+    /// Pick the most selective secondary index able to narrow this query
+    ///
+    /// Returns `None` when no filter is backed by an index, in which case every
+    /// note has to be examined.
+    pub(crate) fn index_scan(&self) -> Option<IndexScan> {
+        if let Some(prefix) = self.label.as_deref().and_then(string_index_prefix) {
+            return Some(IndexScan::Prefix { tree: LABEL_INDEX_TREE, prefix });
+        }
+        if let Some(prefix) = self.asset.as_deref().and_then(string_index_prefix) {
+            return Some(IndexScan::Prefix { tree: ASSET_INDEX_TREE, prefix });
+        }
+        if let Some(prefix) = self.tags.iter().next().and_then(|tag| string_index_prefix(tag)) {
+            return Some(IndexScan::Prefix { tree: TAG_INDEX_TREE, prefix });
+        }
+        if let Some(state) = &self.state {
+            return Some(IndexScan::Prefix {
+                tree: STATE_INDEX_TREE,
+                prefix: vec![state_tag(state)],
+            });
+        }
+        if self.min_value.is_some() || self.max_value.is_some() {
+            return Some(IndexScan::ValueRange {
+                min: self.min_value.unwrap_or(0),
+                max: self.max_value.unwrap_or(u64::MAX),
+            });
+        }
+        None
+    }
+}
+
+/// A lookup into one of the secondary index trees
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum IndexScan {
+    /// All keys of `tree` starting with `prefix`
+    Prefix { tree: &'static str, prefix: Vec<u8> },
+    /// All value index keys between `min` and `max` (inclusive)
+    ValueRange { min: u64, max: u64 },
+}
+
+impl IndexScan {
+    /// Extract the note ID from a key found by this scan
+    pub(crate) fn id_from_key(&self, key: &[u8]) -> Option<String> {
+        let offset = match self {
+            IndexScan::Prefix { prefix, .. } => prefix.len(),
+            IndexScan::ValueRange { .. } => 8,
+        };
+        key.get(offset..)
+            .and_then(|id| String::from_utf8(id.to_vec()).ok())
+    }
+}
+
+/// Stable one-byte tag for a note state in the state index
+pub(crate) fn state_tag(state: &NoteState) -> u8 {
+    match state {
+        NoteState::Unspent => 0,
+        NoteState::Spent => 1,
+        NoteState::PendingSpend => 2,
+    }
+}
+
+/// Length-prefixed encoding so that no string prefix can collide with another
+///
+/// Returns `None` for strings longer than `u16::MAX` bytes, whose length does
+/// not fit the prefix. Such strings are rejected by
+/// [`NoteMetadata::validate`] and are never indexed, so queries for them fall
+/// back to a full scan.
+pub(crate) fn string_index_prefix(s: &str) -> Option<Vec<u8>> {
+    let len = u16::try_from(s.len()).ok()?;
+    let mut key = Vec::with_capacity(2 + s.len());
+    key.extend_from_slice(&len.to_be_bytes());
+    key.extend_from_slice(s.as_bytes());
+    Some(key)
+}
+
+/// Compute every index entry for a note
+///
+/// The returned `(tree, key)` pairs are what must exist in the index trees
+/// for the note to be found by queries.
+pub(crate) fn index_entries(
+    id: &str,
+    state: &NoteState,
+    value: u64,
+    metadata: &NoteMetadata,
+) -> Vec<(&'static str, Vec<u8>)> {
+    let with_id = |mut key: Vec<u8>| {
+        key.extend_from_slice(id.as_bytes());
+        key
+    };
+
+    let mut entries = vec![
+        (STATE_INDEX_TREE, with_id(vec![state_tag(state)])),
+        (VALUE_INDEX_TREE, with_id(value.to_be_bytes().to_vec())),
+    ];
+    if let Some(prefix) = metadata.asset.as_deref().and_then(string_index_prefix) {
+        entries.push((ASSET_INDEX_TREE, with_id(prefix)));
+    }
+    if let Some(prefix) = metadata.label.as_deref().and_then(string_index_prefix) {
+        entries.push((LABEL_INDEX_TREE, with_id(prefix)));
+    }
+    for prefix in metadata.tags.iter().filter_map(|tag| string_index_prefix(tag)) {
+        entries.push((TAG_INDEX_TREE, with_id(prefix)));
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::PublicNote;
+
+    fn entry(value: u64, state: NoteState) -> NoteEntry {
+        NoteEntry {
+            note: PublicNote { value, commitment: vec![0; 32] },
+            state,
+            nullifier: None,
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_matches_filters() {
+        let meta = NoteMetadata::new()
+            .with_asset("XTZ")
+            .with_label("savings")
+            .with_tag("cold")
+            .with_source(NoteSource::Deposit)
+            .with_created_at(1_000)
+            .with_property("memo", "rent");
+        let note = entry(500, NoteState::Unspent);
+
+        assert!(NoteQuery::new().matches(&note, &meta));
+        assert!(NoteQuery::new().with_value_range(100, 500).matches(&note, &meta));
+        assert!(!NoteQuery::new().with_min_value(501).matches(&note, &meta));
+        assert!(!NoteQuery::new().with_state(NoteState::Spent).matches(&note, &meta));
+        assert!(NoteQuery::new().with_asset("XTZ").with_label("savings").matches(&note, &meta));
+        assert!(!NoteQuery::new().with_label("other").matches(&note, &meta));
+        assert!(!NoteQuery::new().with_asset("USD").matches(&note, &NoteMetadata::default()));
+        assert!(NoteQuery::new().with_tag("cold").with_source(NoteSource::Deposit).matches(&note, &meta));
+        assert!(!NoteQuery::new().with_tag("cold").with_tag("hot").matches(&note, &meta));
+        assert!(!NoteQuery::new().with_source(NoteSource::Change).matches(&note, &meta));
+        assert!(NoteQuery::new().with_property("memo", "rent").matches(&note, &meta));
+        assert!(!NoteQuery::new().with_property("memo", "food").matches(&note, &meta));
+        assert!(NoteQuery::new().with_created_range(1_000, 2_000).matches(&note, &meta));
+        assert!(!NoteQuery::new().with_created_range(1_001, 2_000).matches(&note, &meta));
+        assert!(!NoteQuery::new().with_created_range(0, 2_000).matches(&note, &NoteMetadata::default()));
+    }
+
+    #[test]
+    fn test_sort_and_paginate() {
+        let notes = vec![
+            ("b".to_string(), entry(300, NoteState::Unspent)),
+            ("a".to_string(), entry(100, NoteState::Unspent)),
+            ("c".to_string(), entry(200, NoteState::Unspent)),
+        ];
+
+        let by_value = NoteQuery::new()
+            .sort_by(NoteSortField::Value, SortOrder::Descending)
+            .with_offset(1)
+            .with_limit(1)
+            .sort_and_paginate(notes.clone());
+        assert_eq!(by_value.total, 3);
+        assert_eq!(by_value.notes.len(), 1);
+        assert_eq!(by_value.notes[0].0, "c");
+
+        let by_id = NoteQuery::new().sort_and_paginate(notes);
+        let ids: Vec<_> = by_id.notes.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_index_scan_selection() {
+        assert_eq!(NoteQuery::new().index_scan(), None);
+
+        let scan = NoteQuery::new()
+            .with_state(NoteState::Unspent)
+            .with_label("x")
+            .index_scan()
+            .unwrap();
+        assert!(matches!(scan, IndexScan::Prefix { tree: LABEL_INDEX_TREE, .. }));
+
+        let scan = NoteQuery::new().with_min_value(10).index_scan().unwrap();
+        assert_eq!(scan, IndexScan::ValueRange { min: 10, max: u64::MAX });
+    }
+
+    #[test]
+    fn test_string_prefixes_do_not_collide() {
+        let meta_a = NoteMetadata::new().with_asset("ab");
+        let entries = index_entries("c", &NoteState::Unspent, 1, &meta_a);
+        let (_, asset_key) = entries.iter().find(|(t, _)| *t == ASSET_INDEX_TREE).unwrap();
+
+        // "abc" must not be found when scanning for asset "a" or "abc"
+        assert!(!asset_key.starts_with(&string_index_prefix("a").unwrap()));
+        assert!(!asset_key.starts_with(&string_index_prefix("abc").unwrap()));
+        assert!(asset_key.starts_with(&string_index_prefix("ab").unwrap()));
+
+        let scan = IndexScan::Prefix { tree: ASSET_INDEX_TREE, prefix: string_index_prefix("ab").unwrap() };
+        assert_eq!(scan.id_from_key(asset_key).as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_oversized_strings_are_not_indexed() {
+        // A 64 KiB string would wrap its u16 length and collide with "a"
+        let long = format!("a{}", "b".repeat(u16::MAX as usize));
+        assert!(string_index_prefix(&"x".repeat(u16::MAX as usize)).is_some());
+        assert_eq!(string_index_prefix(&long), None);
+
+        let meta = NoteMetadata::new().with_label(long.clone()).with_asset("ab");
+        let entries = index_entries("c", &NoteState::Unspent, 1, &meta);
+        assert!(entries.iter().all(|(t, _)| *t != LABEL_INDEX_TREE));
+
+        // The query falls back to the next usable index
+        let scan = NoteQuery::new().with_label(long).with_asset("ab").index_scan().unwrap();
+        assert!(matches!(scan, IndexScan::Prefix { tree: ASSET_INDEX_TREE, .. }));
+    }
+}
@@ -4,6 +4,8 @@
 //! secure random bytes using the `getrandom` crate.
 
 use getrandom::getrandom;
+use rand::SeedableRng;
+use rand_chacha::ChaChaRng;
 use crate::error::{SparkError, SparkResult};
 use crate::secret::Secret;
 
@@ -24,6 +26,19 @@ pub fn generate_random_bytes(len: usize) -> SparkResult<Vec<u8>> {
     Ok(bytes)
 }
 
+/// Create a cryptographically secure generator for proof randomness
+///
+/// The generator is a ChaCha20 stream seeded with [`generate_random_bytes`].
+///
+/// # Returns
+/// * `Ok(ChaChaRng)` - A freshly seeded generator
+/// * `Err(SparkError)` - If random generation fails
+pub fn secure_rng() -> SparkResult<ChaChaRng> {
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&generate_random_bytes(32)?);
+    Ok(ChaChaRng::from_seed(seed))
+}
+
 /// Generate a secret for a Spark note (32 bytes by default)
 ///
 /// # Returns
@@ -69,6 +84,14 @@ mod tests {
         assert_eq!(secret.len(), 16);
     }
 
+    #[test]
+    fn test_secure_rng() {
+        use rand::RngCore;
+        let a = secure_rng().unwrap().next_u64();
+        let b = secure_rng().unwrap().next_u64();
+        assert_ne!(a, b);
+    }
+
     #[test]
     fn test_generate_secret_uniqueness() {
         let s1 = generate_secret().unwrap();
@@ -55,26 +55,27 @@ impl std::fmt::Debug for Secret {
     }
 }
 
-// Custom serialization that doesn't expose the secret in serialized form
+// Secrets are never written by serde. Use `NoteManager::export_backup`,
+// which encrypts them, to move secrets between machines.
 impl Serialize for Secret {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, _serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        // Serialize as a placeholder - secrets should not be serialized
-        // In production, this should error or use encryption
-        serializer.serialize_bytes(&[])
+        Err(serde::ser::Error::custom(
+            "Secret cannot be serialized in clear text; use an encrypted wallet backup",
+        ))
     }
 }
 
 impl<'de> Deserialize<'de> for Secret {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    fn deserialize<D>(_deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
-        // Deserialize as empty - secrets should not be deserialized from untrusted sources
-        let _ = Vec::<u8>::deserialize(deserializer)?;
-        Ok(Secret::new(vec![]))
+        Err(serde::de::Error::custom(
+            "Secret cannot be deserialized from clear text; restore an encrypted wallet backup",
+        ))
     }
 }
 
@@ -111,5 +112,11 @@ mod tests {
         // Note: We can't directly verify memory was zeroized,
         // but the ZeroizeOnDrop trait ensures it will be
     }
-}
 
+    #[test]
+    fn test_secret_serde_refuses_clear_text() {
+        let secret = Secret::new(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(serde_json::to_string(&secret).is_err());
+        assert!(serde_json::from_str::<Secret>("[1,2,3,4,5,6,7,8]").is_err());
+    }
+}
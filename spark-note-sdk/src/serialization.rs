@@ -2,15 +2,31 @@
 //!
 //! This module provides functions for serializing and deserializing
 //! Spark Note data structures with versioning support.
+//!
+//! Nullifier sets have two formats: a JSON document
+//! ([`export_nullifier_set`]) and a compact binary stream
+//! ([`NullifierSetWriter`] / [`NullifierSetReader`]) laid out as
+//!
+//! ```text
+//! magic "SPNS" | version u32 LE | count u64 LE | count sorted 32-byte entries | BLAKE3 of everything before
+//! ```
 
 use std::collections::HashSet;
+use std::io::{Read, Write};
 use serde::{Deserialize, Serialize};
 
 use crate::error::{SparkError, SparkResult};
+use crate::nullifier::{Nullifier, NullifierSet};
 use crate::validation::validate_nullifier;
 
 /// Current data format version
 pub const CURRENT_VERSION: u32 = 1;
+/// Magic bytes opening a binary nullifier set
+pub const BINARY_MAGIC: &[u8; 4] = b"SPNS";
+/// Current binary nullifier set version
+pub const BINARY_VERSION: u32 = 1;
+/// Default largest number of entries a [`NullifierSetReader`] accepts
+pub const DEFAULT_MAX_BINARY_ENTRIES: u64 = 50_000_000;
 
 /// Versioned nullifier set for export/import
 #[derive(Debug, Clone, Serialize, Deserialize)] // uniffi::Record
@@ -31,7 +47,7 @@ pub struct NullifierSetExport {
 pub fn export_nullifier_set(spent_set: &HashSet<Vec<u8>>) -> SparkResult<String> {
     let nullifiers: Vec<String> = spent_set
         .iter()
-        .map(|n| hex::encode(n))
+        .map(hex::encode)
         .collect();
     
     let export = NullifierSetExport {
@@ -84,6 +100,216 @@ pub fn import_nullifier_set(json: &str) -> SparkResult<HashSet<Vec<u8>>> {
     Ok(spent_set)
 }
 
+/// Streaming writer for the binary nullifier set format
+///
+/// The entry count is written up front, so it must be known before the
+/// first entry; entries must then be written in strictly increasing byte
+/// order, and [`finish`](Self::finish) appends the checksum.
+pub struct NullifierSetWriter<W: Write> {
+    writer: W,
+    hasher: blake3::Hasher,
+    expected: u64,
+    written: u64,
+    last: Option<Nullifier>,
+}
+
+impl<W: Write> NullifierSetWriter<W> {
+    /// Write the header for a set of `count` nullifiers
+    pub fn new(writer: W, count: u64) -> SparkResult<Self> {
+        let mut this = NullifierSetWriter {
+            writer,
+            hasher: blake3::Hasher::new(),
+            expected: count,
+            written: 0,
+            last: None,
+        };
+        this.put(BINARY_MAGIC)?;
+        this.put(&BINARY_VERSION.to_le_bytes())?;
+        this.put(&count.to_le_bytes())?;
+        Ok(this)
+    }
+
+    /// Write the next entry
+    ///
+    /// # Errors
+    /// Fails if the entry is not greater than the previous one, if more
+    /// entries than announced are written, or on an I/O error
+    pub fn write(&mut self, nullifier: &Nullifier) -> SparkResult<()> {
+        if self.written == self.expected {
+            return Err(binary_error(format!("More than the announced {} entries written", self.expected)));
+        }
+        if self.last.is_some_and(|last| last.as_bytes() >= nullifier.as_bytes()) {
+            return Err(binary_error("Entries must be written in strictly increasing order"));
+        }
+        self.put(nullifier.as_bytes())?;
+        self.last = Some(*nullifier);
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Append the checksum and return the underlying writer
+    ///
+    /// # Errors
+    /// Fails if fewer entries than announced were written
+    pub fn finish(mut self) -> SparkResult<W> {
+        if self.written != self.expected {
+            return Err(binary_error(format!("{} of the announced {} entries written", self.written, self.expected)));
+        }
+        let checksum = self.hasher.finalize();
+        self.writer.write_all(checksum.as_bytes()).map_err(io_error)?;
+        self.writer.flush().map_err(io_error)?;
+        Ok(self.writer)
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> SparkResult<()> {
+        self.hasher.update(bytes);
+        self.writer.write_all(bytes).map_err(io_error)
+    }
+}
+
+/// Streaming reader for the binary nullifier set format
+///
+/// Yields entries one at a time without holding the set in memory. The
+/// header is checked on [`new`](Self::new); after the last entry the
+/// checksum is verified and the stream must end, and an error is yielded if
+/// either check fails. Consumers must treat entries as untrusted until the
+/// iterator has finished without error.
+pub struct NullifierSetReader<R: Read> {
+    reader: R,
+    hasher: blake3::Hasher,
+    count: u64,
+    read: u64,
+    last: Option<Nullifier>,
+    done: bool,
+}
+
+impl<R: Read> NullifierSetReader<R> {
+    /// Read and check the header
+    ///
+    /// # Errors
+    /// Fails on a bad magic, an unsupported version, a count above
+    /// `max_entries`, or an I/O error
+    pub fn new(mut reader: R, max_entries: u64) -> SparkResult<Self> {
+        let mut header = [0u8; 16];
+        reader.read_exact(&mut header).map_err(io_error)?;
+        if &header[..4] != BINARY_MAGIC {
+            return Err(binary_error("Not a binary nullifier set"));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().expect("header is 16 bytes"));
+        if version > BINARY_VERSION {
+            return Err(binary_error(format!("Unsupported version: {} (current: {})", version, BINARY_VERSION)));
+        }
+        let count = u64::from_le_bytes(header[8..16].try_into().expect("header is 16 bytes"));
+        if count > max_entries {
+            return Err(binary_error(format!("Set holds {} entries (limit {})", count, max_entries)));
+        }
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&header);
+        Ok(NullifierSetReader { reader, hasher, count, read: 0, last: None, done: false })
+    }
+
+    /// Number of entries announced by the header
+    pub fn entry_count(&self) -> u64 {
+        self.count
+    }
+
+    fn next_entry(&mut self) -> SparkResult<Option<Nullifier>> {
+        if self.read == self.count {
+            self.verify_trailer()?;
+            return Ok(None);
+        }
+
+        let mut bytes = [0u8; 32];
+        self.reader.read_exact(&mut bytes).map_err(io_error)?;
+        self.hasher.update(&bytes);
+        let nullifier = Nullifier::new(bytes);
+        if self.last.is_some_and(|last| last.as_bytes() >= nullifier.as_bytes()) {
+            return Err(binary_error(format!("Entry {} is out of order or duplicated", self.read)));
+        }
+        self.last = Some(nullifier);
+        self.read += 1;
+        Ok(Some(nullifier))
+    }
+
+    fn verify_trailer(&mut self) -> SparkResult<()> {
+        let mut checksum = [0u8; 32];
+        self.reader.read_exact(&mut checksum).map_err(io_error)?;
+        if !crate::crypto::constant_time_eq(&checksum, self.hasher.finalize().as_bytes()) {
+            return Err(binary_error("Checksum mismatch"));
+        }
+        let mut trailing = [0u8; 1];
+        match self.reader.read(&mut trailing).map_err(io_error)? {
+            0 => Ok(()),
+            _ => Err(binary_error("Unexpected data after the checksum")),
+        }
+    }
+}
+
+impl<R: Read> Iterator for NullifierSetReader<R> {
+    type Item = SparkResult<Nullifier>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(nullifier)) => Some(Ok(nullifier)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Writes a nullifier set in the binary format
+///
+/// # Arguments
+/// * `set` - The set to write
+/// * `writer` - Destination of the stream
+///
+/// # Returns
+/// * `Ok(W)` - The writer, once the checksum has been written
+/// * `Err(SparkError)` on an I/O error
+pub fn export_nullifier_set_binary<W: Write>(set: &NullifierSet, writer: W) -> SparkResult<W> {
+    let mut entries: Vec<&Nullifier> = set.iter().collect();
+    entries.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+    let mut writer = NullifierSetWriter::new(writer, entries.len() as u64)?;
+    for nullifier in entries {
+        writer.write(nullifier)?;
+    }
+    writer.finish()
+}
+
+/// Reads a whole binary nullifier set
+///
+/// # Arguments
+/// * `reader` - Source of the stream
+/// * `max_entries` - Largest number of entries accepted
+///
+/// # Returns
+/// * `Ok(NullifierSet)` - The imported set
+/// * `Err(SparkError)` if the stream is malformed, too large or corrupted
+pub fn import_nullifier_set_binary<R: Read>(reader: R, max_entries: u64) -> SparkResult<NullifierSet> {
+    NullifierSetReader::new(reader, max_entries)?.collect()
+}
+
+fn binary_error(message: impl Into<String>) -> SparkError {
+    SparkError::SerializationError {
+        message: format!("Binary nullifier set: {}", message.into()),
+    }
+}
+
+fn io_error(e: std::io::Error) -> SparkError {
+    binary_error(format!("I/O error: {}", e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +336,54 @@ mod tests {
         assert!(result.is_err());
     }
     
+    fn binary_set() -> (NullifierSet, Vec<u8>) {
+        let set: NullifierSet = (0..50u8).map(|i| Nullifier::new([i.wrapping_mul(37); 32])).collect();
+        let bytes = export_nullifier_set_binary(&set, Vec::new()).unwrap();
+        (set, bytes)
+    }
+
+    #[test]
+    fn test_binary_roundtrip_and_layout() {
+        let (set, bytes) = binary_set();
+        assert_eq!(bytes.len(), 16 + 50 * 32 + 32);
+        assert_eq!(&bytes[..4], BINARY_MAGIC);
+
+        let imported = import_nullifier_set_binary(bytes.as_slice(), DEFAULT_MAX_BINARY_ENTRIES).unwrap();
+        assert_eq!(imported.len(), set.len());
+        assert!(set.iter().all(|n| imported.contains(n)));
+
+        let reader = NullifierSetReader::new(bytes.as_slice(), 50).unwrap();
+        assert_eq!(reader.entry_count(), 50);
+        let entries: Vec<Nullifier> = reader.map(Result::unwrap).collect();
+        assert!(entries.windows(2).all(|w| w[0].as_bytes() < w[1].as_bytes()));
+    }
+
+    #[test]
+    fn test_binary_rejects_bad_input() {
+        let (_, bytes) = binary_set();
+
+        assert!(NullifierSetReader::new(bytes.as_slice(), 49).is_err());
+        assert!(import_nullifier_set_binary(&bytes[..bytes.len() - 1], 50).is_err());
+
+        let mut corrupted = bytes.clone();
+        corrupted[100] ^= 1;
+        assert!(import_nullifier_set_binary(corrupted.as_slice(), 50).is_err());
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(import_nullifier_set_binary(trailing.as_slice(), 50).is_err());
+
+        let mut bad_magic = bytes;
+        bad_magic[0] = b'X';
+        assert!(import_nullifier_set_binary(bad_magic.as_slice(), 50).is_err());
+
+        // The writer enforces order and the announced count
+        let mut writer = NullifierSetWriter::new(Vec::new(), 2).unwrap();
+        writer.write(&Nullifier::new([2; 32])).unwrap();
+        assert!(writer.write(&Nullifier::new([1; 32])).is_err());
+        assert!(writer.finish().is_err());
+    }
+
     #[test]
     fn test_import_invalid_hex() {
         let export = NullifierSetExport {
@@ -0,0 +1,668 @@
+//! Thread-safe shared note manager
+//!
+//! This module provides [`SharedNoteManager`], a cloneable handle around a
+//! [`NoteManager`] for servers and other concurrent callers. State is guarded
+//! by an async read/write lock, spend-related operations additionally take a
+//! per-note lock, and network round-trips run without holding the manager
+//! lock, so one note can be spent while another task scans the chain.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, RwLock};
+
+use crate::error::SparkResult;
+use crate::events::{NoteEvent, NoteEventBus, NoteEventReceiver, ObserverId};
+use crate::history::HistoryAction;
+use crate::manager::{NoteEntry, NoteManager, NoteMetadata};
+use crate::note::SparkNote;
+use crate::nullifier::ReconcileReport;
+use crate::query::{NoteQuery, NoteQueryResult};
+use crate::spend::SpendReceipt;
+use crate::tezos::TezosOperationResult;
+use crate::tezos_batch::BatchResult;
+
+/// A [`NoteManager`] that can be shared between threads and tasks
+///
+/// Cloning the handle is cheap; all clones operate on the same manager.
+///
+/// # Example
+/// ```
+/// use spark_note_sdk::{create_note, NoteManager};
+/// use spark_note_sdk::shared::SharedNoteManager;
+/// use spark_note_sdk::secret::Secret;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let shared = SharedNoteManager::new(NoteManager::new());
+///
+/// let note = create_note(1000, Secret::new(vec![1; 16])).unwrap();
+/// shared.add_note("note1".to_string(), note).await.unwrap();
+///
+/// let worker = shared.clone();
+/// let value = tokio::spawn(async move { worker.get_note("note1").await.unwrap().note.value })
+///     .await
+///     .unwrap();
+/// assert_eq!(value, 1000);
+/// # });
+/// ```
+#[derive(Clone)]
+pub struct SharedNoteManager {
+    inner: Arc<RwLock<NoteManager>>,
+    /// One lock per note ID, serializing spend-related operations on that note
+    note_locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+    events: NoteEventBus,
+}
+
+impl SharedNoteManager {
+    /// Wraps a manager for shared use
+    pub fn new(manager: NoteManager) -> Self {
+        let events = manager.event_bus().clone();
+        SharedNoteManager {
+            inner: Arc::new(RwLock::new(manager)),
+            note_locks: Arc::new(Mutex::new(HashMap::new())),
+            events,
+        }
+    }
+
+    /// Run a closure with shared access to the underlying manager
+    pub async fn read<R>(&self, f: impl FnOnce(&NoteManager) -> R) -> R {
+        f(&*self.inner.read().await)
+    }
+
+    /// Run a closure with exclusive access to the underlying manager
+    ///
+    /// The closure bypasses the per-note locks, so it should not be used to
+    /// spend notes that other tasks may be spending concurrently.
+    pub async fn write<R>(&self, f: impl FnOnce(&mut NoteManager) -> R) -> R {
+        f(&mut *self.inner.write().await)
+    }
+
+    /// Acquire the lock serializing spend-related operations on one note
+    pub async fn lock_note(&self, id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.note_locks.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            prune_unused(&mut locks);
+            locks.entry(id.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+
+    /// Acquire the locks of several notes, in a fixed order so that two
+    /// tasks locking overlapping sets cannot deadlock
+    async fn lock_notes(&self, ids: &[&str]) -> Vec<OwnedMutexGuard<()>> {
+        let mut lock_order = ids.to_vec();
+        lock_order.sort_unstable();
+        lock_order.dedup();
+        let mut guards = Vec::with_capacity(lock_order.len());
+        for id in lock_order {
+            guards.push(self.lock_note(id).await);
+        }
+        guards
+    }
+
+    /// Subscribe to change events without taking the manager lock
+    pub fn subscribe(&self) -> NoteEventReceiver {
+        self.events.subscribe()
+    }
+
+    /// Register a callback invoked for every change event
+    pub fn add_observer<F>(&self, observer: F) -> ObserverId
+    where
+        F: Fn(&NoteEvent) + Send + Sync + 'static,
+    {
+        self.events.add_observer(observer)
+    }
+
+    /// Unregister a callback added with [`add_observer`](Self::add_observer)
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        self.events.remove_observer(id)
+    }
+
+    /// Adds a note to the manager
+    pub async fn add_note(&self, id: String, note: SparkNote) -> SparkResult<()> {
+        self.inner.write().await.add_note(id, note)
+    }
+
+    /// Adds a note to the manager together with its metadata
+    pub async fn add_note_with_metadata(&self, id: String, note: SparkNote, metadata: NoteMetadata) -> SparkResult<()> {
+        self.inner.write().await.add_note_with_metadata(id, note, metadata)
+    }
+
+    /// Gets a note by ID (public fields only)
+    pub async fn get_note(&self, id: &str) -> Option<NoteEntry> {
+        self.inner.read().await.get_note(id)
+    }
+
+    /// Lists all notes (public fields only)
+    pub async fn list_notes(&self) -> Vec<(String, NoteEntry)> {
+        self.inner.read().await.list_notes()
+    }
+
+    /// Runs a filtered, sorted and paginated query over the notes
+    pub async fn query(&self, query: &NoteQuery) -> SparkResult<NoteQueryResult> {
+        self.inner.read().await.query(query)
+    }
+
+    /// Total value of all notes that have not been spent
+    pub async fn balance(&self) -> u64 {
+        self.inner.read().await.balance()
+    }
+
+    /// Total value of the notes that can be spent right now
+    pub async fn spendable_balance(&self) -> u64 {
+        self.inner.read().await.spendable_balance()
+    }
+
+    /// Removes a note by ID, waiting for any in-flight spend of it to finish
+    ///
+    /// As with [`NoteManager::remove_note`], database errors are not
+    /// reported; use [`try_remove_note`](Self::try_remove_note) to get them.
+    pub async fn remove_note(&self, id: &str) -> Option<NoteEntry> {
+        let guard = self.lock_note(id).await;
+        let removed = self.inner.write().await.remove_note(id);
+        drop(guard);
+        self.prune_note_locks();
+        removed
+    }
+
+    /// Removes a note by ID, returning database errors
+    pub async fn try_remove_note(&self, id: &str) -> SparkResult<Option<NoteEntry>> {
+        let guard = self.lock_note(id).await;
+        let removed = self.inner.write().await.try_remove_note(id);
+        drop(guard);
+        self.prune_note_locks();
+        removed
+    }
+
+    /// Forget the note locks that no task holds or waits for
+    fn prune_note_locks(&self) {
+        prune_unused(&mut self.note_locks.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+    }
+
+    /// Generates a nullifier for a note
+    pub async fn generate_nullifier_for_note(&self, id: &str, secret: Vec<u8>) -> SparkResult<Vec<u8>> {
+        let _guard = self.lock_note(id).await;
+        self.inner.write().await.generate_nullifier_for_note(id, secret)
+    }
+
+    /// Marks a note as spent by its nullifier
+    ///
+    /// Concurrent calls for the same note are serialized; exactly one succeeds
+    /// and the others fail with `NullifierErrorCode::AlreadySpent`.
+    pub async fn mark_note_as_spent(&self, id: &str) -> SparkResult<()> {
+        let _guard = self.lock_note(id).await;
+        self.inner.write().await.mark_note_as_spent(id)
+    }
+
+    /// Add a spent nullifier directly
+    pub async fn add_spent_nullifier(&self, nullifier: &[u8]) -> SparkResult<()> {
+        self.inner.write().await.add_spent_nullifier(nullifier)
+    }
+
+    /// Check if a nullifier has been spent
+    pub async fn is_nullifier_spent(&self, nullifier: &[u8]) -> bool {
+        self.inner.read().await.is_nullifier_spent(nullifier)
+    }
+
+    /// Check if a nullifier has been spent, returning store read errors
+    pub async fn try_is_nullifier_spent(&self, nullifier: &[u8]) -> SparkResult<bool> {
+        self.inner.read().await.try_is_nullifier_spent(nullifier)
+    }
+
+    /// Sync a deposit to Tezos without holding the manager lock during the RPC calls
+    pub async fn sync_deposit_to_tezos(&self, id: &str, secret_key: &str) -> SparkResult<TezosOperationResult> {
+        let (client, note, proof) = self.inner.read().await.prepare_deposit(id)?;
+        let result = client.deposit(&note, &proof, secret_key).await?;
+        self.inner.read().await.record_history(HistoryAction::DepositSynced {
+            id: id.to_string(),
+            operation_hash: result.operation_hash.clone(),
+        })?;
+        Ok(result)
+    }
+
+    /// Spend a note on Tezos
+    ///
+    /// The note's lock is held for the whole operation, but the manager lock
+    /// is only taken to read the note and to record the result, not while the
+    /// proof is generated and injected. Unlike
+    /// [`NoteManager::sync_spend_to_tezos`], the note is marked as spent once
+    /// the operation has been injected, so a second caller racing on the same
+    /// note fails with `NullifierErrorCode::AlreadySpent` instead of
+    /// submitting it twice.
+    ///
+    /// Once injected, the operation is returned even if the note cannot be
+    /// marked; [`sync_spent_nullifiers`](Self::sync_spent_nullifiers) then
+    /// marks it from the chain.
+    pub async fn sync_spend_to_tezos(&self, id: &str, secret_key: &str) -> SparkResult<TezosOperationResult> {
+        let _guard = self.lock_note(id).await;
+        let prepared = self.inner.read().await.prepare_spend(id)?;
+
+        let result = prepared.submit(secret_key).await?;
+        let _ = self.inner.write().await.record_injected_spend(id, &prepared.nullifier, &result.operation_hash);
+        Ok(result)
+    }
+
+    /// Sync several deposits and spends to Tezos as one operation
+    ///
+    /// Same as [`NoteManager::sync_batch_to_tezos`], except that the locks of
+    /// the spent notes are held for the whole operation and those notes are
+    /// marked as spent once it has been injected, as in
+    /// [`sync_spend_to_tezos`](Self::sync_spend_to_tezos).
+    pub async fn sync_batch_to_tezos(&self, deposit_ids: &[&str], spend_ids: &[&str], secret_key: &str) -> SparkResult<BatchResult> {
+        let _guards = self.lock_notes(spend_ids).await;
+        let prepared = self.inner.read().await.prepare_batch(deposit_ids, spend_ids)?;
+
+        let result = prepared.submit(secret_key).await?;
+        let mut manager = self.inner.write().await;
+        let _ = manager.record_batch(deposit_ids, &[], &result.operation.operation_hash);
+        for (id, spend) in &prepared.spends {
+            let _ = manager.record_injected_spend(id, &spend.nullifier, &result.operation.operation_hash);
+        }
+        Ok(result)
+    }
+
+    /// Spend a whole note with a real spending proof
+    ///
+    /// Same as [`NoteManager::spend_note`], holding the note's lock for the
+    /// whole spend but the manager lock only to start and record it, so other
+    /// notes stay usable while the proof is generated and submitted.
+    pub async fn spend_note(&self, id: &str, amount: u64, secret_key: &str) -> SparkResult<SpendReceipt> {
+        let _guard = self.lock_note(id).await;
+        let prepared = self.inner.write().await.begin_spend(id, amount)?;
+        let result = prepared.submit(secret_key).await;
+        self.inner.write().await.finish_spend(id, prepared, result)
+    }
+
+    /// Scan the Tezos blockchain for deposit events
+    ///
+    /// Commitments are fetched without holding the manager lock; the lock is
+    /// only taken to match them against the wallet.
+    pub async fn scan(&self, _viewing_key: &[u8]) -> SparkResult<usize> {
+        let client = self.inner.read().await.client()?;
+        let commitments = client.fetch_deposit_events().await?;
+        self.inner.write().await.apply_scanned_commitments(commitments)
+    }
+
+    /// Reconcile the spent set with the contract's nullifiers
+    ///
+    /// As with [`scan`](Self::scan), the nullifiers are fetched without
+    /// holding the manager lock. The reconcile itself holds every note's
+    /// lock, so it waits for spends in flight to record their result.
+    pub async fn sync_spent_nullifiers(&self) -> SparkResult<ReconcileReport> {
+        let client = self.inner.read().await.client()?;
+        let nullifiers = client.fetch_spent_nullifiers().await?;
+        let ids = self.inner.read().await.list_note_ids();
+        let _guards = self.lock_notes(&ids.iter().map(String::as_str).collect::<Vec<_>>()).await;
+        self.inner.write().await.reconcile_spent_nullifiers(&nullifiers)
+    }
+}
+
+/// Drop the locks that only the map references
+///
+/// Tasks clone a lock while holding the map, so a lock nobody else
+/// references cannot be handed out again once it is dropped.
+fn prune_unused(locks: &mut HashMap<String, Arc<AsyncMutex<()>>>) {
+    locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+}
+
+impl std::fmt::Debug for SharedNoteManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedNoteManager")
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{NullifierErrorCode, SparkError};
+    use crate::events::NoteEvent;
+    use crate::note::create_note;
+    use crate::secret::Secret;
+    use crate::spend::test_spending_keys;
+    use crate::tezos::{mock_node, TezosClient, TEST_CONTRACT};
+    use crate::tezos_keys::TEST_SECRET_KEY;
+    use crate::tezos_storage::test_storage;
+    use wiremock::matchers::{method, path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+
+    fn is_already_spent(result: &SparkResult<impl std::fmt::Debug>) -> bool {
+        matches!(
+            result,
+            Err(SparkError::NullifierError { code: NullifierErrorCode::AlreadySpent, .. })
+        )
+    }
+
+    async fn shared_with_notes(count: u8) -> SharedNoteManager {
+        add_notes(SharedNoteManager::new(NoteManager::new()), count).await
+    }
+
+    /// Notes that can be spent on `server`: they are in the commitment tree
+    /// and the manager has the spending keys
+    async fn spendable_with_notes(count: u8, server: &MockServer) -> SharedNoteManager {
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let manager = NoteManager::new().with_tezos_client(client).with_spending_keys(test_spending_keys());
+        let shared = add_notes(SharedNoteManager::new(manager), count).await;
+        shared
+            .write(|m| {
+                let commitments = m.list_notes().into_iter().map(|(_, entry)| entry.note.commitment).collect();
+                m.apply_scanned_commitments(commitments)
+            })
+            .await
+            .unwrap();
+        shared
+    }
+
+    async fn add_notes(shared: SharedNoteManager, count: u8) -> SharedNoteManager {
+        for i in 0..count {
+            let secret = vec![i + 1; 16];
+            let note = create_note(100 + i as u64, Secret::new(secret.clone())).unwrap();
+            let id = format!("note{}", i);
+            shared.add_note(id.clone(), note).await.unwrap();
+            shared.generate_nullifier_for_note(&id, secret).await.unwrap();
+        }
+        shared
+    }
+
+    /// Number of operations injected into `server`
+    async fn injections(server: &MockServer) -> usize {
+        let requests = server.received_requests().await.unwrap();
+        requests.iter().filter(|r| r.url.path() == "/injection/operation").count()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_double_spend_attempts() {
+        let shared = shared_with_notes(4).await;
+
+        let mut tasks = Vec::new();
+        for attempt in 0..64 {
+            let shared = shared.clone();
+            let id = format!("note{}", attempt % 4);
+            tasks.push(tokio::spawn(async move { shared.mark_note_as_spent(&id).await }));
+        }
+
+        let mut successes = 0;
+        for task in tasks {
+            let result = task.await.unwrap();
+            if result.is_ok() {
+                successes += 1;
+            } else {
+                assert!(is_already_spent(&result), "unexpected error: {:?}", result);
+            }
+        }
+
+        assert_eq!(successes, 4);
+        assert_eq!(shared.read(|m| m.spent_nullifier_count()).await, 4);
+        assert_eq!(shared.spendable_balance().await, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_on_chain_spends_inject_once() {
+        let server = mock_node(ResponseTemplate::new(200).set_body_json("ooSpendOperationHash")).await;
+
+        let shared = spendable_with_notes(1, &server).await;
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let shared = shared.clone();
+                tokio::spawn(async move { shared.sync_spend_to_tezos("note0", TEST_SECRET_KEY).await })
+            })
+            .collect();
+
+        let mut hashes = Vec::new();
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(result) => hashes.push(result.operation_hash),
+                result => assert!(is_already_spent(&result), "unexpected result: {:?}", result),
+            }
+        }
+
+        assert_eq!(hashes, vec!["ooSpendOperationHash".to_string()]);
+        let synced = shared
+            .read(|m| m.history().unwrap())
+            .await
+            .into_iter()
+            .filter(|r| matches!(r.action, HistoryAction::SpendSynced { .. }))
+            .count();
+        assert_eq!(synced, 1);
+        assert!(shared.read(|m| m.prepare_spend("note0").is_err()).await);
+        assert_eq!(injections(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_remove_note_keeps_waited_lock() {
+        let shared = shared_with_notes(1).await;
+        let held = shared.lock_note("note0").await;
+
+        let remover = {
+            let shared = shared.clone();
+            tokio::spawn(async move { shared.try_remove_note("note0").await })
+        };
+        tokio::task::yield_now().await;
+        let (acquired_tx, acquired_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let waiter = {
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                let _guard = shared.lock_note("note0").await;
+                acquired_tx.send(()).unwrap();
+                release_rx.await.unwrap();
+            })
+        };
+        tokio::task::yield_now().await;
+
+        drop(held);
+        assert!(remover.await.unwrap().unwrap().is_some());
+        acquired_rx.await.unwrap();
+
+        // The waiter still holds the lock the removal left in place
+        let attempt = tokio::time::timeout(std::time::Duration::from_millis(50), shared.lock_note("note0")).await;
+        assert!(attempt.is_err(), "two tasks held the lock of the same note");
+
+        release_tx.send(()).unwrap();
+        waiter.await.unwrap();
+        drop(shared.lock_note("note0").await);
+        // Once nobody uses it, the removed note's lock is forgotten
+        shared.prune_note_locks();
+        assert!(shared.note_locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_note_locks_do_not_accumulate() {
+        let shared = shared_with_notes(0).await;
+        let held = shared.lock_note("held").await;
+        for i in 0..100 {
+            drop(shared.lock_note(&format!("note{}", i)).await);
+        }
+        // Only the held lock and the last one released remain
+        assert_eq!(shared.note_locks.lock().unwrap().len(), 2);
+        drop(held);
+        shared.prune_note_locks();
+        assert!(shared.note_locks.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_sync_spent_nullifiers_waits_for_note_locks() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"/storage$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(test_storage(1, 2)))
+            .mount(&server)
+            .await;
+        let shared = shared_with_notes(1).await;
+        let nullifier = shared.get_note("note0").await.unwrap().nullifier.unwrap();
+        Mock::given(method("GET"))
+            .and(path("/v1/bigmaps/2/keys"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([{"id": 1, "key": hex::encode(&nullifier)}])))
+            .mount(&server)
+            .await;
+        shared.write(|m| {
+            let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_indexer(&server.uri());
+            m.tezos_client = Some(Arc::new(client));
+        })
+        .await;
+
+        // A spend in flight holds the note's lock
+        let held = shared.lock_note("note0").await;
+        let reconcile = {
+            let shared = shared.clone();
+            tokio::spawn(async move { shared.sync_spent_nullifiers().await })
+        };
+        // Wait until the reconcile queues for the lock
+        let lock = shared.note_locks.lock().unwrap()["note0"].clone();
+        while Arc::strong_count(&lock) < 4 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(shared.get_note("note0").await.unwrap().state, crate::manager::NoteState::Unspent);
+
+        // The spend records its result before the reconcile sees the note
+        shared.write(|m| m.record_injected_spend("note0", &nullifier, "ooSpend")).await.unwrap();
+        drop(held);
+        let report = reconcile.await.unwrap().unwrap();
+        assert!(report.marked_spent.is_empty());
+        assert_eq!(shared.get_note("note0").await.unwrap().state, crate::manager::NoteState::Spent);
+    }
+
+    #[tokio::test]
+    async fn test_sync_batch() {
+        let server = mock_node(ResponseTemplate::new(200).set_body_json("ooBatchOperationHash")).await;
+
+        let shared = spendable_with_notes(3, &server).await;
+
+        // A note cannot be in a batch twice
+        assert!(shared.sync_batch_to_tezos(&["note0"], &["note0"], TEST_SECRET_KEY).await.is_err());
+
+        let result = shared.sync_batch_to_tezos(&["note0"], &["note2", "note1"], TEST_SECRET_KEY).await.unwrap();
+        assert_eq!(result.operation.operation_hash, "ooBatchOperationHash");
+        assert_eq!(result.note_ids, ["note0", "note2", "note1"]);
+
+        let synced: Vec<_> = shared
+            .read(|m| m.history().unwrap())
+            .await
+            .into_iter()
+            .filter_map(|r| match r.action {
+                HistoryAction::DepositSynced { id, operation_hash } => Some(("deposit", id, operation_hash)),
+                HistoryAction::SpendSynced { id, operation_hash } => Some(("spend", id, operation_hash)),
+                _ => None,
+            })
+            .collect();
+        let hash = "ooBatchOperationHash".to_string();
+        assert_eq!(
+            synced,
+            [
+                ("deposit", "note0".to_string(), hash.clone()),
+                ("spend", "note2".to_string(), hash.clone()),
+                ("spend", "note1".to_string(), hash),
+            ]
+        );
+
+        // The spent notes cannot be batched again
+        assert!(is_already_spent(&shared.sync_batch_to_tezos(&[], &["note1"], TEST_SECRET_KEY).await));
+        assert_eq!(shared.spendable_balance().await, 100);
+        assert_eq!(injections(&server).await, 1);
+    }
+
+    /// Indexer response that reports the request and waits for the test
+    /// before answering, holding the caller in flight
+    struct GatedResponse {
+        reached: Arc<tokio::sync::Notify>,
+        release: Mutex<std::sync::mpsc::Receiver<()>>,
+    }
+
+    impl wiremock::Respond for GatedResponse {
+        fn respond(&self, _request: &wiremock::Request) -> ResponseTemplate {
+            self.reached.notify_one();
+            // Runs on the indexer's own server thread, so the node stays responsive
+            let _ = self.release.lock().unwrap().recv();
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([]))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_spend_while_scanning() {
+        let server = mock_node(ResponseTemplate::new(200).set_body_json("ooSpend")).await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"/storage$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(test_storage(1, 2)))
+            .mount(&server)
+            .await;
+        // The scan's indexer request is held until the spend has finished
+        let indexer = MockServer::start().await;
+        let reached = Arc::new(tokio::sync::Notify::new());
+        let (release, released) = std::sync::mpsc::channel();
+        Mock::given(method("GET"))
+            .and(path("/v1/bigmaps/1/keys"))
+            .respond_with(GatedResponse { reached: reached.clone(), release: Mutex::new(released) })
+            .mount(&indexer)
+            .await;
+
+        let shared = spendable_with_notes(2, &server).await;
+        shared.write(|m| {
+            let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_indexer(&indexer.uri());
+            m.tezos_client = Some(Arc::new(client));
+        })
+        .await;
+        let mut events = shared.subscribe();
+
+        let scanner = {
+            let shared = shared.clone();
+            tokio::spawn(async move { shared.scan(&[0u8; 32]).await })
+        };
+        reached.notified().await;
+
+        // A spend waiting for the scan would never finish
+        let spend = shared.sync_spend_to_tezos("note1", TEST_SECRET_KEY);
+        let spent = tokio::time::timeout(std::time::Duration::from_secs(120), spend).await;
+        spent.expect("spend should not wait for the scan").unwrap();
+        assert!(!scanner.is_finished());
+        release.send(()).unwrap();
+        scanner.await.unwrap().unwrap();
+
+        assert!(matches!(events.recv().await, Some(NoteEvent::NoteSpent { id, .. }) if id == "note1"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn test_concurrent_mixed_operations() {
+        let shared = shared_with_notes(0).await;
+
+        let writers: Vec<_> = (0..32u8)
+            .map(|i| {
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    let secret = vec![i + 1; 16];
+                    let id = format!("n{}", i);
+                    let note = create_note(10, Secret::new(secret.clone())).unwrap();
+                    shared.add_note(id.clone(), note).await.unwrap();
+                    shared.generate_nullifier_for_note(&id, secret).await.unwrap();
+                    if i % 2 == 0 {
+                        shared.mark_note_as_spent(&id).await.unwrap();
+                    } else {
+                        shared.try_remove_note(&id).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    for _ in 0..20 {
+                        let balance = shared.balance().await;
+                        assert!(balance <= 320);
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+
+        for task in writers.into_iter().chain(readers) {
+            task.await.unwrap();
+        }
+
+        assert_eq!(shared.list_notes().await.len(), 16);
+        assert_eq!(shared.balance().await, 0);
+        assert_eq!(shared.read(|m| m.spent_nullifier_count()).await, 16);
+    }
+}
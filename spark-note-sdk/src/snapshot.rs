@@ -0,0 +1,349 @@
+//! Signed nullifier set snapshots
+//!
+//! A light client importing a nullifier set needs to know who vouches for
+//! it. A snapshot pairs a set written in the binary format (see
+//! [`NullifierSetWriter`](crate::serialization::NullifierSetWriter)) with a
+//! [`SignedSnapshotHeader`]: the chain ID, block level, set root and entry
+//! count, signed with an ed25519 key. [`import_snapshot`] only accepts a set
+//! whose header is signed by one of the configured [`TrustedSigners`] and
+//! whose contents match the signed root and count.
+
+use std::io::Read;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{SparkError, SparkResult};
+use crate::nullifier::{Nullifier, NullifierSet};
+use crate::serialization::NullifierSetReader;
+
+/// Domain separating snapshot signatures from other ed25519 uses
+const SNAPSHOT_SIGNING_DOMAIN: &[u8] = b"SPARK_NULLIFIER_SNAPSHOT_V1";
+/// Key-derivation context of the set root
+const SET_ROOT_CONTEXT: &str = "spark-note-sdk nullifier set root v1";
+
+/// What a snapshot signer vouches for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    /// Chain the nullifiers were read from (for example `NetXnHfVqm9iesp`)
+    pub chain_id: String,
+    /// Block level the set is complete up to
+    pub level: u64,
+    /// Root of the set (hex-encoded, see [`nullifier_set_root`])
+    pub root: String,
+    /// Number of nullifiers in the set
+    pub count: u64,
+}
+
+impl SnapshotHeader {
+    /// Describe `set` as of `level` on `chain_id`
+    pub fn for_set(chain_id: impl Into<String>, level: u64, set: &NullifierSet) -> Self {
+        SnapshotHeader {
+            chain_id: chain_id.into(),
+            level,
+            root: hex::encode(nullifier_set_root(sorted(set))),
+            count: set.len() as u64,
+        }
+    }
+
+    /// Bytes covered by the signature
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut bytes = SNAPSHOT_SIGNING_DOMAIN.to_vec();
+        bytes.extend_from_slice(&(self.chain_id.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(self.chain_id.as_bytes());
+        bytes.extend_from_slice(&self.level.to_le_bytes());
+        bytes.extend_from_slice(self.root.as_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes
+    }
+}
+
+/// A snapshot header with its signer and signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedSnapshotHeader {
+    /// The signed header
+    pub header: SnapshotHeader,
+    /// ed25519 public key of the signer (hex-encoded)
+    pub signer: String,
+    /// ed25519 signature over the header (hex-encoded)
+    pub signature: String,
+}
+
+impl SignedSnapshotHeader {
+    /// Serialize to JSON
+    pub fn to_json(&self) -> SparkResult<String> {
+        serde_json::to_string(self).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to serialize snapshot header: {}", e),
+        })
+    }
+
+    /// Parse from JSON (the signature is checked on import)
+    pub fn from_json(json: &str) -> SparkResult<Self> {
+        serde_json::from_str(json).map_err(|e| SparkError::SerializationError {
+            message: format!("Failed to deserialize snapshot header: {}", e),
+        })
+    }
+
+    /// Check that the header is signed by a trusted signer
+    ///
+    /// # Returns
+    /// * `Ok(())` if the signer is trusted and the signature is valid
+    /// * `Err(SparkError)` otherwise
+    pub fn verify(&self, trusted: &TrustedSigners) -> SparkResult<()> {
+        let signer = decode_public_key(&self.signer)?;
+        if !trusted.contains(&signer) {
+            return Err(snapshot_error(format!("Signer {} is not trusted", self.signer)));
+        }
+        let signature = hex::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| snapshot_error("Invalid signature encoding"))?;
+        signer
+            .verify_strict(&self.header.signing_bytes(), &signature)
+            .map_err(|_| snapshot_error("Signature does not match the header"))
+    }
+}
+
+/// ed25519 key signing snapshot headers
+#[derive(Clone)]
+pub struct SnapshotSigner {
+    key: SigningKey,
+}
+
+impl SnapshotSigner {
+    /// Use a 32-byte ed25519 secret key
+    pub fn from_bytes(secret: [u8; 32]) -> Self {
+        SnapshotSigner { key: SigningKey::from_bytes(&secret) }
+    }
+
+    /// Generate a new random key
+    pub fn generate() -> SparkResult<Self> {
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(&crate::rng::generate_random_bytes(32)?);
+        let signer = Self::from_bytes(secret);
+        zeroize::Zeroize::zeroize(&mut secret);
+        Ok(signer)
+    }
+
+    /// Public key to add to clients' [`TrustedSigners`]
+    pub fn public_key(&self) -> [u8; 32] {
+        self.key.verifying_key().to_bytes()
+    }
+
+    /// Sign a header
+    pub fn sign(&self, header: SnapshotHeader) -> SignedSnapshotHeader {
+        let signature = self.key.sign(&header.signing_bytes());
+        SignedSnapshotHeader {
+            header,
+            signer: hex::encode(self.public_key()),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+
+    /// Describe and sign `set` as of `level` on `chain_id`
+    pub fn sign_set(&self, chain_id: impl Into<String>, level: u64, set: &NullifierSet) -> SignedSnapshotHeader {
+        self.sign(SnapshotHeader::for_set(chain_id, level, set))
+    }
+}
+
+impl std::fmt::Debug for SnapshotSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SnapshotSigner({})", hex::encode(self.public_key()))
+    }
+}
+
+/// Snapshot signers a client accepts
+#[derive(Debug, Clone, Default)]
+pub struct TrustedSigners {
+    keys: Vec<VerifyingKey>,
+}
+
+impl TrustedSigners {
+    /// Creates an empty list, which trusts no one
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust an ed25519 public key
+    ///
+    /// # Errors
+    /// Fails if the bytes are not a valid public key
+    pub fn with_signer(mut self, public_key: [u8; 32]) -> SparkResult<Self> {
+        let key = VerifyingKey::from_bytes(&public_key)
+            .map_err(|_| snapshot_error("Invalid signer public key"))?;
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+        Ok(self)
+    }
+
+    /// Trust a hex-encoded ed25519 public key
+    pub fn with_hex_signer(self, public_key: &str) -> SparkResult<Self> {
+        let key = decode_public_key(public_key)?;
+        self.with_signer(key.to_bytes())
+    }
+
+    /// Stop trusting a public key
+    ///
+    /// # Returns
+    /// `true` if the key was trusted
+    pub fn remove_signer(&mut self, public_key: &[u8; 32]) -> bool {
+        let before = self.keys.len();
+        self.keys.retain(|key| key.as_bytes() != public_key);
+        self.keys.len() != before
+    }
+
+    /// Number of trusted signers
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no signer is trusted
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn contains(&self, key: &VerifyingKey) -> bool {
+        self.keys.contains(key)
+    }
+}
+
+/// Root committing to a set's contents
+///
+/// BLAKE3, keyed for this purpose, over the entries in increasing byte
+/// order. The entries must be sorted and unique.
+pub fn nullifier_set_root<'a, I>(sorted_entries: I) -> [u8; 32]
+where
+    I: IntoIterator<Item = &'a Nullifier>,
+{
+    let mut hasher = blake3::Hasher::new_derive_key(SET_ROOT_CONTEXT);
+    for nullifier in sorted_entries {
+        hasher.update(nullifier.as_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+/// Import a snapshot after checking its signature and contents
+///
+/// # Arguments
+/// * `signed` - The snapshot header, with signer and signature
+/// * `reader` - The set, in the binary format
+/// * `trusted` - Signers the client accepts
+/// * `chain_id` - Chain the client expects the snapshot to be for
+///
+/// # Returns
+/// * `Ok(NullifierSet)` - The verified set
+/// * `Err(SparkError)` if the signer is not trusted, the signature is
+///   invalid, the snapshot is for another chain, or the set does not match
+///   the signed root and count
+pub fn import_snapshot<R: Read>(
+    signed: &SignedSnapshotHeader,
+    reader: R,
+    trusted: &TrustedSigners,
+    chain_id: &str,
+) -> SparkResult<NullifierSet> {
+    signed.verify(trusted)?;
+    let header = &signed.header;
+    if header.chain_id != chain_id {
+        return Err(snapshot_error(format!(
+            "Snapshot is for chain {}, expected {}",
+            header.chain_id, chain_id
+        )));
+    }
+
+    // The signed count bounds the amount read
+    let reader = NullifierSetReader::new(reader, header.count)?;
+    if reader.entry_count() != header.count {
+        return Err(snapshot_error("Set size does not match the signed count"));
+    }
+    let mut hasher = blake3::Hasher::new_derive_key(SET_ROOT_CONTEXT);
+    let mut set = NullifierSet::new();
+    for nullifier in reader {
+        let nullifier = nullifier?;
+        hasher.update(nullifier.as_bytes());
+        set.add(nullifier);
+    }
+
+    if hex::encode(hasher.finalize().as_bytes()) != header.root {
+        return Err(snapshot_error("Set does not match the signed root"));
+    }
+    Ok(set)
+}
+
+fn sorted(set: &NullifierSet) -> Vec<&Nullifier> {
+    let mut entries: Vec<&Nullifier> = set.iter().collect();
+    entries.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+    entries
+}
+
+fn decode_public_key(public_key: &str) -> SparkResult<VerifyingKey> {
+    let bytes: [u8; 32] = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| snapshot_error("Invalid signer public key encoding"))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| snapshot_error("Invalid signer public key"))
+}
+
+fn snapshot_error(message: impl Into<String>) -> SparkError {
+    SparkError::OperationError {
+        message: format!("Nullifier snapshot: {}", message.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::export_nullifier_set_binary;
+
+    const CHAIN: &str = "NetXnHfVqm9iesp";
+
+    fn snapshot() -> (SnapshotSigner, SignedSnapshotHeader, Vec<u8>) {
+        let set: NullifierSet = (1..=20u8).map(|i| Nullifier::new([i; 32])).collect();
+        let signer = SnapshotSigner::from_bytes([7; 32]);
+        let signed = signer.sign_set(CHAIN, 1_234_567, &set);
+        let bytes = export_nullifier_set_binary(&set, Vec::new()).unwrap();
+        (signer, signed, bytes)
+    }
+
+    #[test]
+    fn test_trusted_snapshot_imports() {
+        let (signer, signed, bytes) = snapshot();
+        let trusted = TrustedSigners::new().with_signer(signer.public_key()).unwrap();
+
+        let header = SignedSnapshotHeader::from_json(&signed.to_json().unwrap()).unwrap();
+        let set = import_snapshot(&header, bytes.as_slice(), &trusted, CHAIN).unwrap();
+        assert_eq!(set.len(), 20);
+        assert_eq!(header.header.level, 1_234_567);
+
+        assert!(import_snapshot(&header, bytes.as_slice(), &trusted, "NetXdQprcVkpaWU").is_err());
+    }
+
+    #[test]
+    fn test_untrusted_or_altered_snapshots_are_rejected() {
+        let (signer, signed, bytes) = snapshot();
+        let other = SnapshotSigner::from_bytes([8; 32]);
+
+        let mut trusted = TrustedSigners::new()
+            .with_hex_signer(&hex::encode(other.public_key()))
+            .unwrap();
+        assert!(import_snapshot(&signed, bytes.as_slice(), &trusted, CHAIN).is_err());
+        trusted = trusted.with_signer(signer.public_key()).unwrap();
+        assert!(import_snapshot(&signed, bytes.as_slice(), &trusted, CHAIN).is_ok());
+
+        // Changing any signed field breaks the signature
+        let mut altered = signed.clone();
+        altered.header.level += 1;
+        assert!(import_snapshot(&altered, bytes.as_slice(), &trusted, CHAIN).is_err());
+
+        // A validly signed header does not vouch for another set
+        let smaller: NullifierSet = (1..=19u8).map(|i| Nullifier::new([i; 32])).collect();
+        let smaller_bytes = export_nullifier_set_binary(&smaller, Vec::new()).unwrap();
+        assert!(import_snapshot(&signed, smaller_bytes.as_slice(), &trusted, CHAIN).is_err());
+        let swapped: NullifierSet = (2..=21u8).map(|i| Nullifier::new([i; 32])).collect();
+        let swapped_bytes = export_nullifier_set_binary(&swapped, Vec::new()).unwrap();
+        assert!(import_snapshot(&signed, swapped_bytes.as_slice(), &trusted, CHAIN).is_err());
+
+        assert!(trusted.remove_signer(&signer.public_key()));
+        assert!(import_snapshot(&signed, bytes.as_slice(), &trusted, CHAIN).is_err());
+    }
+}
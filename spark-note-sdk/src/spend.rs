@@ -0,0 +1,244 @@
+//! Spending notes with Groth16 proofs
+//!
+//! This module provides the [`SpendingKeys`] a manager needs to prove spends
+//! and the [`SpendReceipt`] returned by
+//! [`NoteManager::spend_note`](crate::manager::NoteManager::spend_note).
+//! A spend is split in three steps so that the slow proving step and the
+//! network call can run without holding the manager: the note is first moved
+//! to [`NoteState::PendingSpend`](crate::manager::NoteState::PendingSpend),
+//! the proof is generated and submitted, and the note is then either marked
+//! as spent or returned to `Unspent`.
+
+use std::sync::Arc;
+
+use ark_bls12_381::Bls12_381;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Deserialize, Serialize};
+
+use crate::commitment_tree::MerkleWitness;
+use crate::crypto::{self, Groth16ProvingKey, Groth16VerifyingKey};
+use crate::error::{SparkError, SparkResult};
+use crate::note::SparkNote;
+use crate::tezos::{TezosClient, TezosOperationResult};
+use crate::tezos_batch::{BatchResult, OperationBatch};
+
+/// Groth16 keys for the spending circuit
+#[derive(Debug, Clone)]
+pub struct SpendingKeys {
+    /// Key used to generate spending proofs
+    pub proving_key: Groth16ProvingKey<Bls12_381>,
+    /// Key used to check proofs before they are submitted
+    pub verifying_key: Groth16VerifyingKey<Bls12_381>,
+}
+
+impl SpendingKeys {
+    /// Wrap an existing key pair
+    pub fn new(proving_key: Groth16ProvingKey<Bls12_381>, verifying_key: Groth16VerifyingKey<Bls12_381>) -> Self {
+        SpendingKeys { proving_key, verifying_key }
+    }
+
+    /// Run the (deterministic, POC) circuit setup
+    ///
+    /// This takes several seconds; prefer loading serialized keys with
+    /// [`from_bytes`](Self::from_bytes) in applications.
+    pub fn setup() -> Self {
+        let (proving_key, verifying_key) = crypto::setup_spending_snark();
+        SpendingKeys { proving_key, verifying_key }
+    }
+
+    /// Load keys serialized with [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(proving_key: &[u8], verifying_key: &[u8]) -> SparkResult<Self> {
+        let proving_key = Groth16ProvingKey::deserialize_compressed(proving_key)
+            .map_err(|e| SparkError::invalid_proof(format!("Failed to deserialize proving key: {:?}", e)))?;
+        let verifying_key = Groth16VerifyingKey::deserialize_compressed(verifying_key)
+            .map_err(|e| SparkError::invalid_proof(format!("Failed to deserialize verifying key: {:?}", e)))?;
+        Ok(SpendingKeys { proving_key, verifying_key })
+    }
+
+    /// Serialize the proving and verifying keys
+    pub fn to_bytes(&self) -> (Vec<u8>, Vec<u8>) {
+        let mut proving_key = Vec::new();
+        self.proving_key.serialize_compressed(&mut proving_key).expect("serialization should not fail");
+        let mut verifying_key = Vec::new();
+        self.verifying_key.serialize_compressed(&mut verifying_key).expect("serialization should not fail");
+        (proving_key, verifying_key)
+    }
+}
+
+/// Outcome of a successful [`spend_note`](crate::manager::NoteManager::spend_note)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendReceipt {
+    /// The injected Tezos operation
+    pub operation: TezosOperationResult,
+    /// Nullifier revealed by the spend
+    pub nullifier: Vec<u8>,
+    /// Commitment tree root the proof was generated against
+    pub root: Vec<u8>,
+    /// Amount spent, the whole value of the note
+    pub amount: u64,
+}
+
+/// A spend whose note has been moved to `PendingSpend`
+///
+/// Owns everything needed to prove and submit the spend, so it can be
+/// processed without borrowing the manager.
+pub(crate) struct PreparedSpend {
+    pub client: Arc<TezosClient>,
+    pub keys: Arc<SpendingKeys>,
+    pub note: SparkNote,
+    pub nullifier: Vec<u8>,
+    pub witness: MerkleWitness,
+    pub amount: u64,
+}
+
+impl PreparedSpend {
+    /// Generate and check the spending proof
+    ///
+    /// Proving takes seconds of CPU time, so it runs on the blocking thread
+    /// pool instead of the async runtime.
+    pub(crate) async fn prove(&self) -> SparkResult<Vec<u8>> {
+        let (keys, note, witness, nullifier) =
+            (self.keys.clone(), self.note.clone(), self.witness.clone(), self.nullifier.clone());
+        tokio::task::spawn_blocking(move || prove_spend(&keys, &note, &witness, &nullifier))
+            .await
+            .map_err(|e| SparkError::invalid_proof(format!("Spending proof task failed: {}", e)))?
+    }
+
+    /// Prove the spend and inject it
+    pub(crate) async fn submit(&self, secret_key: &str) -> SparkResult<TezosOperationResult> {
+        let proof = self.prove().await?;
+        self.client.spend(&self.nullifier, &proof, secret_key).await
+    }
+}
+
+/// A batch whose notes have been checked, owning everything needed to prove
+/// and submit it without borrowing the manager
+pub(crate) struct PreparedBatch {
+    pub client: Arc<TezosClient>,
+    /// The deposits of the batch, with their proofs
+    pub deposits: OperationBatch,
+    pub spends: Vec<(String, PreparedSpend)>,
+}
+
+impl PreparedBatch {
+    /// Prove every spend and build the batch, deposits first
+    ///
+    /// Each proof runs on the blocking thread pool, as in
+    /// [`PreparedSpend::prove`].
+    pub(crate) async fn prove(&self) -> SparkResult<OperationBatch> {
+        let mut batch = self.deposits.clone();
+        for (id, spend) in &self.spends {
+            batch = batch.with_spend(id, spend.nullifier.clone(), spend.prove().await?);
+        }
+        Ok(batch)
+    }
+
+    /// Prove the batch and inject it as one operation
+    pub(crate) async fn submit(&self, secret_key: &str) -> SparkResult<BatchResult> {
+        let batch = self.prove().await?;
+        self.client.submit_batch(&batch, secret_key).await
+    }
+}
+
+/// Generate a spending proof for `note` and check it with the verifying key
+fn prove_spend(keys: &SpendingKeys, note: &SparkNote, witness: &MerkleWitness, nullifier: &[u8]) -> SparkResult<Vec<u8>> {
+    let proof = note.prove_spending(&keys.proving_key, &witness.root, witness.path.clone())?;
+    if !crypto::verify_spending_proof(&keys.verifying_key, &proof, &witness.root, nullifier)? {
+        return Err(SparkError::invalid_proof("Generated spending proof does not verify"));
+    }
+    Ok(proof.to_bytes())
+}
+
+/// Spending keys shared by the tests, set up once per test binary
+#[cfg(test)]
+pub(crate) fn test_spending_keys() -> Arc<SpendingKeys> {
+    static KEYS: std::sync::OnceLock<Arc<SpendingKeys>> = std::sync::OnceLock::new();
+    KEYS.get_or_init(|| Arc::new(SpendingKeys::setup())).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::{NoteManager, NoteState};
+    use crate::note::create_note;
+    use crate::secret::Secret;
+    use crate::tezos::{decode_injected, mock_node, TEST_CONTRACT};
+    use crate::tezos_keys::TEST_SECRET_KEY;
+    use wiremock::{MockServer, ResponseTemplate};
+
+    /// A manager holding one synced 1000-unit note, talking to `server`
+    fn synced_manager(server: &MockServer) -> NoteManager {
+        let mut manager = NoteManager::new()
+            .with_tezos_client(TezosClient::new(&server.uri(), TEST_CONTRACT))
+            .with_spending_keys(test_spending_keys());
+        let note = create_note(1000, Secret::new(vec![5; 32])).unwrap();
+        let other = create_note(10, Secret::new(vec![6; 32])).unwrap();
+        manager.add_note("note".to_string(), note.clone()).unwrap();
+        manager.apply_scanned_commitments(vec![other.commitment, note.commitment]).unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_spend_note_end_to_end() {
+        let server = mock_node(ResponseTemplate::new(200).set_body_json("ooSpendHash")).await;
+        let mut manager = synced_manager(&server);
+
+        let receipt = manager.spend_note("note", 1000, TEST_SECRET_KEY).await.unwrap();
+        assert_eq!(receipt.operation.operation_hash, "ooSpendHash");
+        assert_eq!(receipt.root, manager.commitment_tree().root_bytes());
+        assert_eq!(receipt.amount, 1000);
+        assert_eq!(manager.get_note("note").unwrap().state, NoteState::Spent);
+        assert!(manager.is_nullifier_spent(&receipt.nullifier));
+
+        // No local change note is invented for a remainder the chain never sees
+        assert_eq!(manager.list_notes().len(), 1);
+        assert_eq!(manager.balance(), 0);
+
+        // The injected operation carries the nullifier and a proof the verifier accepts
+        let requests = server.received_requests().await.unwrap();
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let args = &injected["contents"][0]["parameters"]["value"]["args"];
+        assert_eq!(args[0]["bytes"], hex::encode(&receipt.nullifier));
+        let proof = crypto::SpendingProof::from_bytes(&hex::decode(args[1]["bytes"].as_str().unwrap()).unwrap()).unwrap();
+        assert!(crypto::verify_spending_proof(&test_spending_keys().verifying_key, &proof, &receipt.root, &receipt.nullifier).unwrap());
+
+        assert!(manager.spend_note("note", 1000, TEST_SECRET_KEY).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_spend_sends_real_proof() {
+        let server = mock_node(ResponseTemplate::new(200).set_body_json("ooSyncHash")).await;
+        let mut manager = synced_manager(&server);
+        let nullifier = manager.generate_nullifier_for_note("note", vec![5; 32]).unwrap();
+
+        let result = manager.sync_spend_to_tezos("note", TEST_SECRET_KEY).await.unwrap();
+        assert_eq!(result.operation_hash, "ooSyncHash");
+
+        let requests = server.received_requests().await.unwrap();
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let args = &injected["contents"][0]["parameters"]["value"]["args"];
+        assert_eq!(args[0]["bytes"], hex::encode(&nullifier));
+        let proof = crypto::SpendingProof::from_bytes(&hex::decode(args[1]["bytes"].as_str().unwrap()).unwrap()).unwrap();
+        let root = manager.commitment_tree().root_bytes();
+        assert!(crypto::verify_spending_proof(&test_spending_keys().verifying_key, &proof, &root, &nullifier).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failed_spend_returns_note_to_unspent() {
+        let server = mock_node(ResponseTemplate::new(500)).await;
+        let mut manager = synced_manager(&server);
+
+        assert!(manager.spend_note("note", 1000, TEST_SECRET_KEY).await.is_err());
+        let entry = manager.get_note("note").unwrap();
+        assert_eq!(entry.state, NoteState::Unspent);
+        assert!(!manager.is_nullifier_spent(&entry.nullifier.unwrap()));
+        assert_eq!(manager.spendable_balance(), 1000);
+
+        // Invalid amounts are rejected before anything is submitted
+        assert!(manager.spend_note("note", 0, TEST_SECRET_KEY).await.is_err());
+        assert!(manager.spend_note("note", 1001, TEST_SECRET_KEY).await.is_err());
+        // A partial spend would nullify the remainder with the note
+        assert!(manager.spend_note("note", 300, TEST_SECRET_KEY).await.is_err());
+        assert_eq!(manager.get_note("note").unwrap().state, NoteState::Unspent);
+    }
+}
@@ -3,15 +3,25 @@
 //! This module provides a client for interacting with the Tezos blockchain,
 //! specifically for depositing commitments and spending nullifiers on-chain.
 
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 use reqwest::Client;
 use crate::error::SparkResult;
 use crate::manager::PublicNote;
+use crate::micheline::{Micheline, ToMicheline};
+use crate::tezos_batch::{BatchCall, BatchResult, OperationBatch};
+use crate::tezos_fees::{apply_estimate, prepare_simulation, OperationLimits, Simulation};
+use crate::tezos_forge::{OperationContent, Parameters, Reveal, Transaction, UnsignedOperation};
+use crate::tezos_keys::{TezosSecretKey, TezosSignature};
+use crate::tezos_receipts::{OperationReceipt, CONFIRMATION_LOOKBACK, CONFIRMATION_TIMEOUT_BLOCKS, MANAGER_OPERATIONS_PASS};
+use crate::tezos_storage::{script_expr_hash, IndexerKey, RegistryStorage, BIG_MAP_PAGE_SIZE};
 
 /// Result of a Tezos operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TezosOperationResult {
     pub operation_hash: String,
+    /// Always `"pending"` when injected; see [`TezosClient::wait_for_confirmation`]
     pub status: String,
 }
 
@@ -24,6 +34,14 @@ pub struct TezosClient {
     contract_address: String,
     #[allow(dead_code)]
     client: Client,
+    /// Also forge through the node and compare with the local bytes
+    rpc_forge_check: bool,
+    /// Caller overrides of the estimated fee and limits
+    limits: OperationLimits,
+    /// Delay between two polls of the head while waiting for confirmations
+    poll_interval: Duration,
+    /// Base URL of a TzKT-compatible indexer, used to list big-map keys
+    indexer_url: Option<String>,
 }
 
 impl TezosClient {
@@ -33,67 +51,180 @@ impl TezosClient {
             rpc_node: rpc_node.to_string(),
             contract_address: contract_address.to_string(),
             client: Client::new(),
+            rpc_forge_check: false,
+            limits: OperationLimits::default(),
+            poll_interval: Duration::from_secs(5),
+            indexer_url: None,
         }
     }
 
+    /// Cross-check every locally forged operation with the node's forging RPC
+    ///
+    /// Operations are always forged locally; with the check enabled, the
+    /// node's bytes must match or the operation is not sent.
+    pub fn with_rpc_forge_check(mut self, enabled: bool) -> Self {
+        self.rpc_forge_check = enabled;
+        self
+    }
+
+    /// Fix the fee, gas limit or storage limit of every operation
+    ///
+    /// Anything left unset is estimated by simulating the operation (see
+    /// [`simulate_operation`](Self::simulate_operation)).
+    pub fn with_limits(mut self, limits: OperationLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set how often the head is polled while waiting for confirmations
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Use a TzKT-compatible indexer (e.g. `https://api.ghostnet.tzkt.io`)
+    ///
+    /// Nodes cannot list big-map keys, so fetching commitments and spent
+    /// nullifiers requires one.
+    pub fn with_indexer(mut self, indexer_url: &str) -> Self {
+        self.indexer_url = Some(indexer_url.trim_end_matches('/').to_string());
+        self
+    }
+
     /// Deposit a commitment on-chain
+    ///
+    /// The note value is sent as the operation amount (in mutez), and `proof`
+    /// must be a [`DepositProof`](crate::crypto::DepositProof) showing that the
+    /// commitment opens to that amount. The proof is checked before anything
+    /// is sent to the node.
+    ///
+    /// `secret_key` is an `edsk`, `spsk` or `p2sk` key (see
+    /// [`TezosSecretKey`]); the operation is sent from its implicit account,
+    /// preceded by a reveal if the account has not revealed its key yet.
     pub async fn deposit(
         &self,
         note: &PublicNote,
         proof: &[u8],
         secret_key: &str,
     ) -> SparkResult<TezosOperationResult> {
-        println!("Depositing commitment {} to Tezos contract {}...", 
-                hex::encode(&note.commitment), self.contract_address);
-        
-        // Get current head for branch
-        let branch = self.get_head_hash().await?;
-        
-        // Get sender address from secret key (simplified - in real impl use proper key derivation)
-        let sender_address = self.derive_address_from_secret(secret_key)?;
-        
-        // Get counter for the sender
-        let counter = self.get_counter(&sender_address).await?;
-        let next_counter = counter + 1;
-        
-        // Forge the operation (simplified Michelson call)
-        let operation = self.forge_deposit_operation(&branch, next_counter, note, proof)?;
-        
-        // Sign the operation
-        let signature = self.sign_operation(&operation, secret_key)?;
-        
-        // Inject the operation
-        let op_hash = self.inject_operation(&operation, &signature).await?;
-        
-        Ok(TezosOperationResult {
-            operation_hash: op_hash,
-            status: "pending".to_string(),
-        })
+        if !deposit_proof_opens(note, proof)? {
+            return Err(crate::error::SparkError::invalid_proof(
+                "Deposit proof does not open the commitment to the deposited amount",
+            ));
+        }
+
+        let call = BatchCall::Deposit { note: note.clone(), proof: proof.to_vec() };
+        let (operation, _) = self.submit_calls([&call], secret_key).await?;
+        Ok(operation)
     }
 
     /// Spend a nullifier on-chain
+    ///
+    /// `secret_key` is an `edsk`, `spsk` or `p2sk` key (see
+    /// [`TezosSecretKey`]); the operation is sent from its implicit account,
+    /// preceded by a reveal if the account has not revealed its key yet.
     pub async fn spend(
         &self,
         nullifier: &[u8],
         proof: &[u8],
         secret_key: &str,
     ) -> SparkResult<TezosOperationResult> {
-         println!("Spending nullifier {} on Tezos contract {}...", 
-                 hex::encode(nullifier), self.contract_address);
-
-         let branch = self.get_head_hash().await?;
-         let sender_address = self.derive_address_from_secret(secret_key)?;
-         let counter = self.get_counter(&sender_address).await?;
-         let next_counter = counter + 1;
-         
-         let operation = self.forge_spend_operation(&branch, next_counter, nullifier, proof)?;
-         let signature = self.sign_operation(&operation, secret_key)?;
-         let op_hash = self.inject_operation(&operation, &signature).await?;
-         
-         Ok(TezosOperationResult {
-             operation_hash: op_hash,
-             status: "pending".to_string(),
-         })
+        let call = BatchCall::Spend { nullifier: nullifier.to_vec(), proof: proof.to_vec() };
+        let (operation, _) = self.submit_calls([&call], secret_key).await?;
+        Ok(operation)
+    }
+
+    /// Send several deposits and spends as one operation
+    ///
+    /// The calls get sequential counters and share one branch, simulation,
+    /// signature and injection. If the source account has not revealed its
+    /// public key, a reveal is placed before them. Deposit proofs are checked
+    /// as in [`deposit`](Self::deposit) before anything is sent.
+    ///
+    /// # Errors
+    /// Fails if the batch is empty, a deposit proof does not open its
+    /// commitment, or any step of the submission fails. Nothing is injected
+    /// unless every call simulates successfully.
+    pub async fn submit_batch(&self, batch: &OperationBatch, secret_key: &str) -> SparkResult<BatchResult> {
+        if batch.is_empty() {
+            return Err(crate::error::SparkError::tezos_error("Operation batch is empty"));
+        }
+        for (id, call) in batch.calls() {
+            if let BatchCall::Deposit { note, proof } = call {
+                if !deposit_proof_opens(note, proof)? {
+                    return Err(crate::error::SparkError::invalid_proof(format!(
+                        "Deposit proof of note {} does not open the commitment to the deposited amount",
+                        id
+                    )));
+                }
+            }
+        }
+
+        let (operation, revealed) = self.submit_calls(batch.calls().iter().map(|(_, call)| call), secret_key).await?;
+        Ok(BatchResult {
+            operation,
+            revealed,
+            note_ids: batch.calls().iter().map(|(id, _)| id.clone()).collect(),
+        })
+    }
+
+    /// Sign and inject contract calls as one operation from the implicit
+    /// account of `secret_key`
+    ///
+    /// The calls share one branch and get sequential counters. If the account
+    /// has not revealed its public key yet, a reveal is placed before them.
+    ///
+    /// # Returns
+    /// The injected operation, and whether a reveal was sent
+    async fn submit_calls<'a>(
+        &self,
+        calls: impl IntoIterator<Item = &'a BatchCall>,
+        secret_key: &str,
+    ) -> SparkResult<(TezosOperationResult, bool)> {
+        let key = TezosSecretKey::from_base58(secret_key)?;
+
+        let branch = self.get_head_hash().await?;
+        let sender_address = key.address();
+        let mut counter = self.get_counter(&sender_address).await?;
+
+        let mut operation = UnsignedOperation { branch: branch.clone(), contents: Vec::new() };
+        let needs_reveal = self.get_manager_key(&sender_address).await?.is_none();
+        if needs_reveal {
+            counter += 1;
+            operation.contents.push(OperationContent::Reveal(Reveal {
+                source: sender_address.clone(),
+                fee: 0,
+                counter,
+                gas_limit: 0,
+                storage_limit: 0,
+                public_key: key.public_key().to_base58(),
+            }));
+        }
+        for call in calls {
+            counter += 1;
+            let call = match call {
+                BatchCall::Deposit { note, proof } => {
+                    self.forge_deposit_operation(&branch, &sender_address, counter, note, proof)
+                }
+                BatchCall::Spend { nullifier, proof } => {
+                    self.forge_spend_operation(&branch, &sender_address, counter, nullifier, proof)
+                }
+            };
+            operation.contents.extend(call.contents);
+        }
+
+        self.estimate(&mut operation).await?;
+        let forged = self.forge(&operation).await?;
+        let signature = self.sign_operation(&forged, &key)?;
+        let op_hash = self.inject_operation(&forged, &signature).await?;
+
+        Ok((
+            TezosOperationResult {
+                operation_hash: op_hash,
+                status: "pending".to_string(),
+            },
+            needs_reveal,
+        ))
     }
 
     /// Helper to get the current head hash (branch) from RPC
@@ -106,6 +237,90 @@ impl TezosClient {
         Ok(hash)
     }
 
+    /// Level of the current head
+    async fn get_head_level(&self) -> SparkResult<u64> {
+        let url = format!("{}/chains/main/blocks/head/header", self.rpc_node);
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to get head: {}", e) })?;
+        let header: serde_json::Value = resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse head: {}", e) })?;
+        header["level"].as_u64()
+            .ok_or_else(|| crate::error::SparkError::tezos_error("Head header has no level"))
+    }
+
+    /// Hash of the block at `level` on the main chain
+    async fn get_block_hash(&self, level: u64) -> SparkResult<String> {
+        let url = format!("{}/chains/main/blocks/{}/hash", self.rpc_node, level);
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to get block {}: {}", level, e) })?;
+        resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse block {}: {}", level, e) })
+    }
+
+    /// Manager operations of a block, with their results
+    async fn get_manager_operations(&self, block_hash: &str) -> SparkResult<serde_json::Value> {
+        let url = format!("{}/chains/main/blocks/{}/operations/{}", self.rpc_node, block_hash, MANAGER_OPERATIONS_PASS);
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to get operations of {}: {}", block_hash, e) })?;
+        resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse operations of {}: {}", block_hash, e) })
+    }
+
+    /// Wait until an injected operation is included and confirmed
+    ///
+    /// Polls the head every [`with_poll_interval`](Self::with_poll_interval),
+    /// searching each new block (and the few before the call) for the
+    /// operation. If the including block is replaced by a reorganisation,
+    /// the search resumes from its level.
+    ///
+    /// # Arguments
+    /// * `operation_hash` - Hash returned by the injection
+    /// * `confirmations` - Blocks required from the including block to the head, counting both (at least 1)
+    ///
+    /// # Returns
+    /// The receipt, whether the operation was applied or failed
+    ///
+    /// # Errors
+    /// Fails if the operation is not included within
+    /// [`CONFIRMATION_TIMEOUT_BLOCKS`] blocks, or on RPC errors
+    pub async fn wait_for_confirmation(&self, operation_hash: &str, confirmations: u64) -> SparkResult<OperationReceipt> {
+        let confirmations = confirmations.max(1);
+        let start = self.get_head_level().await?;
+        let mut next_level = start.saturating_sub(CONFIRMATION_LOOKBACK).max(1);
+        let mut found: Option<OperationReceipt> = None;
+        loop {
+            let head = self.get_head_level().await?;
+            if let Some(receipt) = &found {
+                if self.get_block_hash(receipt.level).await? != receipt.block_hash {
+                    next_level = receipt.level;
+                    found = None;
+                }
+            }
+            while found.is_none() && next_level <= head {
+                let block_hash = self.get_block_hash(next_level).await?;
+                let operations = self.get_manager_operations(&block_hash).await?;
+                found = OperationReceipt::from_block_operations(&operations, operation_hash, &block_hash, next_level)?;
+                next_level += 1;
+            }
+            match &mut found {
+                Some(receipt) => {
+                    receipt.confirmations = head + 1 - receipt.level;
+                    if receipt.confirmations >= confirmations {
+                        return Ok(receipt.clone());
+                    }
+                }
+                None if head >= start + CONFIRMATION_TIMEOUT_BLOCKS => {
+                    return Err(crate::error::SparkError::tezos_error(format!(
+                        "Operation {} was not included within {} blocks",
+                        operation_hash, CONFIRMATION_TIMEOUT_BLOCKS
+                    )));
+                }
+                None => {}
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
     /// Fetch the full storage of the contract
     pub async fn get_contract_storage(&self) -> SparkResult<serde_json::Value> {
         let url = format!("{}/chains/main/blocks/head/context/contracts/{}/storage", self.rpc_node, self.contract_address);
@@ -116,33 +331,146 @@ impl TezosClient {
         Ok(storage)
     }
 
-    /// Fetch keys from a BigMap (Simulation of Indexer/RPC query)
-    /// In a real scenario, this would iterate over the big_map keys via TzKT or a node with indexer.
-    pub async fn get_big_map_keys(&self, _big_map_id: i64) -> SparkResult<Vec<Vec<u8>>> {
-        // --- Ghostnet Indexer Logic ---
-        // let url = format!("https://api.ghostnet.tzkt.io/v1/bigmaps/{}/keys", big_map_id);
-        
-        // For POC, return dummy keys that represent "found" commitments
-        Ok(vec![
-            vec![0u8; 32],
-            vec![1u8; 32],
-        ])
+    /// Fetch and parse the registry storage
+    pub async fn get_registry_storage(&self) -> SparkResult<RegistryStorage> {
+        RegistryStorage::from_json(&self.get_contract_storage().await?)
+    }
+
+    /// Look up one big-map value by key
+    ///
+    /// # Arguments
+    /// * `big_map_id` - Big map to read
+    /// * `key` - Key, e.g. `nullifier.to_micheline()` for a `bytes` key
+    ///
+    /// # Returns
+    /// The value, or `None` if the key is absent
+    pub async fn get_big_map_value(&self, big_map_id: u64, key: &Micheline) -> SparkResult<Option<Micheline>> {
+        let url = format!(
+            "{}/chains/main/blocks/head/context/big_maps/{}/{}",
+            self.rpc_node, big_map_id, script_expr_hash(key)?
+        );
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to read big map {}: {}", big_map_id, e) })?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(crate::error::SparkError::OperationError {
+                message: format!("Big map lookup failed with status: {}", resp.status())
+            });
+        }
+        let value: serde_json::Value = resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse big map value: {}", e) })?;
+        Micheline::from_json(&value).map(Some)
     }
 
-    /// Fetch deposit events (commitments) from the contract storage
-    /// For CameLIGO big_maps, we would query the big_map contents via RPC or Indexer.
+    /// Whether the contract has recorded this nullifier as spent
+    pub async fn is_nullifier_spent(&self, nullifier: &[u8]) -> SparkResult<bool> {
+        let storage = self.get_registry_storage().await?;
+        let value = self.get_big_map_value(storage.nullifiers, &nullifier.to_micheline()).await?;
+        Ok(value.is_some())
+    }
+
+    /// List every key of a `bytes`-keyed big map through the indexer
+    ///
+    /// Keys are fetched in pages of [`BIG_MAP_PAGE_SIZE`] and returned in
+    /// insertion order.
+    ///
+    /// # Errors
+    /// Fails if no indexer is configured (see [`with_indexer`](Self::with_indexer))
+    /// or a key is not bytes
+    pub async fn get_big_map_keys(&self, big_map_id: u64) -> SparkResult<Vec<Vec<u8>>> {
+        let indexer = self.indexer_url.as_ref().ok_or_else(|| {
+            crate::error::SparkError::tezos_error("Listing big-map keys requires an indexer")
+        })?;
+        let url = format!("{}/v1/bigmaps/{}/keys", indexer, big_map_id);
+        let limit = BIG_MAP_PAGE_SIZE.to_string();
+
+        let mut keys = Vec::new();
+        let mut last_id: Option<u64> = None;
+        loop {
+            let mut query = vec![("active", "true".to_string()), ("sort.asc", "id".to_string()), ("limit", limit.clone())];
+            if let Some(id) = last_id {
+                query.push(("offset.cr", id.to_string()));
+            }
+            let resp = self.client.get(&url).query(&query).send().await
+                .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to list big map {}: {}", big_map_id, e) })?;
+            if !resp.status().is_success() {
+                return Err(crate::error::SparkError::OperationError {
+                    message: format!("Listing big map {} failed with status: {}", big_map_id, resp.status())
+                });
+            }
+            let page: Vec<IndexerKey> = resp.json().await
+                .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse big map keys: {}", e) })?;
+            for key in &page {
+                keys.push(key.bytes()?);
+            }
+            match page.last() {
+                Some(key) if page.len() == BIG_MAP_PAGE_SIZE => last_id = Some(key.id),
+                _ => return Ok(keys),
+            }
+        }
+    }
+
+    /// Fetch every commitment deposited in the contract, in deposit order
     pub async fn fetch_deposit_events(&self) -> SparkResult<Vec<Vec<u8>>> {
-        println!("Fetching commitments from Tezos contract {}...", self.contract_address);
-        
-        // 1. Get storage to find the commitments big_map ID
-        let storage = self.get_contract_storage().await.unwrap_or_default();
-        
-        // Michelson storage for our CameLIGO contract usually looks like:
-        // Pair (Pair (Big_map_ID commitments) (Big_map_ID nullifiers)) (Bytes vk_hash)
-        // We simulate finding the ID 123 here.
-        let big_map_id = storage.get("args").and_then(|a| a.get(0)).and_then(|a| a.get("args")).and_then(|a| a.get(0)).and_then(|a| a.as_i64()).unwrap_or(123);
-        
-        self.get_big_map_keys(big_map_id).await
+        let storage = self.get_registry_storage().await?;
+        self.get_big_map_keys(storage.commitments).await
+    }
+
+    /// Fetch every spent nullifier recorded by the contract
+    pub async fn fetch_spent_nullifiers(&self) -> SparkResult<Vec<Vec<u8>>> {
+        let storage = self.get_registry_storage().await?;
+        self.get_big_map_keys(storage.nullifiers).await
+    }
+
+    /// Get the chain identifier
+    async fn get_chain_id(&self) -> SparkResult<String> {
+        let url = format!("{}/chains/main/chain_id", self.rpc_node);
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to get chain id: {}", e) })?;
+        resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse chain id: {}", e) })
+    }
+
+    /// Simulate an operation without signing it
+    ///
+    /// The operation runs with a zero fee and the largest gas and storage
+    /// limits, so the result reports what it actually consumes and any
+    /// error it would fail with.
+    pub async fn simulate_operation(&self, operation: &UnsignedOperation) -> SparkResult<Simulation> {
+        let mut simulated = operation.clone();
+        prepare_simulation(&mut simulated);
+        let mut signed = simulated.to_json();
+        signed["signature"] = TezosSignature::placeholder().to_base58().into();
+        let body = serde_json::json!({
+            "operation": signed,
+            "chain_id": self.get_chain_id().await?,
+        });
+
+        let url = format!("{}/chains/main/blocks/head/helpers/scripts/simulate_operation", self.rpc_node);
+        let resp = self.client.post(url)
+            .json(&body)
+            .send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to simulate operation: {}", e) })?;
+        if !resp.status().is_success() {
+            return Err(crate::error::SparkError::OperationError {
+                message: format!("Simulation failed with status: {}", resp.status())
+            });
+        }
+        let result: serde_json::Value = resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse simulation: {}", e) })?;
+        Simulation::from_json(&result)
+    }
+
+    /// Set the fee and limits, simulating the operation unless they are all fixed
+    async fn estimate(&self, operation: &mut UnsignedOperation) -> SparkResult<()> {
+        let simulation = if self.limits.needs_simulation() {
+            Some(self.simulate_operation(operation).await?)
+        } else {
+            None
+        };
+        apply_estimate(operation, simulation.as_ref(), &self.limits)
     }
 
     /// Get the counter for an address
@@ -156,57 +484,85 @@ impl TezosClient {
             .map_err(|e| crate::error::SparkError::OperationError { message: format!("Invalid counter: {}", e) })
     }
 
-    /// Derive address from secret key (simplified)
-    fn derive_address_from_secret(&self, _secret_key: &str) -> SparkResult<String> {
-        // In real implementation, derive from ed25519 public key
-        // For POC, return a dummy address
-        Ok("tz1DummyAddressForPOC".to_string())
+    /// Get the revealed public key of an account, `None` if it is not revealed
+    async fn get_manager_key(&self, address: &str) -> SparkResult<Option<String>> {
+        let url = format!("{}/chains/main/blocks/head/context/contracts/{}/manager_key", self.rpc_node, address);
+        let resp = self.client.get(url).send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to get manager key: {}", e) })?;
+        resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse manager key: {}", e) })
     }
 
     /// Forge a deposit operation
-    fn forge_deposit_operation(&self, branch: &str, counter: u64, note: &PublicNote, proof: &[u8]) -> SparkResult<serde_json::Value> {
-        // Simplified Michelson operation forging
-        let operation = serde_json::json!({
-            "branch": branch,
-            "contents": [{
-                "kind": "transaction",
-                "source": "tz1DummyAddressForPOC",
-                "fee": "10000",
-                "counter": counter.to_string(),
-                "gas_limit": "20000",
-                "storage_limit": "1000",
-                "amount": "0",
-                "destination": self.contract_address,
-                "parameters": {
-                    "entrypoint": "deposit",
-                    "value": {
-                        "prim": "Pair",
-                        "args": [
-                            {"bytes": hex::encode(&note.commitment)},
-                            {"bytes": hex::encode(proof)}
-                        ]
-                    }
-                }
-            }]
-        });
-        Ok(operation)
+    fn forge_deposit_operation(&self, branch: &str, source: &str, counter: u64, note: &PublicNote, proof: &[u8]) -> UnsignedOperation {
+        let value = (note.commitment.as_slice(), proof).to_micheline();
+        self.contract_call(branch, source, counter, note.value, "deposit", value)
     }
 
-    /// Sign an operation (simplified)
-    fn sign_operation(&self, _operation: &serde_json::Value, _secret_key: &str) -> SparkResult<String> {
-        // In real implementation, hash and sign with ed25519
-        // For POC, return dummy signature
-        Ok("edsigDummySignatureForPOC".to_string())
+    /// Build a call to the contract
+    ///
+    /// The fee and limits are left at zero until [`estimate`](Self::estimate).
+    fn contract_call(&self, branch: &str, source: &str, counter: u64, amount: u64, entrypoint: &str, value: Micheline) -> UnsignedOperation {
+        UnsignedOperation {
+            branch: branch.to_string(),
+            contents: vec![OperationContent::Transaction(Transaction {
+                source: source.to_string(),
+                fee: 0,
+                counter,
+                gas_limit: 0,
+                storage_limit: 0,
+                amount,
+                destination: self.contract_address.clone(),
+                parameters: Some(Parameters { entrypoint: entrypoint.to_string(), value }),
+            })],
+        }
     }
 
-    /// Inject an operation
-    async fn inject_operation(&self, operation: &serde_json::Value, signature: &str) -> SparkResult<String> {
-        let signed_op = serde_json::json!({
-            "signed_operation": {
-                "operation": operation,
-                "signature": signature
+    /// Forge an operation locally, checking it against the node if enabled
+    async fn forge(&self, operation: &UnsignedOperation) -> SparkResult<Vec<u8>> {
+        let forged = operation.forge()?;
+        if self.rpc_forge_check {
+            let remote = self.forge_remote(operation).await?;
+            if remote != forged {
+                return Err(crate::error::SparkError::tezos_error(format!(
+                    "Local forging does not match the node: {} != {}",
+                    hex::encode(&forged),
+                    hex::encode(&remote)
+                )));
             }
-        });
+        }
+        Ok(forged)
+    }
+
+    /// Forge an operation with the node's `helpers/forge/operations` RPC
+    pub async fn forge_remote(&self, operation: &UnsignedOperation) -> SparkResult<Vec<u8>> {
+        let url = format!("{}/chains/main/blocks/head/helpers/forge/operations", self.rpc_node);
+        let resp = self.client.post(url)
+            .json(&operation.to_json())
+            .send().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to forge operation: {}", e) })?;
+        if !resp.status().is_success() {
+            return Err(crate::error::SparkError::OperationError {
+                message: format!("Forging failed with status: {}", resp.status())
+            });
+        }
+        let forged: String = resp.json().await
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Failed to parse forged operation: {}", e) })?;
+        hex::decode(forged)
+            .map_err(|e| crate::error::SparkError::OperationError { message: format!("Invalid forged operation: {}", e) })
+    }
+
+    /// Sign a forged operation with the generic-operation watermark
+    fn sign_operation(&self, forged: &[u8], key: &TezosSecretKey) -> SparkResult<TezosSignature> {
+        key.sign_operation(forged)
+    }
+
+    /// Inject a signed operation
+    ///
+    /// The node expects the hex encoding of the forged bytes followed by the
+    /// raw 64-byte signature.
+    async fn inject_operation(&self, forged: &[u8], signature: &TezosSignature) -> SparkResult<String> {
+        let signed_op = hex::encode([forged, signature.as_bytes()].concat());
 
         let url = format!("{}/injection/operation", self.rpc_node);
         let resp = self.client.post(url)
@@ -226,30 +582,491 @@ impl TezosClient {
     }
 
     /// Forge a spend operation
-    fn forge_spend_operation(&self, branch: &str, counter: u64, nullifier: &[u8], proof: &[u8]) -> SparkResult<serde_json::Value> {
-        let operation = serde_json::json!({
-            "branch": branch,
-            "contents": [{
-                "kind": "transaction",
-                "source": "tz1DummyAddressForPOC",
-                "fee": "10000",
-                "counter": counter.to_string(),
-                "gas_limit": "20000",
-                "storage_limit": "1000",
-                "amount": "0",
-                "destination": self.contract_address,
-                "parameters": {
-                    "entrypoint": "spend",
-                    "value": {
-                        "prim": "Pair",
-                        "args": [
-                            {"bytes": hex::encode(nullifier)},
-                            {"bytes": hex::encode(proof)}
-                        ]
-                    }
-                }
-            }]
-        });
-        Ok(operation)
+    fn forge_spend_operation(&self, branch: &str, source: &str, counter: u64, nullifier: &[u8], proof: &[u8]) -> UnsignedOperation {
+        let value = (nullifier, proof).to_micheline();
+        self.contract_call(branch, source, counter, 0, "spend", value)
+    }
+}
+
+/// Whether `proof` is a [`DepositProof`](crate::crypto::DepositProof) opening
+/// the note's commitment to its value
+fn deposit_proof_opens(note: &PublicNote, proof: &[u8]) -> SparkResult<bool> {
+    let deposit_proof = crate::crypto::DepositProof::from_bytes(proof)?;
+    crate::crypto::verify_deposit_proof(&note.commitment, note.value, &deposit_proof)
+}
+
+/// Decode the body of an injection request, checking its signature
+#[cfg(test)]
+pub(crate) fn decode_injected(body: &[u8], secret_key: &str) -> UnsignedOperation {
+    let signed = hex::decode(serde_json::from_slice::<String>(body).unwrap()).unwrap();
+    let (forged, signature) = signed.split_at(signed.len() - 64);
+    // Signatures are deterministic, so the key must produce the same bytes
+    let key = TezosSecretKey::from_base58(secret_key).unwrap();
+    assert_eq!(key.sign_operation(forged).unwrap().as_bytes().as_slice(), signature);
+    UnsignedOperation::decode(forged).unwrap()
+}
+
+/// Mock the chain id, a revealed manager key, and a simulation in which each
+/// content consumes 1500 gas and 100 bytes
+#[cfg(test)]
+pub(crate) async fn mount_simulation(server: &wiremock::MockServer) {
+    use wiremock::matchers::{method, path, path_regex};
+    use wiremock::{Mock, Request, ResponseTemplate};
+
+    Mock::given(method("GET"))
+        .and(path("/chains/main/chain_id"))
+        .respond_with(ResponseTemplate::new(200).set_body_json("NetXdQprcVkpaWU"))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/chains/main/blocks/head/context/contracts/[^/]+/manager_key$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json("edpkvGfYw3LyB1UcCahKQk4rF2tvbMUk8GFiTuMjL75uGXrpvKXhjn"))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/chains/main/blocks/head/helpers/scripts/simulate_operation"))
+        .respond_with(|request: &Request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let contents: Vec<serde_json::Value> = body["operation"]["contents"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|content| serde_json::json!({
+                    "kind": content["kind"],
+                    "metadata": {"operation_result": {
+                        "status": "applied",
+                        "consumed_milligas": "1500000",
+                        "paid_storage_size_diff": "100"
+                    }}
+                }))
+                .collect();
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"contents": contents}))
+        })
+        .mount(server)
+        .await;
+}
+
+/// Mock node answering the branch and counter lookups, the simulation, and
+/// injections with `injection`
+#[cfg(test)]
+pub(crate) async fn mock_node(injection: wiremock::ResponseTemplate) -> wiremock::MockServer {
+    use wiremock::matchers::{method, path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/chains/main/blocks/head/hash"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(TEST_BRANCH))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/chains/main/blocks/head/context/contracts/[^/]+/counter$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json("7"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/injection/operation"))
+        .respond_with(injection)
+        .mount(&server)
+        .await;
+    mount_simulation(&server).await;
+    server
+}
+
+/// Contract address used by the tests
+#[cfg(test)]
+pub(crate) const TEST_CONTRACT: &str = "KT1M7dL5CKWHiZhoTZCMdEbX17z7nSvWkW9r";
+/// Block hash returned by the tests' mock nodes
+#[cfg(test)]
+pub(crate) const TEST_BRANCH: &str = "BLgoXvMtV5TqCwMtszCjEcnFT15R6B4ESk6GiajPh72rq4yaHuv";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::create_note;
+    use crate::secret::Secret;
+    use crate::tezos_keys::TEST_SECRET_KEY;
+    use crate::tezos_storage::test_storage;
+    use wiremock::matchers::{method, path, path_regex, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn deposit_node() -> MockServer {
+        mock_node(ResponseTemplate::new(200).set_body_json("ooDepositHash")).await
+    }
+
+    /// Report the source account as not revealed
+    async fn mount_unrevealed(server: &MockServer) {
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/chains/main/blocks/head/context/contracts/[^/]+/manager_key$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::Value::Null))
+            .with_priority(1)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_deposit_requires_opening_proof() {
+        let server = deposit_node().await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let note = create_note(2500, Secret::new(vec![3; 32])).unwrap();
+        let public = PublicNote::from(&note);
+
+        // A proof for another amount is rejected before contacting the node
+        let wrong = crate::crypto::prove_deposit(2501, note.secret_bytes()).unwrap().to_bytes();
+        assert!(client.deposit(&public, &wrong, TEST_SECRET_KEY).await.is_err());
+        assert!(client.deposit(&public, &[0u8; 128], TEST_SECRET_KEY).await.is_err());
+        assert!(client.deposit(&public, &note.prove_deposit().unwrap().to_bytes(), "edsk_invalid").await.is_err());
+        assert!(server.received_requests().await.unwrap().is_empty());
+
+        let proof = note.prove_deposit().unwrap().to_bytes();
+        let result = client.deposit(&public, &proof, TEST_SECRET_KEY).await.unwrap();
+        assert_eq!(result.operation_hash, "ooDepositHash");
+
+        let requests = server.received_requests().await.unwrap();
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let contents = &injected["contents"][0];
+        assert_eq!(contents["amount"], "2500");
+        assert_eq!(contents["source"], "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb");
+        assert_eq!(contents["parameters"]["value"]["args"][1]["bytes"], hex::encode(&proof));
+        // Limits come from the simulation plus the safety margins
+        assert_eq!(contents["gas_limit"], "1600");
+        assert_eq!(contents["storage_limit"], "120");
+    }
+
+    #[tokio::test]
+    async fn test_single_calls_reveal_unrevealed_account() {
+        let server = deposit_node().await;
+        mount_unrevealed(&server).await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let note = create_note(2500, Secret::new(vec![3; 32])).unwrap();
+
+        client.deposit(&PublicNote::from(&note), &note.prove_deposit().unwrap().to_bytes(), TEST_SECRET_KEY).await.unwrap();
+        client.spend(&[5; 32], &[0; 128], TEST_SECRET_KEY).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let injected: Vec<_> = requests
+            .iter()
+            .filter(|r| r.url.path() == "/injection/operation")
+            .map(|r| decode_injected(&r.body, TEST_SECRET_KEY).to_json())
+            .collect();
+        assert_eq!(injected.len(), 2);
+        for operation in &injected {
+            let contents = operation["contents"].as_array().unwrap();
+            let kinds: Vec<_> = contents.iter().map(|c| c["kind"].as_str().unwrap()).collect();
+            assert_eq!(kinds, ["reveal", "transaction"]);
+            let counters: Vec<_> = contents.iter().map(|c| c["counter"].as_str().unwrap()).collect();
+            assert_eq!(counters, ["8", "9"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_operation_limits() {
+        let note = create_note(2500, Secret::new(vec![3; 32])).unwrap();
+        let public = PublicNote::from(&note);
+        let proof = note.prove_deposit().unwrap().to_bytes();
+
+        // Fixed gas and storage limits skip the simulation
+        let server = deposit_node().await;
+        let limits = OperationLimits::new().with_fee(9000).with_gas_limit(4000).with_storage_limit(300);
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_limits(limits);
+        client.deposit(&public, &proof, TEST_SECRET_KEY).await.unwrap();
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| !r.url.path().ends_with("/simulate_operation")));
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let contents = &injected["contents"][0];
+        assert_eq!(contents["fee"], "9000");
+        assert_eq!(contents["gas_limit"], "4000");
+        assert_eq!(contents["storage_limit"], "300");
+
+        // A failing simulation stops the operation before injection
+        let server = deposit_node().await;
+        Mock::given(method("POST"))
+            .and(path("/chains/main/blocks/head/helpers/scripts/simulate_operation"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "contents": [{"metadata": {"operation_result": {
+                    "status": "failed",
+                    "errors": [{"kind": "temporary", "id": "proto.script_rejected"}]
+                }}}]
+            })))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let err = client.deposit(&public, &proof, TEST_SECRET_KEY).await.unwrap_err();
+        assert!(err.to_string().contains("script_rejected"));
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| r.url.path() != "/injection/operation"));
+    }
+
+    /// A chain whose head is at `first_head` for two polls, then `later_head`,
+    /// with `ooDeposit` applied in the block at level 98
+    async fn mock_chain(first_head: u64, later_head: u64) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/head/header"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"level": first_head})))
+            .up_to_n_times(2)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/head/header"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"level": later_head})))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/98/hash"))
+            .respond_with(ResponseTemplate::new(200).set_body_json("BLockIncluded"))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/chains/main/blocks/\d+/hash$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json("BLockOther"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/BLockIncluded/operations/3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "hash": "ooDeposit",
+                "contents": [{"kind": "transaction", "metadata": {"operation_result": {
+                    "status": "applied",
+                    "consumed_milligas": "1500000"
+                }}}]
+            }])))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/BLockOther/operations/3"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_confirmation() {
+        let server = mock_chain(100, 102).await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_poll_interval(Duration::from_millis(1));
+
+        // Included before waiting started: found by looking back from the head
+        let receipt = client.wait_for_confirmation("ooDeposit", 3).await.unwrap();
+        assert_eq!((receipt.level, receipt.confirmations), (98, 3));
+        assert_eq!(receipt.block_hash, "BLockIncluded");
+        assert_eq!(receipt.status(), crate::tezos_fees::OperationStatus::Applied);
+
+        // More confirmations wait for the head to move
+        let server = mock_chain(100, 102).await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_poll_interval(Duration::from_millis(1));
+        let receipt = client.wait_for_confirmation("ooDeposit", 5).await.unwrap();
+        assert_eq!(receipt.confirmations, 5);
+
+        // An operation that never shows up is given up on
+        let server = mock_chain(100, 100 + CONFIRMATION_TIMEOUT_BLOCKS).await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_poll_interval(Duration::from_millis(1));
+        assert!(client.wait_for_confirmation("ooMissing", 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_big_map_keys() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/chains/main/blocks/head/context/contracts/{}/storage", TEST_CONTRACT)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(test_storage(31, 32)))
+            .mount(&server)
+            .await;
+        // A full first page, then the rest after the last id
+        let first: Vec<_> = (1..=BIG_MAP_PAGE_SIZE as u64)
+            .map(|id| serde_json::json!({"id": id, "key": hex::encode(id.to_be_bytes())}))
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/v1/bigmaps/31/keys"))
+            .and(query_param("sort.asc", "id"))
+            .and(query_param_is_missing("offset.cr"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(first))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/v1/bigmaps/31/keys"))
+            .and(query_param("offset.cr", BIG_MAP_PAGE_SIZE.to_string()))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {"id": 5000, "key": "aa"}
+            ])))
+            .mount(&server)
+            .await;
+
+        // Without an indexer, listing fails rather than returning nothing
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        assert!(client.fetch_deposit_events().await.is_err());
+
+        let client = client.with_indexer(&format!("{}/", server.uri()));
+        let commitments = client.fetch_deposit_events().await.unwrap();
+        assert_eq!(commitments.len(), BIG_MAP_PAGE_SIZE + 1);
+        assert_eq!(commitments[0], 1u64.to_be_bytes());
+        assert_eq!(commitments[BIG_MAP_PAGE_SIZE], vec![0xaa]);
+    }
+
+    #[tokio::test]
+    async fn test_big_map_lookup() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("/chains/main/blocks/head/context/contracts/{}/storage", TEST_CONTRACT)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(test_storage(31, 32)))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/chains/main/blocks/head/context/big_maps/32/exprvFZBZx8KL8Zadc1tNeijqe88GrCkpssB9EZuSFQKPsfGKCaTUw"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"prim": "Unit"})))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"^/chains/main/blocks/head/context/big_maps/"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        assert_eq!(client.get_registry_storage().await.unwrap().nullifiers, 32);
+        assert!(client.is_nullifier_spent(&[0x00, 0x11, 0x22, 0x33]).await.unwrap());
+        assert!(!client.is_nullifier_spent(&[0x00, 0x11, 0x22, 0x34]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unexpected_storage_fails() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex(r"/storage$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"prim": "Unit"})))
+            .mount(&server)
+            .await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT).with_indexer(&server.uri());
+        assert!(client.fetch_deposit_events().await.is_err());
+        assert!(client.fetch_spent_nullifiers().await.is_err());
+        // Nothing is listed from a guessed big map
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| !r.url.path().starts_with("/v1/")));
+    }
+
+    #[tokio::test]
+    async fn test_rpc_forge_check() {
+        let note = create_note(2500, Secret::new(vec![3; 32])).unwrap();
+        let public = PublicNote::from(&note);
+        let proof = note.prove_deposit().unwrap().to_bytes();
+        let limits = OperationLimits::new().with_gas_limit(4000).with_storage_limit(300);
+        let mut operation = TezosClient::new("", TEST_CONTRACT)
+            .forge_deposit_operation(TEST_BRANCH, "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb", 8, &public, &proof);
+        apply_estimate(&mut operation, None, &limits).unwrap();
+        let forged = operation.forge().unwrap();
+
+        // A node that agrees with the local bytes
+        let server = deposit_node().await;
+        Mock::given(method("POST"))
+            .and(path("/chains/main/blocks/head/helpers/forge/operations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(hex::encode(&forged)))
+            .mount(&server)
+            .await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT)
+            .with_rpc_forge_check(true)
+            .with_limits(limits);
+        assert_eq!(client.forge_remote(&operation).await.unwrap(), forged);
+        client.deposit(&public, &proof, TEST_SECRET_KEY).await.unwrap();
+
+        // A node that disagrees stops the operation before injection
+        let server = deposit_node().await;
+        Mock::given(method("POST"))
+            .and(path("/chains/main/blocks/head/helpers/forge/operations"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(hex::encode(&forged[1..])))
+            .mount(&server)
+            .await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT)
+            .with_rpc_forge_check(true)
+            .with_limits(limits);
+        assert!(client.deposit(&public, &proof, TEST_SECRET_KEY).await.is_err());
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.iter().all(|r| r.url.path() != "/injection/operation"));
+    }
+
+    #[tokio::test]
+    async fn test_submit_batch() {
+        use crate::crypto::{verify_spending_proof, SpendingProof};
+        use crate::manager::NoteManager;
+        use crate::spend::test_spending_keys;
+
+        // Spend proofs need the notes in the commitment tree
+        let mut manager = NoteManager::new()
+            .with_tezos_client(TezosClient::new("http://unused", TEST_CONTRACT))
+            .with_spending_keys(test_spending_keys());
+        for (id, value, seed) in [("first", 2500, 3), ("second", 1000, 4), ("spent", 10, 5), ("other", 20, 6)] {
+            manager.add_note(id.to_string(), create_note(value, Secret::new(vec![seed; 32])).unwrap()).unwrap();
+        }
+        let spent = manager.generate_nullifier_for_note("spent", vec![5; 32]).unwrap();
+        let other = manager.generate_nullifier_for_note("other", vec![6; 32]).unwrap();
+        let commitments = ["spent", "other"].map(|id| manager.get_note(id).unwrap().note.commitment);
+        manager.apply_scanned_commitments(commitments.to_vec()).unwrap();
+        let root = manager.commitment_tree().root_bytes();
+        let batch = manager.prepare_batch(&["first", "second"], &["spent", "other"]).unwrap().prove().await.unwrap();
+
+        // An unrevealed account gets a reveal before its calls
+        let server = deposit_node().await;
+        mount_unrevealed(&server).await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let result = client.submit_batch(&batch, TEST_SECRET_KEY).await.unwrap();
+        assert!(result.revealed);
+        assert_eq!(result.note_ids, ["first", "second", "spent", "other"]);
+
+        let requests = server.received_requests().await.unwrap();
+        let injections = requests.iter().filter(|r| r.url.path() == "/injection/operation").count();
+        assert_eq!(injections, 1);
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let contents = injected["contents"].as_array().unwrap();
+        let kinds: Vec<_> = contents.iter().map(|c| c["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["reveal", "transaction", "transaction", "transaction", "transaction"]);
+        let counters: Vec<_> = contents.iter().map(|c| c["counter"].as_str().unwrap()).collect();
+        assert_eq!(counters, ["8", "9", "10", "11", "12"]);
+        assert_eq!(contents[0]["public_key"], "edpkvGfYw3LyB1UcCahKQk4rF2tvbMUk8GFiTuMjL75uGXrpvKXhjn");
+        assert_eq!(contents[1]["amount"], "2500");
+        assert_eq!(contents[2]["amount"], "1000");
+        // Every spend carries its nullifier and a proof the verifier accepts
+        for (content, nullifier) in contents[3..].iter().zip([&spent, &other]) {
+            assert_eq!(content["parameters"]["entrypoint"], "spend");
+            let args = &content["parameters"]["value"]["args"];
+            assert_eq!(args[0]["bytes"], hex::encode(nullifier));
+            let proof = SpendingProof::from_bytes(&hex::decode(args[1]["bytes"].as_str().unwrap()).unwrap()).unwrap();
+            assert!(verify_spending_proof(&test_spending_keys().verifying_key, &proof, &root, nullifier).unwrap());
+        }
+        // Every content is estimated from its own result
+        assert!(contents.iter().all(|c| c["gas_limit"] == "1600" && c["storage_limit"] == "120"));
+
+        // A revealed account sends only its calls
+        let server = deposit_node().await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let result = client.submit_batch(&batch, TEST_SECRET_KEY).await.unwrap();
+        assert!(!result.revealed);
+        let requests = server.received_requests().await.unwrap();
+        let injected = decode_injected(&requests.last().unwrap().body, TEST_SECRET_KEY).to_json();
+        let counters: Vec<_> = injected["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["counter"].as_str().unwrap())
+            .collect();
+        assert_eq!(counters, ["8", "9", "10", "11"]);
+    }
+
+    #[tokio::test]
+    async fn test_submit_batch_checks_calls() {
+        let server = deposit_node().await;
+        let client = TezosClient::new(&server.uri(), TEST_CONTRACT);
+        let note = create_note(2500, Secret::new(vec![3; 32])).unwrap();
+        let wrong = crate::crypto::prove_deposit(2501, note.secret_bytes()).unwrap().to_bytes();
+
+        assert!(client.submit_batch(&OperationBatch::new(), TEST_SECRET_KEY).await.is_err());
+        let batch = OperationBatch::new()
+            .with_spend("spent", vec![5; 32], vec![0; 128])
+            .with_deposit("note", PublicNote::from(&note), wrong);
+        let err = client.submit_batch(&batch, TEST_SECRET_KEY).await.unwrap_err();
+        assert!(err.to_string().contains("note"));
+        assert!(server.received_requests().await.unwrap().is_empty());
     }
 }
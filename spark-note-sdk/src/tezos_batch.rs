@@ -0,0 +1,156 @@
+//! Operation groups
+//!
+//! An [`OperationBatch`] collects several deposits and spends, each tagged
+//! with the note it is for, and [`TezosClient::submit_batch`](crate::tezos::TezosClient::submit_batch)
+//! sends them as one signed operation: one branch, counter and simulation
+//! lookup, sequential counters, and a single injection. When the source
+//! account has not revealed its public key yet, a reveal is placed first.
+//!
+//! The contents of an operation are applied together, so one failing call
+//! reverts the others. [`BatchResult::note_results`] pairs each note with the
+//! result of its call once the operation is included.
+
+use crate::error::{SparkError, SparkResult};
+use crate::manager::PublicNote;
+use crate::tezos::TezosOperationResult;
+use crate::tezos_fees::ContentResult;
+use crate::tezos_receipts::OperationReceipt;
+
+/// One contract call of a batch
+#[derive(Debug, Clone)]
+pub enum BatchCall {
+    /// Deposit a note's commitment, with its opening proof
+    Deposit {
+        /// Note deposited
+        note: PublicNote,
+        /// [`DepositProof`](crate::crypto::DepositProof) bytes
+        proof: Vec<u8>,
+    },
+    /// Spend a nullifier
+    Spend {
+        /// Nullifier spent
+        nullifier: Vec<u8>,
+        /// Spend proof bytes
+        proof: Vec<u8>,
+    },
+}
+
+/// Contract calls to send as one operation
+#[derive(Debug, Clone, Default)]
+pub struct OperationBatch {
+    calls: Vec<(String, BatchCall)>,
+}
+
+impl OperationBatch {
+    /// An empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a deposit for note `id`
+    pub fn with_deposit(mut self, id: &str, note: PublicNote, proof: Vec<u8>) -> Self {
+        self.calls.push((id.to_string(), BatchCall::Deposit { note, proof }));
+        self
+    }
+
+    /// Add a spend for note `id`
+    pub fn with_spend(mut self, id: &str, nullifier: Vec<u8>, proof: Vec<u8>) -> Self {
+        self.calls.push((id.to_string(), BatchCall::Spend { nullifier, proof }));
+        self
+    }
+
+    /// Calls in order, with the note each is for
+    pub fn calls(&self) -> &[(String, BatchCall)] {
+        &self.calls
+    }
+
+    /// Number of calls
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Whether the batch has no calls
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+}
+
+/// An injected batch
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    /// The injected operation
+    pub operation: TezosOperationResult,
+    /// Whether a reveal was sent before the calls
+    pub revealed: bool,
+    /// Note of each call, in the order of the operation's contents
+    pub note_ids: Vec<String>,
+}
+
+impl BatchResult {
+    /// Pair each note with the result of its call
+    ///
+    /// # Errors
+    /// Fails if the receipt is for another operation or has a different
+    /// number of contents
+    pub fn note_results<'a>(&'a self, receipt: &'a OperationReceipt) -> SparkResult<Vec<(&'a str, &'a ContentResult)>> {
+        let offset = self.revealed as usize;
+        if receipt.operation_hash != self.operation.operation_hash || receipt.contents.len() != offset + self.note_ids.len() {
+            return Err(SparkError::tezos_error(format!(
+                "Receipt of {} does not match batch {}",
+                receipt.operation_hash, self.operation.operation_hash
+            )));
+        }
+        Ok(self
+            .note_ids
+            .iter()
+            .map(String::as_str)
+            .zip(&receipt.contents[offset..])
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tezos_fees::OperationStatus;
+
+    fn content(status: OperationStatus) -> ContentResult {
+        ContentResult { status, consumed_milligas: 0, paid_storage_size_diff: 0, errors: Vec::new() }
+    }
+
+    #[test]
+    fn test_note_results() {
+        let batch = OperationBatch::new()
+            .with_spend("a", vec![1; 32], vec![0; 128])
+            .with_spend("b", vec![2; 32], vec![0; 128]);
+        assert_eq!(batch.len(), 2);
+
+        let result = BatchResult {
+            operation: TezosOperationResult { operation_hash: "ooBatch".to_string(), status: "pending".to_string() },
+            revealed: true,
+            note_ids: batch.calls().iter().map(|(id, _)| id.clone()).collect(),
+        };
+        let mut receipt = OperationReceipt {
+            operation_hash: "ooBatch".to_string(),
+            block_hash: "BLock".to_string(),
+            level: 1,
+            confirmations: 1,
+            contents: vec![
+                content(OperationStatus::Applied),
+                content(OperationStatus::Backtracked),
+                content(OperationStatus::Failed),
+            ],
+        };
+
+        // The reveal is skipped
+        let results = result.note_results(&receipt).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!((results[0].0, results[0].1.status), ("a", OperationStatus::Backtracked));
+        assert_eq!((results[1].0, results[1].1.status), ("b", OperationStatus::Failed));
+
+        receipt.contents.pop();
+        assert!(result.note_results(&receipt).is_err());
+        receipt.operation_hash = "ooOther".to_string();
+        assert!(result.note_results(&receipt).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+//! Operation simulation and fee estimation
+//!
+//! Before an operation is signed, [`TezosClient`](crate::tezos::TezosClient)
+//! simulates it with generous limits and reads the gas and storage each
+//! content consumed (see [`Simulation`]). [`apply_estimate`] then sets each
+//! gas and storage limit to the consumed amount plus a safety margin, and the
+//! fee to the minimal fee bakers accept for the content's size and gas.
+//! [`OperationLimits`] lets callers fix any of the three instead.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::{SparkError, SparkResult};
+use crate::tezos_forge::UnsignedOperation;
+
+/// Fixed part of the minimal fee, in mutez
+pub const MINIMAL_FEE_MUTEZ: u64 = 100;
+/// Minimal fee per byte of the forged operation, in nanotez
+pub const MINIMAL_NANOTEZ_PER_BYTE: u64 = 1000;
+/// Minimal fee per unit of gas limit, in nanotez
+pub const MINIMAL_NANOTEZ_PER_GAS_UNIT: u64 = 100;
+/// Gas added to the simulated consumption
+pub const GAS_SAFETY_MARGIN: u64 = 100;
+/// Bytes added to the simulated storage usage
+pub const STORAGE_SAFETY_MARGIN: u64 = 20;
+/// Largest gas limit of a single content
+pub const SIMULATION_GAS_LIMIT: u64 = 1_040_000;
+/// Largest total gas limit of an operation
+pub const SIMULATION_BLOCK_GAS_LIMIT: u64 = 2_600_000;
+/// Storage limit used when simulating
+pub const SIMULATION_STORAGE_LIMIT: u64 = 60_000;
+
+/// Storage burnt for a newly allocated implicit account
+const ALLOCATION_STORAGE: u64 = 257;
+/// Bytes of the branch and signature, counted in the first content's fee
+const BRANCH_AND_SIGNATURE_SIZE: usize = 32 + 64;
+
+/// Status of an applied (or not) operation content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationStatus {
+    /// Applied successfully
+    Applied,
+    /// Failed, so the whole operation was reverted
+    Failed,
+    /// Applied, then reverted because a later content failed
+    Backtracked,
+    /// Not applied because an earlier content failed
+    Skipped,
+}
+
+impl OperationStatus {
+    /// Parse the `status` field of an operation result
+    pub fn parse(status: &str) -> SparkResult<Self> {
+        match status {
+            "applied" => Ok(OperationStatus::Applied),
+            "failed" => Ok(OperationStatus::Failed),
+            "backtracked" => Ok(OperationStatus::Backtracked),
+            "skipped" => Ok(OperationStatus::Skipped),
+            other => Err(SparkError::tezos_error(format!("Unknown operation status: {}", other))),
+        }
+    }
+}
+
+/// Result of one content, including the internal operations it emitted
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentResult {
+    /// Status of the content itself
+    pub status: OperationStatus,
+    /// Gas consumed, in milligas
+    pub consumed_milligas: u64,
+    /// Storage paid for, in bytes, including allocated accounts
+    pub paid_storage_size_diff: u64,
+    /// Errors reported by the node, as returned
+    pub errors: Vec<Value>,
+}
+
+impl ContentResult {
+    /// Parse the `metadata` of a content
+    pub fn from_metadata(metadata: &Value) -> SparkResult<Self> {
+        let result = metadata
+            .get("operation_result")
+            .ok_or_else(|| SparkError::tezos_error("Missing operation_result"))?;
+        let status = OperationStatus::parse(
+            result["status"].as_str().ok_or_else(|| SparkError::tezos_error("Missing result status"))?,
+        )?;
+        let mut content = ContentResult {
+            status,
+            consumed_milligas: 0,
+            paid_storage_size_diff: 0,
+            errors: Vec::new(),
+        };
+        content.add(result)?;
+        if let Some(internal) = metadata.get("internal_operation_results").and_then(Value::as_array) {
+            for operation in internal {
+                content.add(&operation["result"])?;
+            }
+        }
+        Ok(content)
+    }
+
+    /// Gas consumed, rounded up to whole units
+    pub fn consumed_gas(&self) -> u64 {
+        self.consumed_milligas.div_ceil(1000)
+    }
+
+    fn add(&mut self, result: &Value) -> SparkResult<()> {
+        self.consumed_milligas += number_field(result, "consumed_milligas")?;
+        self.paid_storage_size_diff += number_field(result, "paid_storage_size_diff")?;
+        if result["allocated_destination_contract"].as_bool() == Some(true) {
+            self.paid_storage_size_diff += ALLOCATION_STORAGE;
+        }
+        if let Some(errors) = result["errors"].as_array() {
+            self.errors.extend(errors.iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+/// Result of simulating an operation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    /// One result per content, in order
+    pub contents: Vec<ContentResult>,
+}
+
+impl Simulation {
+    /// Parse the response of the `simulate_operation` or `run_operation` RPC
+    pub fn from_json(response: &Value) -> SparkResult<Self> {
+        let contents = response["contents"]
+            .as_array()
+            .ok_or_else(|| SparkError::tezos_error("Simulation response has no contents"))?
+            .iter()
+            .map(|content| ContentResult::from_metadata(&content["metadata"]))
+            .collect::<SparkResult<_>>()?;
+        Ok(Simulation { contents })
+    }
+
+    /// Whether every content was applied
+    pub fn is_applied(&self) -> bool {
+        self.contents.iter().all(|c| c.status == OperationStatus::Applied)
+    }
+
+    /// Errors of all contents
+    pub fn errors(&self) -> Vec<&Value> {
+        self.contents.iter().flat_map(|c| c.errors.iter()).collect()
+    }
+}
+
+/// Caller-chosen limits, applied to every content
+///
+/// Limits left as `None` are estimated. When both the gas and storage
+/// limits are set, the operation is not simulated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OperationLimits {
+    /// Fee in mutez
+    pub fee: Option<u64>,
+    /// Gas limit
+    pub gas_limit: Option<u64>,
+    /// Storage limit in bytes
+    pub storage_limit: Option<u64>,
+}
+
+impl OperationLimits {
+    /// Estimate every limit
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use this fee instead of the minimal one
+    pub fn with_fee(mut self, fee: u64) -> Self {
+        self.fee = Some(fee);
+        self
+    }
+
+    /// Use this gas limit instead of the simulated one
+    pub fn with_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.gas_limit = Some(gas_limit);
+        self
+    }
+
+    /// Use this storage limit instead of the simulated one
+    pub fn with_storage_limit(mut self, storage_limit: u64) -> Self {
+        self.storage_limit = Some(storage_limit);
+        self
+    }
+
+    /// Whether the operation must be simulated to fill in the limits
+    pub fn needs_simulation(&self) -> bool {
+        self.gas_limit.is_none() || self.storage_limit.is_none()
+    }
+}
+
+/// Minimal fee, in mutez, for `size` forged bytes and a gas limit
+pub fn minimal_fee(size: usize, gas_limit: u64) -> u64 {
+    let nanotez = MINIMAL_FEE_MUTEZ * 1000
+        + MINIMAL_NANOTEZ_PER_BYTE * size as u64
+        + MINIMAL_NANOTEZ_PER_GAS_UNIT * gas_limit;
+    nanotez.div_ceil(1000)
+}
+
+/// Set the limits an operation is simulated with
+///
+/// The fee is zero and the gas is split so the operation fits in a block.
+pub fn prepare_simulation(operation: &mut UnsignedOperation) {
+    let count = operation.contents.len().max(1) as u64;
+    let gas_limit = SIMULATION_GAS_LIMIT.min(SIMULATION_BLOCK_GAS_LIMIT / count);
+    for content in &mut operation.contents {
+        content.set_limits(0, gas_limit, SIMULATION_STORAGE_LIMIT);
+    }
+}
+
+/// Set the fee, gas limit and storage limit of every content
+///
+/// # Arguments
+/// * `operation` - Operation to update
+/// * `simulation` - Its simulation; required unless `limits` fixes gas and storage
+/// * `limits` - Caller overrides
+///
+/// # Errors
+/// Fails if the simulation is missing, does not match the operation, or
+/// reports a content that was not applied
+pub fn apply_estimate(
+    operation: &mut UnsignedOperation,
+    simulation: Option<&Simulation>,
+    limits: &OperationLimits,
+) -> SparkResult<()> {
+    let estimated: Vec<(u64, u64)> = match simulation {
+        Some(simulation) => {
+            if simulation.contents.len() != operation.contents.len() {
+                return Err(SparkError::tezos_error(format!(
+                    "Simulation has {} results for {} contents",
+                    simulation.contents.len(),
+                    operation.contents.len()
+                )));
+            }
+            if !simulation.is_applied() {
+                return Err(SparkError::tezos_error(format!(
+                    "Operation would fail: {}",
+                    Value::from(simulation.errors().into_iter().cloned().collect::<Vec<_>>())
+                )));
+            }
+            simulation
+                .contents
+                .iter()
+                .map(|c| (c.consumed_gas() + GAS_SAFETY_MARGIN, c.paid_storage_size_diff + STORAGE_SAFETY_MARGIN))
+                .collect()
+        }
+        None if limits.needs_simulation() => {
+            return Err(SparkError::tezos_error("Gas and storage limits need a simulation"))
+        }
+        None => vec![(0, 0); operation.contents.len()],
+    };
+
+    for (i, (gas, storage)) in estimated.into_iter().enumerate() {
+        let gas_limit = limits.gas_limit.unwrap_or(gas);
+        let storage_limit = limits.storage_limit.unwrap_or(storage);
+        let extra = if i == 0 { BRANCH_AND_SIGNATURE_SIZE } else { 0 };
+        let content = &mut operation.contents[i];
+        // The fee is part of the bytes it pays for, so repeat until it settles
+        let mut fee = limits.fee.unwrap_or(0);
+        loop {
+            content.set_limits(fee, gas_limit, storage_limit);
+            if limits.fee.is_some() {
+                break;
+            }
+            let needed = minimal_fee(content.forge()?.len() + extra, gas_limit);
+            if needed <= fee {
+                break;
+            }
+            fee = needed;
+        }
+    }
+    Ok(())
+}
+
+fn number_field(result: &Value, field: &str) -> SparkResult<u64> {
+    match &result[field] {
+        Value::Null => Ok(0),
+        Value::String(s) => s
+            .parse()
+            .map_err(|e| SparkError::tezos_error(format!("Invalid {}: {}", field, e))),
+        other => Err(SparkError::tezos_error(format!("Invalid {}: {}", field, other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::micheline::ToMicheline;
+    use crate::tezos::{TEST_BRANCH, TEST_CONTRACT};
+    use crate::tezos_forge::{OperationContent, Parameters, Transaction};
+    use serde_json::json;
+
+    fn operation() -> UnsignedOperation {
+        UnsignedOperation {
+            branch: TEST_BRANCH.to_string(),
+            contents: vec![OperationContent::Transaction(Transaction {
+                source: "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb".to_string(),
+                fee: 0,
+                counter: 8,
+                gas_limit: 0,
+                storage_limit: 0,
+                amount: 2500,
+                destination: TEST_CONTRACT.to_string(),
+                parameters: Some(Parameters { entrypoint: "deposit".to_string(), value: vec![0u8].to_micheline() }),
+            })],
+        }
+    }
+
+    fn limits(operation: &UnsignedOperation) -> (u64, u64, u64) {
+        match &operation.contents[0] {
+            OperationContent::Transaction(tx) => (tx.fee, tx.gas_limit, tx.storage_limit),
+            other => panic!("expected a transaction, got {:?}", other),
+        }
+    }
+
+    fn simulation_response(status: &str) -> Value {
+        json!({"contents": [{
+            "kind": "transaction",
+            "metadata": {
+                "operation_result": {
+                    "status": status,
+                    "consumed_milligas": "1234567",
+                    "paid_storage_size_diff": "67",
+                    "errors": if status == "applied" { json!(null) } else { json!([{"id": "proto.script_rejected"}]) }
+                },
+                "internal_operation_results": [{
+                    "kind": "transaction",
+                    "result": {"status": status, "consumed_milligas": "1000", "allocated_destination_contract": true}
+                }]
+            }
+        }]})
+    }
+
+    #[test]
+    fn test_parse_simulation() {
+        let simulation = Simulation::from_json(&simulation_response("applied")).unwrap();
+        assert!(simulation.is_applied());
+        let content = &simulation.contents[0];
+        assert_eq!(content.consumed_milligas, 1_235_567);
+        assert_eq!(content.consumed_gas(), 1236);
+        assert_eq!(content.paid_storage_size_diff, 67 + 257);
+
+        let failed = Simulation::from_json(&simulation_response("failed")).unwrap();
+        assert!(!failed.is_applied());
+        assert_eq!(failed.errors(), vec![&json!({"id": "proto.script_rejected"})]);
+        assert!(Simulation::from_json(&json!({"contents": [{"metadata": {}}]})).is_err());
+    }
+
+    #[test]
+    fn test_minimal_fee() {
+        assert_eq!(minimal_fee(0, 0), 100);
+        assert_eq!(minimal_fee(200, 1500), 100 + 200 + 150);
+        // Rounded up to whole mutez
+        assert_eq!(minimal_fee(1, 1), 102);
+    }
+
+    #[test]
+    fn test_apply_estimate() {
+        let simulation = Simulation::from_json(&simulation_response("applied")).unwrap();
+        let mut op = operation();
+        apply_estimate(&mut op, Some(&simulation), &OperationLimits::new()).unwrap();
+        let (fee, gas, storage) = limits(&op);
+        assert_eq!(gas, 1236 + GAS_SAFETY_MARGIN);
+        assert_eq!(storage, 67 + 257 + STORAGE_SAFETY_MARGIN);
+        // The fee covers the final bytes, including branch and signature
+        let size = op.contents[0].forge().unwrap().len() + 96;
+        assert_eq!(fee, minimal_fee(size, gas));
+
+        let failed = Simulation::from_json(&simulation_response("failed")).unwrap();
+        assert!(apply_estimate(&mut operation(), Some(&failed), &OperationLimits::new()).is_err());
+        assert!(apply_estimate(&mut operation(), None, &OperationLimits::new()).is_err());
+    }
+
+    #[test]
+    fn test_limit_overrides() {
+        let simulation = Simulation::from_json(&simulation_response("applied")).unwrap();
+        let mut op = operation();
+        apply_estimate(&mut op, Some(&simulation), &OperationLimits::new().with_fee(5000)).unwrap();
+        assert_eq!(limits(&op), (5000, 1236 + GAS_SAFETY_MARGIN, 67 + 257 + STORAGE_SAFETY_MARGIN));
+
+        let fixed = OperationLimits::new().with_gas_limit(3000).with_storage_limit(0);
+        assert!(!fixed.needs_simulation());
+        let mut op = operation();
+        apply_estimate(&mut op, None, &fixed).unwrap();
+        let size = op.contents[0].forge().unwrap().len() + 96;
+        assert_eq!(limits(&op), (minimal_fee(size, 3000), 3000, 0));
+
+        let mut op = operation();
+        prepare_simulation(&mut op);
+        assert_eq!(limits(&op), (0, SIMULATION_GAS_LIMIT, SIMULATION_STORAGE_LIMIT));
+    }
+}
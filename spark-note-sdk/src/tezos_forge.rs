@@ -0,0 +1,508 @@
+//! Tezos operation forging
+//!
+//! Turns an [`UnsignedOperation`] into the binary encoding that is signed
+//! and injected, without asking a node to do it, and decodes such bytes back
+//! (see [`UnsignedOperation::decode`]) so the two can be checked against
+//! each other. Contract parameters are [`Micheline`] values.
+//!
+//! The layout follows the Tezos binary encoding: the 32-byte branch, then
+//! for each content its tag, 21-byte source, fee, counter, gas and storage
+//! limits (all as unsigned zarith numbers), and then:
+//! - for a transaction, the amount, 22-byte destination and optional
+//!   parameters (entrypoint plus length-prefixed Micheline);
+//! - for a reveal, the public key (curve tag plus key bytes) and the flag of
+//!   the optional proof, which only `tz4` keys use.
+
+use serde_json::{json, Value};
+use tezos_crypto_rs::base58::{FromBase58Check, ToBase58Check};
+
+use crate::error::{SparkError, SparkResult};
+use crate::micheline::{write_len_prefixed, write_zarith_n, Micheline, Reader};
+use crate::tezos_keys::{TezosCurve, TezosPublicKey};
+
+/// Tag of a reveal content
+pub const REVEAL_TAG: u8 = 107;
+/// Tag of a transaction content
+pub const TRANSACTION_TAG: u8 = 108;
+
+const BLOCK_HASH_PREFIX: [u8; 2] = [1, 52];
+const KT1_PREFIX: [u8; 3] = [2, 90, 121];
+/// Implicit account prefixes, indexed by their tag in the binary encoding
+const IMPLICIT_PREFIXES: [[u8; 3]; 4] = [[6, 161, 159], [6, 161, 161], [6, 161, 164], [6, 161, 166]];
+
+/// Entrypoints with a dedicated tag, indexed by that tag
+const ENTRYPOINTS: [&str; 10] = [
+    "default",
+    "root",
+    "do",
+    "set_delegate",
+    "remove_delegate",
+    "deposit",
+    "stake",
+    "unstake",
+    "finalize_unstake",
+    "set_delegate_parameters",
+];
+const NAMED_ENTRYPOINT_TAG: u8 = 255;
+
+/// Parameters of a contract call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameters {
+    /// Entrypoint called
+    pub entrypoint: String,
+    /// Argument
+    pub value: Micheline,
+}
+
+/// A transaction content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    /// Implicit account paying for the operation (`tz1`, `tz2`, `tz3`, `tz4`)
+    pub source: String,
+    /// Fee in mutez
+    pub fee: u64,
+    /// Counter of the source account
+    pub counter: u64,
+    /// Gas limit
+    pub gas_limit: u64,
+    /// Storage limit in bytes
+    pub storage_limit: u64,
+    /// Amount transferred in mutez
+    pub amount: u64,
+    /// Receiving account or contract
+    pub destination: String,
+    /// Contract call, or `None` for a plain transfer
+    pub parameters: Option<Parameters>,
+}
+
+/// A reveal content, publishing the public key of an implicit account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reveal {
+    /// Account whose key is revealed
+    pub source: String,
+    /// Fee in mutez
+    pub fee: u64,
+    /// Counter of the source account
+    pub counter: u64,
+    /// Gas limit
+    pub gas_limit: u64,
+    /// Storage limit in bytes
+    pub storage_limit: u64,
+    /// Public key (`edpk`, `sppk` or `p2pk`)
+    pub public_key: String,
+}
+
+/// One content of an operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationContent {
+    /// A public key reveal, which must come before the account's other contents
+    Reveal(Reveal),
+    /// A transfer or contract call
+    Transaction(Transaction),
+}
+
+/// An operation ready to be forged and signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedOperation {
+    /// Block hash the operation is based on
+    pub branch: String,
+    /// Contents, applied in order
+    pub contents: Vec<OperationContent>,
+}
+
+impl UnsignedOperation {
+    /// JSON form used by the node RPCs
+    pub fn to_json(&self) -> Value {
+        let contents: Vec<Value> = self.contents.iter().map(OperationContent::to_json).collect();
+        json!({ "branch": self.branch, "contents": contents })
+    }
+
+    /// Forge the operation to its binary encoding
+    ///
+    /// # Errors
+    /// Fails if an address, the branch or a parameter is malformed
+    pub fn forge(&self) -> SparkResult<Vec<u8>> {
+        let mut out = decode_prefixed(&self.branch, &BLOCK_HASH_PREFIX, 32, "block hash")?;
+        for content in &self.contents {
+            content.forge_into(&mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Decode bytes produced by [`forge`](Self::forge)
+    pub fn decode(bytes: &[u8]) -> SparkResult<Self> {
+        let mut reader = Reader::new(bytes);
+        let branch = [BLOCK_HASH_PREFIX.as_slice(), reader.take(32)?].concat().to_base58check();
+        let mut contents = Vec::new();
+        while !reader.is_empty() {
+            contents.push(OperationContent::decode(&mut reader)?);
+        }
+        Ok(UnsignedOperation { branch, contents })
+    }
+}
+
+impl OperationContent {
+    /// Forge this content alone, without branch or signature
+    pub fn forge(&self) -> SparkResult<Vec<u8>> {
+        let mut out = Vec::new();
+        self.forge_into(&mut out)?;
+        Ok(out)
+    }
+
+    /// Set the fee, gas limit and storage limit
+    pub(crate) fn set_limits(&mut self, fee: u64, gas_limit: u64, storage_limit: u64) {
+        match self {
+            OperationContent::Reveal(reveal) => {
+                reveal.fee = fee;
+                reveal.gas_limit = gas_limit;
+                reveal.storage_limit = storage_limit;
+            }
+            OperationContent::Transaction(tx) => {
+                tx.fee = fee;
+                tx.gas_limit = gas_limit;
+                tx.storage_limit = storage_limit;
+            }
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            OperationContent::Reveal(reveal) => json!({
+                "kind": "reveal",
+                "source": reveal.source,
+                "fee": reveal.fee.to_string(),
+                "counter": reveal.counter.to_string(),
+                "gas_limit": reveal.gas_limit.to_string(),
+                "storage_limit": reveal.storage_limit.to_string(),
+                "public_key": reveal.public_key,
+            }),
+            OperationContent::Transaction(tx) => {
+                let mut value = json!({
+                    "kind": "transaction",
+                    "source": tx.source,
+                    "fee": tx.fee.to_string(),
+                    "counter": tx.counter.to_string(),
+                    "gas_limit": tx.gas_limit.to_string(),
+                    "storage_limit": tx.storage_limit.to_string(),
+                    "amount": tx.amount.to_string(),
+                    "destination": tx.destination,
+                });
+                if let Some(parameters) = &tx.parameters {
+                    value["parameters"] = json!({ "entrypoint": parameters.entrypoint, "value": parameters.value.to_json() });
+                }
+                value
+            }
+        }
+    }
+
+    fn forge_into(&self, out: &mut Vec<u8>) -> SparkResult<()> {
+        match self {
+            OperationContent::Reveal(reveal) => {
+                let public_key = TezosPublicKey::from_base58(&reveal.public_key)?;
+                out.push(REVEAL_TAG);
+                out.extend(forge_public_key_hash(&reveal.source)?);
+                for n in [reveal.fee, reveal.counter, reveal.gas_limit, reveal.storage_limit] {
+                    write_zarith_n(out, n);
+                }
+                out.push(public_key.curve().tag());
+                out.extend(public_key.as_bytes());
+                // No proof: only needed for tz4 keys
+                out.push(0x00);
+            }
+            OperationContent::Transaction(tx) => {
+                out.push(TRANSACTION_TAG);
+                out.extend(forge_public_key_hash(&tx.source)?);
+                for n in [tx.fee, tx.counter, tx.gas_limit, tx.storage_limit, tx.amount] {
+                    write_zarith_n(out, n);
+                }
+                out.extend(forge_contract(&tx.destination)?);
+                match &tx.parameters {
+                    Some(parameters) => {
+                        out.push(0xff);
+                        forge_entrypoint(out, &parameters.entrypoint)?;
+                        write_len_prefixed(out, &parameters.value.encode()?)?;
+                    }
+                    None => out.push(0x00),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(reader: &mut Reader) -> SparkResult<Self> {
+        match reader.byte()? {
+            REVEAL_TAG => {
+                let source = decode_public_key_hash(reader)?;
+                let [fee, counter, gas_limit, storage_limit] = [(); 4].map(|_| reader.zarith_n());
+                let tag = reader.byte()?;
+                let curve = TezosCurve::from_tag(tag)
+                    .ok_or_else(|| forge_error(format!("Unsupported public key tag {}", tag)))?;
+                let len = if curve == TezosCurve::Ed25519 { 32 } else { 33 };
+                let public_key = TezosPublicKey::from_bytes(curve, reader.take(len)?)?.to_base58();
+                if reader.byte()? != 0x00 {
+                    return Err(forge_error("Reveal proofs are not supported"));
+                }
+                Ok(OperationContent::Reveal(Reveal {
+                    source,
+                    fee: fee?,
+                    counter: counter?,
+                    gas_limit: gas_limit?,
+                    storage_limit: storage_limit?,
+                    public_key,
+                }))
+            }
+            TRANSACTION_TAG => {
+                let source = decode_public_key_hash(reader)?;
+                let [fee, counter, gas_limit, storage_limit, amount] = [(); 5].map(|_| reader.zarith_n());
+                let destination = decode_contract(reader)?;
+                let parameters = match reader.byte()? {
+                    0x00 => None,
+                    0xff => {
+                        let entrypoint = decode_entrypoint(reader)?;
+                        let len = reader.u32()? as usize;
+                        let mut micheline = Reader::new(reader.take(len)?);
+                        let value = Micheline::decode_from(&mut micheline)?;
+                        if !micheline.is_empty() {
+                            return Err(forge_error("Trailing bytes after transaction parameters"));
+                        }
+                        Some(Parameters { entrypoint, value })
+                    }
+                    flag => return Err(forge_error(format!("Invalid parameters flag {:#04x}", flag))),
+                };
+                Ok(OperationContent::Transaction(Transaction {
+                    source,
+                    fee: fee?,
+                    counter: counter?,
+                    gas_limit: gas_limit?,
+                    storage_limit: storage_limit?,
+                    amount: amount?,
+                    destination,
+                    parameters,
+                }))
+            }
+            tag => Err(forge_error(format!("Unsupported operation content tag {}", tag))),
+        }
+    }
+}
+
+/// Encode an implicit account address as its curve tag and 20-byte hash
+pub(crate) fn forge_public_key_hash(address: &str) -> SparkResult<[u8; 21]> {
+    let decoded = address
+        .from_base58check()
+        .map_err(|_| forge_error(format!("Invalid implicit account address {}", address)))?;
+    let tag = IMPLICIT_PREFIXES
+        .iter()
+        .position(|prefix| decoded.len() == 23 && decoded.starts_with(prefix))
+        .ok_or_else(|| forge_error(format!("Invalid implicit account address {}", address)))?;
+    let mut out = [0u8; 21];
+    out[0] = tag as u8;
+    out[1..].copy_from_slice(&decoded[3..]);
+    Ok(out)
+}
+
+/// Encode an implicit account or `KT1` contract address
+pub(crate) fn forge_contract(address: &str) -> SparkResult<[u8; 22]> {
+    let mut out = [0u8; 22];
+    if address.starts_with("KT1") {
+        let hash = decode_prefixed(address, &KT1_PREFIX, 20, "contract address")?;
+        out[0] = 0x01;
+        out[1..21].copy_from_slice(&hash);
+    } else {
+        out[1..].copy_from_slice(&forge_public_key_hash(address)?);
+    }
+    Ok(out)
+}
+
+fn decode_public_key_hash(reader: &mut Reader) -> SparkResult<String> {
+    let tag = reader.byte()? as usize;
+    let prefix = IMPLICIT_PREFIXES
+        .get(tag)
+        .ok_or_else(|| forge_error(format!("Unknown public key hash tag {}", tag)))?;
+    Ok([prefix.as_slice(), reader.take(20)?].concat().to_base58check())
+}
+
+pub(crate) fn decode_contract(reader: &mut Reader) -> SparkResult<String> {
+    match reader.byte()? {
+        0x00 => decode_public_key_hash(reader),
+        0x01 => {
+            let address = [KT1_PREFIX.as_slice(), reader.take(20)?].concat().to_base58check();
+            match reader.byte()? {
+                0x00 => Ok(address),
+                _ => Err(forge_error("Invalid contract address padding")),
+            }
+        }
+        tag => Err(forge_error(format!("Unknown contract tag {}", tag))),
+    }
+}
+
+fn forge_entrypoint(out: &mut Vec<u8>, entrypoint: &str) -> SparkResult<()> {
+    match ENTRYPOINTS.iter().position(|e| *e == entrypoint) {
+        Some(tag) => out.push(tag as u8),
+        None => {
+            if entrypoint.is_empty() || entrypoint.len() > 31 {
+                return Err(forge_error(format!("Invalid entrypoint name {:?}", entrypoint)));
+            }
+            out.push(NAMED_ENTRYPOINT_TAG);
+            out.push(entrypoint.len() as u8);
+            out.extend(entrypoint.as_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn decode_entrypoint(reader: &mut Reader) -> SparkResult<String> {
+    match reader.byte()? {
+        NAMED_ENTRYPOINT_TAG => {
+            let len = reader.byte()? as usize;
+            std::str::from_utf8(reader.take(len)?)
+                .map(str::to_string)
+                .map_err(|_| forge_error("Entrypoint name is not UTF-8"))
+        }
+        tag => ENTRYPOINTS
+            .get(tag as usize)
+            .map(|e| e.to_string())
+            .ok_or_else(|| forge_error(format!("Unknown entrypoint tag {}", tag))),
+    }
+}
+
+fn decode_prefixed(encoded: &str, prefix: &[u8], len: usize, what: &str) -> SparkResult<Vec<u8>> {
+    let decoded = encoded
+        .from_base58check()
+        .map_err(|_| forge_error(format!("Invalid {} {}", what, encoded)))?;
+    if decoded.len() != prefix.len() + len || !decoded.starts_with(prefix) {
+        return Err(forge_error(format!("Invalid {} {}", what, encoded)));
+    }
+    Ok(decoded[prefix.len()..].to_vec())
+}
+
+fn forge_error(message: impl Into<String>) -> SparkError {
+    SparkError::tezos_error(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::micheline::{ToMicheline, MAX_DECODE_DEPTH};
+
+    const SOURCE: &str = "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb";
+    const CONTRACT: &str = "KT1M7dL5CKWHiZhoTZCMdEbX17z7nSvWkW9r";
+    const BRANCH: &str = "BLgoXvMtV5TqCwMtszCjEcnFT15R6B4ESk6GiajPh72rq4yaHuv";
+
+    fn deposit() -> UnsignedOperation {
+        UnsignedOperation {
+            branch: BRANCH.to_string(),
+            contents: vec![OperationContent::Transaction(Transaction {
+                source: SOURCE.to_string(),
+                fee: 10000,
+                counter: 8,
+                gas_limit: 20000,
+                storage_limit: 1000,
+                amount: 2500,
+                destination: CONTRACT.to_string(),
+                parameters: Some(Parameters {
+                    entrypoint: "deposit".to_string(),
+                    value: (vec![1u8, 2], vec![3u8]).to_micheline(),
+                }),
+            })],
+        }
+    }
+
+    fn transaction(operation: &mut UnsignedOperation) -> &mut Transaction {
+        match &mut operation.contents[0] {
+            OperationContent::Transaction(tx) => tx,
+            other => panic!("expected a transaction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_forge_transaction_golden_vector() {
+        let expected = [
+            "80564a7d70309e7d2e12aebb3feeb7592d56e00096a6c943d4045e91b9483628", // branch
+            "6c",                                                               // transaction
+            "006b82198cb179e8306c1bedd08f12dc863f328886",                       // source (tz1)
+            "904e",                                                             // fee 10000
+            "08",                                                               // counter 8
+            "a09c01",                                                           // gas limit 20000
+            "e807",                                                             // storage limit 1000
+            "c413",                                                             // amount 2500
+            "0189778772a26161782270ee7755f5d056d5e3069b00",                     // destination (KT1)
+            "ff05",                                                             // parameters, %deposit
+            "0000000f07070a0000000201020a0000000103",                           // Pair 0x0102 0x03
+        ]
+        .concat();
+
+        let forged = deposit().forge().unwrap();
+        assert_eq!(hex::encode(&forged), expected);
+        assert_eq!(UnsignedOperation::decode(&forged).unwrap(), deposit());
+
+        // Named entrypoints, implicit destinations and plain transfers
+        let mut other = deposit();
+        transaction(&mut other).parameters.as_mut().unwrap().entrypoint = "spend".to_string();
+        let named = other.forge().unwrap();
+        assert!(hex::encode(&named).contains("ffff057370656e64"));
+        assert_eq!(UnsignedOperation::decode(&named).unwrap(), other);
+        transaction(&mut other).destination = SOURCE.to_string();
+        transaction(&mut other).parameters = None;
+        assert_eq!(UnsignedOperation::decode(&other.forge().unwrap()).unwrap(), other);
+    }
+
+    #[test]
+    fn test_forge_reveal_golden_vector() {
+        let mut operation = deposit();
+        operation.contents.insert(
+            0,
+            OperationContent::Reveal(Reveal {
+                source: SOURCE.to_string(),
+                fee: 1000,
+                counter: 7,
+                gas_limit: 1000,
+                storage_limit: 0,
+                public_key: "edpkvGfYw3LyB1UcCahKQk4rF2tvbMUk8GFiTuMjL75uGXrpvKXhjn".to_string(),
+            }),
+        );
+        let reveal = [
+            "6b",                                                                 // reveal
+            "006b82198cb179e8306c1bedd08f12dc863f328886",                         // source (tz1)
+            "e807",                                                               // fee 1000
+            "07",                                                                 // counter 7
+            "e807",                                                               // gas limit 1000
+            "00",                                                                 // storage limit 0
+            "00d670f72efd9475b62275fae773eb5f5eb1fea4f2a0880e6d21983273bf95a0af", // edpk
+            "00",                                                                 // no proof
+        ]
+        .concat();
+
+        let forged = operation.forge().unwrap();
+        assert_eq!(hex::encode(operation.contents[0].forge().unwrap()), reveal);
+        assert!(hex::encode(&forged).contains(&reveal));
+        assert_eq!(UnsignedOperation::decode(&forged).unwrap(), operation);
+        assert_eq!(operation.to_json()["contents"][0]["kind"], "reveal");
+
+        // A proof flag, which only tz4 keys use, is rejected
+        let with_proof = [&forged[..32 + reveal.len() / 2 - 1], &[0xff]].concat();
+        assert!(UnsignedOperation::decode(&with_proof).is_err());
+    }
+
+    #[test]
+    fn test_malformed_operations_are_rejected() {
+        let mut bad = deposit();
+        bad.branch = "BLockHashForTests".to_string();
+        assert!(bad.forge().is_err());
+
+        let mut bad = deposit();
+        transaction(&mut bad).destination = "KT1TezosDummyAddressForPOC".to_string();
+        assert!(bad.forge().is_err());
+
+        let forged = deposit().forge().unwrap();
+        assert!(UnsignedOperation::decode(&forged[..forged.len() - 1]).is_err());
+        assert!(UnsignedOperation::decode(&[forged.as_slice(), &[0x6b]].concat()).is_err());
+
+        // Parameters nested past the decoding limit
+        let mut deep = deposit();
+        let value = (0..=MAX_DECODE_DEPTH).fold(Micheline::prim("Unit", Vec::new()), |inner, _| {
+            Micheline::prim("Some", vec![inner])
+        });
+        transaction(&mut deep).parameters.as_mut().unwrap().value = value;
+        assert!(UnsignedOperation::decode(&deep.forge().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,513 @@
+//! Tezos keys and addresses
+//!
+//! Parses the base58check secret keys produced by `octez-client` and other
+//! Tezos wallets, and derives the matching public key and implicit account
+//! address:
+//!
+//! | Curve     | Secret key                     | Public key | Address |
+//! |-----------|--------------------------------|------------|---------|
+//! | ed25519   | `edsk` (seed or expanded form) | `edpk`     | `tz1`   |
+//! | secp256k1 | `spsk`                         | `sppk`     | `tz2`   |
+//! | P-256     | `p2sk`                         | `p2pk`     | `tz3`   |
+//!
+//! Encrypted keys (`edesk`, `spesk`, `p2esk`) are not supported; decrypt them
+//! with the wallet that created them first.
+//!
+//! Operations are signed as the node expects: the signature covers the
+//! BLAKE2b-256 hash of the generic-operation watermark (`0x03`) followed by
+//! the forged bytes, and is encoded as `edsig`, `spsig1` or `p2sig`.
+
+use tezos_crypto_rs::base58::{FromBase58Check, ToBase58Check};
+use tezos_crypto_rs::hash::{
+    Ed25519Signature, P256Signature, PublicKeyEd25519, PublicKeyP256, PublicKeySecp256k1, Secp256k1Signature,
+};
+use tezos_crypto_rs::PublicKeySignatureVerifier;
+use zeroize::Zeroize;
+
+use crate::error::{SparkError, SparkResult};
+
+const EDSK_SEED_PREFIX: [u8; 4] = [13, 15, 58, 7];
+const EDSK_EXPANDED_PREFIX: [u8; 4] = [43, 246, 78, 7];
+const SPSK_PREFIX: [u8; 4] = [17, 162, 224, 201];
+const P2SK_PREFIX: [u8; 4] = [16, 81, 238, 189];
+const EDPK_PREFIX: [u8; 4] = [13, 15, 37, 217];
+const SPPK_PREFIX: [u8; 4] = [3, 254, 226, 86];
+const P2PK_PREFIX: [u8; 4] = [3, 178, 139, 127];
+const TZ1_PREFIX: [u8; 3] = [6, 161, 159];
+const TZ2_PREFIX: [u8; 3] = [6, 161, 161];
+const TZ3_PREFIX: [u8; 3] = [6, 161, 164];
+const EDSIG_PREFIX: [u8; 5] = [9, 245, 205, 134, 18];
+const SPSIG_PREFIX: [u8; 5] = [13, 115, 101, 19, 63];
+const P2SIG_PREFIX: [u8; 4] = [54, 240, 44, 52];
+
+/// Watermark prepended to operations before they are hashed and signed
+pub const GENERIC_OPERATION_WATERMARK: u8 = 0x03;
+
+/// Signature scheme of a Tezos key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TezosCurve {
+    /// ed25519 (`tz1` addresses)
+    Ed25519,
+    /// secp256k1 (`tz2` addresses)
+    Secp256k1,
+    /// NIST P-256 (`tz3` addresses)
+    P256,
+}
+
+impl TezosCurve {
+    /// Tag of the curve in the binary encoding of keys and addresses
+    pub fn tag(self) -> u8 {
+        match self {
+            TezosCurve::Ed25519 => 0,
+            TezosCurve::Secp256k1 => 1,
+            TezosCurve::P256 => 2,
+        }
+    }
+
+    /// Curve with the given tag
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(TezosCurve::Ed25519),
+            1 => Some(TezosCurve::Secp256k1),
+            2 => Some(TezosCurve::P256),
+            _ => None,
+        }
+    }
+
+    fn public_key_prefix(self) -> &'static [u8] {
+        match self {
+            TezosCurve::Ed25519 => &EDPK_PREFIX,
+            TezosCurve::Secp256k1 => &SPPK_PREFIX,
+            TezosCurve::P256 => &P2PK_PREFIX,
+        }
+    }
+
+    fn address_prefix(self) -> &'static [u8] {
+        match self {
+            TezosCurve::Ed25519 => &TZ1_PREFIX,
+            TezosCurve::Secp256k1 => &TZ2_PREFIX,
+            TezosCurve::P256 => &TZ3_PREFIX,
+        }
+    }
+
+    fn signature_prefix(self) -> &'static [u8] {
+        match self {
+            TezosCurve::Ed25519 => &EDSIG_PREFIX,
+            TezosCurve::Secp256k1 => &SPSIG_PREFIX,
+            TezosCurve::P256 => &P2SIG_PREFIX,
+        }
+    }
+
+    fn public_key_len(self) -> usize {
+        match self {
+            TezosCurve::Ed25519 => 32,
+            TezosCurve::Secp256k1 | TezosCurve::P256 => 33,
+        }
+    }
+}
+
+enum SecretKeyInner {
+    Ed25519(ed25519_dalek::SigningKey),
+    Secp256k1(libsecp256k1::SecretKey),
+    P256(p256::ecdsa::SigningKey),
+}
+
+/// A Tezos secret key
+pub struct TezosSecretKey {
+    inner: SecretKeyInner,
+}
+
+impl TezosSecretKey {
+    /// Parse an `edsk`, `spsk` or `p2sk` secret key
+    ///
+    /// `edsk` keys are accepted both as a 32-byte seed (54 characters) and in
+    /// the expanded 64-byte form (98 characters), whose public key half must
+    /// match the seed.
+    ///
+    /// # Errors
+    /// Fails if the key is malformed, encrypted or of an unknown kind
+    pub fn from_base58(encoded: &str) -> SparkResult<Self> {
+        let mut decoded = encoded
+            .from_base58check()
+            .map_err(|e| key_error(format!("Invalid base58check secret key: {:?}", e)))?;
+        let key = Self::from_decoded(&decoded);
+        decoded.zeroize();
+        key
+    }
+
+    fn from_decoded(decoded: &[u8]) -> SparkResult<Self> {
+        let (prefix, payload) = decoded.split_at(decoded.len().min(4));
+        let inner = match (prefix, payload.len()) {
+            (p, 32) if p == EDSK_SEED_PREFIX => {
+                SecretKeyInner::Ed25519(ed25519_dalek::SigningKey::from_bytes(&to_array(payload)))
+            }
+            (p, 64) if p == EDSK_EXPANDED_PREFIX => {
+                let key = ed25519_dalek::SigningKey::from_bytes(&to_array(&payload[..32]));
+                if key.verifying_key().as_bytes() != &payload[32..] {
+                    return Err(key_error("Expanded edsk key does not match its public key"));
+                }
+                SecretKeyInner::Ed25519(key)
+            }
+            (p, 32) if p == SPSK_PREFIX => SecretKeyInner::Secp256k1(
+                libsecp256k1::SecretKey::parse_slice(payload)
+                    .map_err(|_| key_error("Invalid secp256k1 secret key"))?,
+            ),
+            (p, 32) if p == P2SK_PREFIX => SecretKeyInner::P256(
+                p256::ecdsa::SigningKey::from_slice(payload)
+                    .map_err(|_| key_error("Invalid P-256 secret key"))?,
+            ),
+            _ => return Err(key_error("Unsupported secret key; expected an unencrypted edsk, spsk or p2sk key")),
+        };
+        Ok(TezosSecretKey { inner })
+    }
+
+    /// Curve of the key
+    pub fn curve(&self) -> TezosCurve {
+        match self.inner {
+            SecretKeyInner::Ed25519(_) => TezosCurve::Ed25519,
+            SecretKeyInner::Secp256k1(_) => TezosCurve::Secp256k1,
+            SecretKeyInner::P256(_) => TezosCurve::P256,
+        }
+    }
+
+    /// Derive the public key
+    pub fn public_key(&self) -> TezosPublicKey {
+        let bytes = match &self.inner {
+            SecretKeyInner::Ed25519(key) => key.verifying_key().to_bytes().to_vec(),
+            SecretKeyInner::Secp256k1(key) => {
+                libsecp256k1::PublicKey::from_secret_key(key).serialize_compressed().to_vec()
+            }
+            SecretKeyInner::P256(key) => key.verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+        };
+        TezosPublicKey { curve: self.curve(), bytes }
+    }
+
+    /// Derive the implicit account address (`tz1`, `tz2` or `tz3`)
+    pub fn address(&self) -> String {
+        self.public_key().address()
+    }
+
+    /// Sign a forged operation
+    ///
+    /// # Arguments
+    /// * `forged` - The operation's binary encoding, without watermark
+    ///
+    /// # Returns
+    /// The signature to append to `forged` before injection
+    pub fn sign_operation(&self, forged: &[u8]) -> SparkResult<TezosSignature> {
+        let digest = operation_digest(forged);
+        let bytes = match &self.inner {
+            SecretKeyInner::Ed25519(key) => {
+                use ed25519_dalek::Signer;
+                key.sign(&digest).to_bytes()
+            }
+            SecretKeyInner::Secp256k1(key) => {
+                // libsecp256k1 uses RFC 6979 nonces and returns low-S signatures
+                libsecp256k1::sign(&libsecp256k1::Message::parse(&digest), key).0.serialize()
+            }
+            SecretKeyInner::P256(key) => sign_p256(key, &digest)?,
+        };
+        Ok(TezosSignature { curve: self.curve(), bytes })
+    }
+
+    /// Encode the key as base58check, using the seed form for `edsk` keys
+    pub fn to_base58(&self) -> String {
+        let (prefix, mut secret) = match &self.inner {
+            SecretKeyInner::Ed25519(key) => (EDSK_SEED_PREFIX, key.to_bytes().to_vec()),
+            SecretKeyInner::Secp256k1(key) => (SPSK_PREFIX, key.serialize().to_vec()),
+            SecretKeyInner::P256(key) => (P2SK_PREFIX, key.to_bytes().to_vec()),
+        };
+        let mut bytes = [prefix.as_slice(), &secret].concat();
+        let encoded = bytes.to_base58check();
+        secret.zeroize();
+        bytes.zeroize();
+        encoded
+    }
+}
+
+impl std::fmt::Debug for TezosSecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TezosSecretKey({})", self.address())
+    }
+}
+
+/// A Tezos public key
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TezosPublicKey {
+    curve: TezosCurve,
+    bytes: Vec<u8>,
+}
+
+impl TezosPublicKey {
+    /// Parse an `edpk`, `sppk` or `p2pk` public key
+    pub fn from_base58(encoded: &str) -> SparkResult<Self> {
+        let decoded = encoded
+            .from_base58check()
+            .map_err(|e| key_error(format!("Invalid base58check public key: {:?}", e)))?;
+        [TezosCurve::Ed25519, TezosCurve::Secp256k1, TezosCurve::P256]
+            .into_iter()
+            .find(|curve| decoded.starts_with(curve.public_key_prefix()) && decoded.len() == 4 + curve.public_key_len())
+            .map(|curve| TezosPublicKey { curve, bytes: decoded[4..].to_vec() })
+            .ok_or_else(|| key_error("Unsupported public key; expected an edpk, sppk or p2pk key"))
+    }
+
+    /// Key from its curve and raw bytes
+    pub fn from_bytes(curve: TezosCurve, bytes: &[u8]) -> SparkResult<Self> {
+        if bytes.len() != curve.public_key_len() {
+            return Err(key_error(format!("Invalid {:?} public key length {}", curve, bytes.len())));
+        }
+        Ok(TezosPublicKey { curve, bytes: bytes.to_vec() })
+    }
+
+    /// Curve of the key
+    pub fn curve(&self) -> TezosCurve {
+        self.curve
+    }
+
+    /// Raw key bytes (compressed points for secp256k1 and P-256)
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Encode the key as base58check
+    pub fn to_base58(&self) -> String {
+        [self.curve.public_key_prefix(), &self.bytes].concat().to_base58check()
+    }
+
+    /// 20-byte BLAKE2b hash of the key, as used in addresses
+    pub fn hash(&self) -> [u8; 20] {
+        to_array(&tezos_crypto_rs::blake2b::digest_160(&self.bytes))
+    }
+
+    /// Implicit account address (`tz1`, `tz2` or `tz3`)
+    pub fn address(&self) -> String {
+        [self.curve.address_prefix(), &self.hash()].concat().to_base58check()
+    }
+
+    /// Check a signature made by [`TezosSecretKey::sign_operation`]
+    pub fn verify_operation(&self, forged: &[u8], signature: &TezosSignature) -> bool {
+        if signature.curve != self.curve {
+            return false;
+        }
+        let message = [&[GENERIC_OPERATION_WATERMARK], forged].concat();
+        let signature = signature.bytes.as_slice();
+        let verified = match self.curve {
+            TezosCurve::Ed25519 => PublicKeyEd25519::try_from(self.as_bytes())
+                .ok()
+                .zip(Ed25519Signature::try_from(signature).ok())
+                .map(|(key, signature)| key.verify_signature(&signature, &message)),
+            TezosCurve::Secp256k1 => PublicKeySecp256k1::try_from(self.as_bytes())
+                .ok()
+                .zip(Secp256k1Signature::try_from(signature).ok())
+                .map(|(key, signature)| key.verify_signature(&signature, &message)),
+            TezosCurve::P256 => PublicKeyP256::try_from(self.as_bytes())
+                .ok()
+                .zip(P256Signature::try_from(signature).ok())
+                .map(|(key, signature)| key.verify_signature(&signature, &message)),
+        };
+        matches!(verified, Some(Ok(true)))
+    }
+}
+
+/// A signature over a Tezos operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TezosSignature {
+    curve: TezosCurve,
+    bytes: [u8; 64],
+}
+
+impl TezosSignature {
+    /// Curve of the signing key
+    pub fn curve(&self) -> TezosCurve {
+        self.curve
+    }
+
+    /// Raw 64-byte signature, as appended to the forged operation
+    pub fn as_bytes(&self) -> &[u8; 64] {
+        &self.bytes
+    }
+
+    /// All-zero signature for RPCs that require one but do not check it
+    pub(crate) fn placeholder() -> Self {
+        TezosSignature { curve: TezosCurve::Ed25519, bytes: [0u8; 64] }
+    }
+
+    /// Encode as `edsig`, `spsig1` or `p2sig`
+    pub fn to_base58(&self) -> String {
+        [self.curve.signature_prefix(), &self.bytes].concat().to_base58check()
+    }
+
+    /// Parse an `edsig`, `spsig1` or `p2sig` signature
+    pub fn from_base58(encoded: &str) -> SparkResult<Self> {
+        let decoded = encoded
+            .from_base58check()
+            .map_err(|e| key_error(format!("Invalid base58check signature: {:?}", e)))?;
+        [TezosCurve::Ed25519, TezosCurve::Secp256k1, TezosCurve::P256]
+            .into_iter()
+            .find(|curve| {
+                let prefix = curve.signature_prefix();
+                decoded.starts_with(prefix) && decoded.len() == prefix.len() + 64
+            })
+            .map(|curve| TezosSignature { curve, bytes: to_array(&decoded[decoded.len() - 64..]) })
+            .ok_or_else(|| key_error("Unsupported signature; expected an edsig, spsig1 or p2sig signature"))
+    }
+}
+
+/// BLAKE2b-256 hash of the watermarked operation
+fn operation_digest(forged: &[u8]) -> [u8; 32] {
+    to_array(&tezos_crypto_rs::blake2b::digest_256(&[&[GENERIC_OPERATION_WATERMARK], forged].concat()))
+}
+
+/// ECDSA over P-256 of an already hashed message, with a low-S signature
+///
+/// The nonce is derived deterministically as in RFC 6979 (HMAC-SHA256).
+fn sign_p256(key: &p256::ecdsa::SigningKey, digest: &[u8; 32]) -> SparkResult<[u8; 64]> {
+    use p256::ecdsa::signature::hazmat::PrehashSigner;
+
+    let signature: p256::ecdsa::Signature = key
+        .sign_prehash(digest)
+        .map_err(|_| key_error("Failed to sign with the P-256 key"))?;
+    let signature = signature.normalize_s().unwrap_or(signature);
+    Ok(signature.to_bytes().into())
+}
+
+fn to_array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0u8; N];
+    array.copy_from_slice(bytes);
+    array
+}
+
+fn key_error(message: impl Into<String>) -> SparkError {
+    SparkError::tezos_error(message)
+}
+
+/// Well-known sandbox key (flextesa's `alice`) used by the tests
+#[cfg(test)]
+pub(crate) const TEST_SECRET_KEY: &str = "edsk3QoqBuvdamxouPhin7swCvkQNgq4jP5KZPbwWNnwdZpSpJiEbq";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (secret key, public key, address), computed independently of this crate
+    const VECTORS: [(&str, &str, &str); 4] = [
+        (
+            "edsk3QoqBuvdamxouPhin7swCvkQNgq4jP5KZPbwWNnwdZpSpJiEbq",
+            "edpkvGfYw3LyB1UcCahKQk4rF2tvbMUk8GFiTuMjL75uGXrpvKXhjn",
+            "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb",
+        ),
+        (
+            "edskRpm2mUhvoUjHjXgMoDRxMKhtKfww1ixmWiHCWhHuMEEbGzdnz8Ks4vgarKDtxok7HmrEo1JzkXkdkvyw7Rtw6BNtSd7MJ7",
+            "edpkvGfYw3LyB1UcCahKQk4rF2tvbMUk8GFiTuMjL75uGXrpvKXhjn",
+            "tz1VSUr8wwNhLAzempoch5d6hLRiTh8Cjcjb",
+        ),
+        (
+            "spsk2fikDxuEvbV4DDkEKqsdR79Bi9Vpo8GX72ScZJpsdrNt1sCQW2",
+            "sppk7atzKMX5aCBvtg5oSNYHqP8PA6qEeQkotdVuLANx7ZgxiTMYLco",
+            "tz29cZnTC5kyxvjEJzRLQ5ktMzPwV2nMzzQG",
+        ),
+        (
+            "p2sk2reEvFRaY9gaeKLJYQ2r2rBNtpFuSaGsbJbWhnJgSst5LMtRPt",
+            "p2pk65W4tQ4Ms4jrAy348LeAipVYrXkTMw3mLV7AYMbRP164qxxb9eo",
+            "tz3bJUwESsVPBzFh6Bhim6Toa3UjfSPHvo4o",
+        ),
+    ];
+
+    #[test]
+    fn test_key_and_address_vectors() {
+        for (secret, public, address) in VECTORS {
+            let key = TezosSecretKey::from_base58(secret).unwrap();
+            assert_eq!(key.public_key().to_base58(), public);
+            assert_eq!(key.address(), address);
+            assert_eq!(TezosPublicKey::from_base58(public).unwrap(), key.public_key());
+
+            // Expanded edsk keys are re-encoded in seed form
+            let reparsed = TezosSecretKey::from_base58(&key.to_base58()).unwrap();
+            assert_eq!(reparsed.address(), address);
+            assert!(!format!("{:?}", key).contains(&key.to_base58()));
+        }
+        assert_eq!(TezosSecretKey::from_base58(VECTORS[1].0).unwrap().to_base58(), VECTORS[0].0);
+    }
+
+    #[test]
+    fn test_sign_operation_vectors() {
+        // The forged deposit from the forging tests, and signatures computed
+        // independently of this crate (deterministic ECDSA, low-S)
+        let forged = hex::decode(concat!(
+            "80564a7d70309e7d2e12aebb3feeb7592d56e00096a6c943d4045e91b94836286c006b82198cb179e8306c1bedd0",
+            "8f12dc863f328886904e08a09c01e807c4130189778772a26161782270ee7755f5d056d5e3069b00ff050000000f",
+            "07070a0000000201020a0000000103",
+        ))
+        .unwrap();
+        let signatures = [
+            (VECTORS[0].0, "edsigteFDLhnYGLuQUkuKkD6bQvYsdD7nSJnYiwYuA2LsxLBDindfmpstqTr8SyFLpiw6nRZJLH9DQP9Fe7UyVhcr6RuejHbECV"),
+            (VECTORS[2].0, "spsig1E5nKzASpBiahJPJgaXXa5oLi4rHYHKxY1nMQGSGbphRJJ1qMb6gM3rnkRXAsaUcAD4r5QJ6Gx2Gd3iUbcAD7yDDWCHcjh"),
+            (VECTORS[3].0, "p2sigfhyoPgshYpQxH5ceBj9s5qh5YK5NrM9dLCoHyn25Byw6SjWkyTq7bPZ2Raayy4bx6t77HWfMzn433HU3e2Qytnqp437CZ"),
+        ];
+        for (secret, expected) in signatures {
+            let key = TezosSecretKey::from_base58(secret).unwrap();
+            let signature = key.sign_operation(&forged).unwrap();
+            assert_eq!(signature.to_base58(), expected);
+            assert_eq!(TezosSignature::from_base58(expected).unwrap(), signature);
+
+            let public = key.public_key();
+            assert!(public.verify_operation(&forged, &signature));
+            assert!(!public.verify_operation(&forged[1..], &signature));
+            let other = TezosSecretKey::from_base58(VECTORS[if secret == VECTORS[0].0 { 2 } else { 0 }].0).unwrap();
+            assert!(!other.public_key().verify_operation(&forged, &signature));
+        }
+    }
+
+    #[test]
+    fn test_p256_rfc6979_vectors() {
+        use p256::ecdsa::signature::hazmat::PrehashSigner;
+        use sha2::{Digest, Sha256};
+
+        // RFC 6979, A.2.5: P-256 with SHA-256, as (message, r, s)
+        let vectors = [
+            (
+                "sample",
+                "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716",
+                "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8",
+            ),
+            (
+                "test",
+                "f1abb023518351cd71d881567b1ea663ed3efcf6c5132b354f28d3b0b7d38367",
+                "019f4113742a2b14bd25926b49c649155f267e60d3814b4c0cc84250e46f0083",
+            ),
+        ];
+        let secret = hex::decode("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721").unwrap();
+        let key = p256::ecdsa::SigningKey::from_slice(&secret).unwrap();
+
+        for (message, r, s) in vectors {
+            let digest: [u8; 32] = Sha256::digest(message.as_bytes()).into();
+            let expected = p256::ecdsa::Signature::from_scalars(
+                to_array::<32>(&hex::decode(r).unwrap()),
+                to_array::<32>(&hex::decode(s).unwrap()),
+            )
+            .unwrap();
+            let raw: p256::ecdsa::Signature = key.sign_prehash(&digest).unwrap();
+            assert_eq!(raw, expected);
+
+            // Operations are signed with the same nonce, normalized to low S
+            let signature = p256::ecdsa::Signature::from_slice(&sign_p256(&key, &digest).unwrap()).unwrap();
+            assert_eq!(signature, expected.normalize_s().unwrap_or(expected));
+            assert!(signature.normalize_s().is_none());
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys_are_rejected() {
+        // Bad checksum, public key passed as secret, and secret passed as public key
+        let mut corrupted = VECTORS[0].0.to_string();
+        corrupted.replace_range(10..11, if &corrupted[10..11] == "a" { "b" } else { "a" });
+        assert!(TezosSecretKey::from_base58(&corrupted).is_err());
+        assert!(TezosSecretKey::from_base58(VECTORS[0].1).is_err());
+        assert!(TezosPublicKey::from_base58(VECTORS[2].0).is_err());
+
+        // Expanded key whose public half belongs to another seed
+        let mut decoded = VECTORS[1].0.from_base58check().unwrap();
+        decoded[40] ^= 1;
+        assert!(TezosSecretKey::from_base58(&decoded.to_base58check()).is_err());
+    }
+}
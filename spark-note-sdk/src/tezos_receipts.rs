@@ -0,0 +1,147 @@
+//! Operation receipts
+//!
+//! Once an operation is injected, [`TezosClient::wait_for_confirmation`](crate::tezos::TezosClient::wait_for_confirmation)
+//! follows new block heads until the operation appears in a block's manager
+//! operations, then until enough blocks are built on top of it. The result
+//! is an [`OperationReceipt`] with the status and errors of each content.
+
+use serde_json::Value;
+
+use crate::error::{SparkError, SparkResult};
+use crate::tezos_fees::{ContentResult, OperationStatus};
+
+/// Validation pass holding manager operations (transactions, reveals, ...)
+pub const MANAGER_OPERATIONS_PASS: usize = 3;
+/// Blocks searched before the head when waiting starts, in case the
+/// operation was included before the waiter was called
+pub const CONFIRMATION_LOOKBACK: u64 = 5;
+/// Blocks after which an operation that was not included is given up on
+pub const CONFIRMATION_TIMEOUT_BLOCKS: u64 = 120;
+
+/// An operation included in a block
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationReceipt {
+    /// Hash of the operation
+    pub operation_hash: String,
+    /// Block the operation was included in
+    pub block_hash: String,
+    /// Level of that block
+    pub level: u64,
+    /// Blocks from the including block to the head, counting both
+    pub confirmations: u64,
+    /// Result of each content, in order
+    pub contents: Vec<ContentResult>,
+}
+
+impl OperationReceipt {
+    /// Build the receipt of `operation_hash` from a block's manager operations
+    ///
+    /// # Arguments
+    /// * `operations` - Response of `/chains/main/blocks/<block>/operations/3`
+    /// * `operation_hash` - Operation to look for
+    /// * `block_hash` - Hash of the block
+    /// * `level` - Level of the block
+    ///
+    /// # Returns
+    /// `None` when the block does not contain the operation
+    pub fn from_block_operations(
+        operations: &Value,
+        operation_hash: &str,
+        block_hash: &str,
+        level: u64,
+    ) -> SparkResult<Option<Self>> {
+        let operations = operations
+            .as_array()
+            .ok_or_else(|| SparkError::tezos_error("Block operations are not a list"))?;
+        let Some(operation) = operations.iter().find(|op| op["hash"] == operation_hash) else {
+            return Ok(None);
+        };
+        let contents = operation["contents"]
+            .as_array()
+            .ok_or_else(|| SparkError::tezos_error("Included operation has no contents"))?
+            .iter()
+            .map(|content| ContentResult::from_metadata(&content["metadata"]))
+            .collect::<SparkResult<_>>()?;
+        Ok(Some(OperationReceipt {
+            operation_hash: operation_hash.to_string(),
+            block_hash: block_hash.to_string(),
+            level,
+            confirmations: 1,
+            contents,
+        }))
+    }
+
+    /// Status of the operation as a whole
+    ///
+    /// Applied when every content was applied; otherwise the operation was
+    /// reverted and this is [`OperationStatus::Failed`]. The status of each
+    /// content is in [`contents`](Self::contents).
+    pub fn status(&self) -> OperationStatus {
+        if self.is_applied() {
+            OperationStatus::Applied
+        } else {
+            OperationStatus::Failed
+        }
+    }
+
+    /// Whether every content was applied
+    pub fn is_applied(&self) -> bool {
+        self.contents.iter().all(|c| c.status == OperationStatus::Applied)
+    }
+
+    /// Errors of all contents
+    pub fn errors(&self) -> Vec<&Value> {
+        self.contents.iter().flat_map(|c| c.errors.iter()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn block(statuses: &[&str]) -> Value {
+        let contents: Vec<Value> = statuses
+            .iter()
+            .map(|status| {
+                json!({"kind": "transaction", "metadata": {"operation_result": {
+                    "status": status,
+                    "consumed_milligas": "1000",
+                    "errors": if *status == "failed" { json!([{"id": "proto.script_rejected"}]) } else { json!(null) }
+                }}})
+            })
+            .collect();
+        json!([
+            {"hash": "onOtherOperation", "contents": []},
+            {"hash": "ooTarget", "contents": contents}
+        ])
+    }
+
+    #[test]
+    fn test_receipt_from_block() {
+        let receipt = OperationReceipt::from_block_operations(&block(&["applied"]), "ooTarget", "BLock", 42)
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.level, 42);
+        assert_eq!(receipt.block_hash, "BLock");
+        assert_eq!(receipt.status(), OperationStatus::Applied);
+        assert!(receipt.errors().is_empty());
+
+        assert!(OperationReceipt::from_block_operations(&block(&["applied"]), "ooMissing", "BLock", 42)
+            .unwrap()
+            .is_none());
+        assert!(OperationReceipt::from_block_operations(&json!({}), "ooTarget", "BLock", 42).is_err());
+    }
+
+    #[test]
+    fn test_failed_receipt() {
+        let receipt =
+            OperationReceipt::from_block_operations(&block(&["backtracked", "failed", "skipped"]), "ooTarget", "BLock", 7)
+                .unwrap()
+                .unwrap();
+        assert_eq!(receipt.status(), OperationStatus::Failed);
+        let statuses: Vec<_> = receipt.contents.iter().map(|c| c.status).collect();
+        assert_eq!(statuses, [OperationStatus::Backtracked, OperationStatus::Failed, OperationStatus::Skipped]);
+        assert_eq!(receipt.errors(), vec![&json!({"id": "proto.script_rejected"})]);
+    }
+}
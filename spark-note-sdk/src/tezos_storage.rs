@@ -0,0 +1,149 @@
+//! Registry storage and big-map access
+//!
+//! The NullifierRegistry storage is the LIGO record
+//! `{ commitments; nullifiers; vk_hash }`. LIGO 1.x lays records out as
+//! right combs, so it compiles to
+//! `pair (big_map bytes unit) (big_map bytes unit) bytes` and the node
+//! returns `Pair <commitments id> <nullifiers id> <vk_hash>`.
+//! [`RegistryStorage::from_json`] reads it and fails on any other shape.
+//!
+//! Single big-map values are read from the node by the script-expression
+//! hash of their key ([`script_expr_hash`]). Nodes cannot list the keys of
+//! a big map, so enumeration goes through a TzKT-compatible indexer (see
+//! [`IndexerKey`]).
+
+use serde::Deserialize;
+use serde_json::Value;
+use tezos_crypto_rs::base58::ToBase58Check;
+use tezos_crypto_rs::blake2b;
+
+use crate::error::{SparkError, SparkResult};
+use crate::micheline::{FromMicheline, Micheline};
+
+/// Keys requested per indexer page
+pub const BIG_MAP_PAGE_SIZE: usize = 1000;
+
+const EXPR_PREFIX: [u8; 4] = [13, 44, 64, 27];
+
+/// Typed storage of the NullifierRegistry contract
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryStorage {
+    /// Big map of deposited commitments
+    pub commitments: u64,
+    /// Big map of spent nullifiers
+    pub nullifiers: u64,
+    /// Hash of the spend verifying key
+    pub vk_hash: Vec<u8>,
+}
+
+impl RegistryStorage {
+    /// Parse the JSON Micheline storage returned by the node
+    ///
+    /// # Errors
+    /// Fails if the storage is not `Pair int int bytes`
+    pub fn from_json(storage: &Value) -> SparkResult<Self> {
+        Self::from_micheline(&Micheline::from_json(storage)?)
+    }
+}
+
+impl FromMicheline for RegistryStorage {
+    fn from_micheline(storage: &Micheline) -> SparkResult<Self> {
+        let (commitments, (nullifiers, vk_hash)) = FromMicheline::from_micheline(storage)
+            .map_err(|e| SparkError::tezos_error(format!("Unexpected registry storage: {}", e)))?;
+        Ok(RegistryStorage { commitments, nullifiers, vk_hash })
+    }
+}
+
+/// Script-expression hash (`expr...`) of a big-map key
+///
+/// This is the base58 form of the blake2b-256 hash of the packed key, as
+/// used by the node's `big_maps/<id>/<hash>` RPC.
+pub fn script_expr_hash(key: &Micheline) -> SparkResult<String> {
+    let digest = blake2b::digest_256(&key.pack()?);
+    Ok([EXPR_PREFIX.as_slice(), &digest].concat().to_base58check())
+}
+
+/// A big-map key as listed by a TzKT-compatible indexer
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexerKey {
+    /// Indexer identifier, increasing in insertion order
+    pub id: u64,
+    /// Key, in the indexer's JSON form (hex for `bytes`)
+    pub key: Value,
+}
+
+impl IndexerKey {
+    /// The key of a `bytes`-keyed big map
+    pub fn bytes(&self) -> SparkResult<Vec<u8>> {
+        let hex_key = self.key.as_str().ok_or_else(|| {
+            SparkError::tezos_error(format!("Big-map key {} is not bytes", self.key))
+        })?;
+        hex::decode(hex_key).map_err(|e| SparkError::tezos_error(format!("Invalid big-map key: {}", e)))
+    }
+}
+
+/// Storage as the node returns it for the given big maps, used by the tests
+#[cfg(test)]
+pub(crate) fn test_storage(commitments: u64, nullifiers: u64) -> Value {
+    serde_json::json!({
+        "prim": "Pair",
+        "args": [{"int": commitments.to_string()}, {"int": nullifiers.to_string()}, {"bytes": "abcd"}]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::micheline::ToMicheline;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_storage() {
+        let storage = RegistryStorage::from_json(&test_storage(123, 124)).unwrap();
+        assert_eq!(storage, RegistryStorage { commitments: 123, nullifiers: 124, vk_hash: vec![0xab, 0xcd] });
+
+        // The same comb with its nested pair spelled out
+        let nested = json!({"prim": "Pair", "args": [
+            {"int": "123"},
+            {"prim": "Pair", "args": [{"int": "124"}, {"bytes": "abcd"}]}
+        ]});
+        assert_eq!(RegistryStorage::from_json(&nested).unwrap(), RegistryStorage::from_json(&test_storage(123, 124)).unwrap());
+
+        // Anything else fails instead of guessing
+        assert!(RegistryStorage::from_json(&json!({})).is_err());
+        let mut swapped = test_storage(123, 124);
+        swapped["args"].as_array_mut().unwrap().reverse();
+        assert!(RegistryStorage::from_json(&swapped).is_err());
+        let mut missing = test_storage(123, 124);
+        missing["args"][1] = json!({"prim": "Unit"});
+        assert!(RegistryStorage::from_json(&missing).is_err());
+        // The tree layout of older LIGO versions
+        let tree = json!({"prim": "Pair", "args": [
+            {"prim": "Pair", "args": [{"int": "123"}, {"int": "124"}]},
+            {"bytes": "abcd"}
+        ]});
+        assert!(RegistryStorage::from_json(&tree).is_err());
+    }
+
+    #[test]
+    fn test_script_expr_hash() {
+        assert_eq!(
+            script_expr_hash(&0u64.to_micheline()).unwrap(),
+            "exprtZBwZUeYYYfUs9B9Rg2ywHezVHnCCnmF9WsDQVrs582dSK63dC"
+        );
+        let key = [0x00u8, 0x11, 0x22, 0x33].to_micheline();
+        assert_eq!(key.pack().unwrap(), hex::decode("050a0000000400112233").unwrap());
+        assert_eq!(
+            script_expr_hash(&key).unwrap(),
+            "exprvFZBZx8KL8Zadc1tNeijqe88GrCkpssB9EZuSFQKPsfGKCaTUw"
+        );
+    }
+
+    #[test]
+    fn test_indexer_key() {
+        let key: IndexerKey = serde_json::from_value(json!({"id": 7, "key": "0011", "value": {}})).unwrap();
+        assert_eq!(key.bytes().unwrap(), vec![0x00, 0x11]);
+        let key: IndexerKey = serde_json::from_value(json!({"id": 8, "key": {"nat": "1"}})).unwrap();
+        assert!(key.bytes().is_err());
+    }
+}
@@ -4,8 +4,10 @@
 
 use wasm_bindgen::prelude::*;
 
+use crate::error::SparkResult;
 use crate::note::{self, SparkNote};
-use crate::nullifier;
+use crate::nullifier::{self, Nullifier, NullifierSet, NullifierSetStats};
+use crate::nullifier_store::NullifierStore;
 use crate::secret::Secret;
 use crate::validation::{validate_secret, validate_value};
 
@@ -61,7 +63,7 @@ impl WasmSparkNote {
     /// WARNING: This will not deserialize secrets. Secrets should never be
     /// deserialized from untrusted sources. Use create_note instead.
     #[wasm_bindgen(js_name = fromJSON)]
-    pub fn from_json(json: &str) -> Result<WasmSparkNote, JsError> {
+    pub fn from_json(_json: &str) -> Result<WasmSparkNote, JsError> {
         // SparkNote deserialization is disabled for security
         // Secrets should never be loaded from JSON
         Err(JsError::new("SparkNote cannot be deserialized - secrets must not be loaded from untrusted sources. Use create_note() instead."))
@@ -90,13 +92,14 @@ pub fn create_note(value: u64, secret: Vec<u8>) -> Result<WasmSparkNote, JsError
     validate_secret(&secret)
         .map_err(|e| JsError::new(&format!("Invalid secret: {} (length: {})", e.detailed_message(), secret.len())))?;
     
+    let secret_len = secret.len();
     let secret = Secret::from(secret);
     let inner = note::create_note(value, secret)
         .map_err(|e| {
             // Preserve full error context using detailed_message
             JsError::new(&format!(
                 "Failed to create note: {} (value: {}, secret_len: {})",
-                e.detailed_message(), value, secret.len()
+                e.detailed_message(), value, secret_len
             ))
         })?;
     Ok(WasmSparkNote { inner })
@@ -115,6 +118,9 @@ pub fn note_commitment(note: &WasmSparkNote) -> Vec<u8> {
 
 /// Check if a nullifier has been spent
 ///
+/// Copies the whole spent set into WASM on every call. For repeated lookups,
+/// load the set once into a `WasmNullifierSet` and use `has` or `hasBatch`.
+///
 /// @param nullifier - The nullifier to check as Uint8Array
 /// @param spent_set - Array of spent nullifiers (each as Uint8Array)
 /// @returns boolean - True if nullifier is in the spent set
@@ -131,6 +137,103 @@ pub fn is_nullifier_spent(nullifier: Vec<u8>, spent_set: JsValue) -> Result<bool
     Ok(nullifier::is_nullifier_spent(&nullifier, &spent_hash_set))
 }
 
+/// Parse an array of nullifiers passed from JavaScript
+fn nullifiers_from_js(nullifiers: JsValue) -> Result<Vec<Nullifier>, JsError> {
+    let nullifiers: Vec<Vec<u8>> = serde_wasm_bindgen::from_value(nullifiers)
+        .map_err(|e| JsError::new(&format!("Failed to deserialize nullifiers: {:?}", e)))?;
+    nullifiers
+        .iter()
+        .map(|n| Nullifier::from_slice(n))
+        .collect::<SparkResult<Vec<_>>>()
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// JavaScript-compatible spent nullifier set
+///
+/// Keeps the set inside WASM memory, so lookups do not have to pass the
+/// whole set across the boundary as `isNullifierSpent` does.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct WasmNullifierSet {
+    inner: NullifierSet,
+}
+
+#[wasm_bindgen]
+impl WasmNullifierSet {
+    /// Create an empty set
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check if a nullifier is in the set
+    ///
+    /// @param nullifier - The 32-byte nullifier as Uint8Array
+    /// @returns boolean - True if the nullifier is spent
+    pub fn has(&self, nullifier: Vec<u8>) -> Result<bool, JsError> {
+        let nullifier = Nullifier::from_slice(&nullifier).map_err(|e| JsError::new(&e.to_string()))?;
+        NullifierStore::contains(self, &nullifier).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Check several nullifiers at once
+    ///
+    /// @param nullifiers - Array of 32-byte nullifiers (each as Uint8Array)
+    /// @returns boolean[] - Whether each nullifier is spent, in order
+    #[wasm_bindgen(js_name = hasBatch)]
+    pub fn has_batch(&self, nullifiers: JsValue) -> Result<JsValue, JsError> {
+        let nullifiers = nullifiers_from_js(nullifiers)?;
+        let spent = self.contains_batch(&nullifiers).map_err(|e| JsError::new(&e.to_string()))?;
+        serde_wasm_bindgen::to_value(&spent)
+            .map_err(|e| JsError::new(&format!("Failed to serialize results: {:?}", e)))
+    }
+
+    /// Add a nullifier
+    ///
+    /// @param nullifier - The 32-byte nullifier as Uint8Array
+    /// @returns boolean - True if it was not in the set yet
+    pub fn add(&mut self, nullifier: Vec<u8>) -> Result<bool, JsError> {
+        let nullifier = Nullifier::from_slice(&nullifier).map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(self.inner.add(nullifier))
+    }
+
+    /// Add every nullifier of a batch, or none if one is already spent
+    ///
+    /// @param nullifiers - Array of 32-byte nullifiers (each as Uint8Array)
+    #[wasm_bindgen(js_name = addBatch)]
+    pub fn add_batch(&mut self, nullifiers: JsValue) -> Result<(), JsError> {
+        let nullifiers = nullifiers_from_js(nullifiers)?;
+        self.inner.insert_batch(&nullifiers).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Number of nullifiers in the set
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> u64 {
+        self.inner.len() as u64
+    }
+}
+
+impl NullifierStore for WasmNullifierSet {
+    fn contains(&self, nullifier: &Nullifier) -> SparkResult<bool> {
+        NullifierStore::contains(&self.inner, nullifier)
+    }
+
+    fn insert(&mut self, nullifier: Nullifier) -> SparkResult<bool> {
+        NullifierStore::insert(&mut self.inner, nullifier)
+    }
+
+    fn insert_batch(&mut self, nullifiers: &[Nullifier]) -> SparkResult<()> {
+        self.inner.insert_batch(nullifiers)
+    }
+
+    fn len(&self) -> u64 {
+        NullifierStore::len(&self.inner)
+    }
+
+    fn stats(&self) -> NullifierSetStats {
+        NullifierStore::stats(&self.inner)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,7 +251,7 @@ mod tests {
     fn test_generate_nullifier_wasm() {
         let secret = vec![1, 2, 3, 4, 5, 6, 7, 8];
         let note = create_note(1000, secret.clone()).unwrap();
-        let nullifier = generate_nullifier(&note, secret);
+        let nullifier = nullifier::generate_nullifier(&note.inner, &Secret::from(secret)).to_vec();
         assert_eq!(nullifier.len(), 32);
     }
 
@@ -162,7 +265,7 @@ mod tests {
         assert_eq!(nullifier.len(), 32);
 
         // The result should match the standalone generate_nullifier
-        let expected = generate_nullifier(&note, secret);
+        let expected = nullifier::generate_nullifier(&note.inner, &Secret::from(secret)).to_vec();
         assert_eq!(nullifier, expected);
     }
 